@@ -0,0 +1,48 @@
+use std::{fs::OpenOptions, io::Write, path::Path};
+
+pub fn is_enabled() -> bool {
+	std::fs::read_to_string("/sys/module/apparmor/parameters/enabled")
+		.map(|enabled| enabled.starts_with('Y'))
+		.unwrap_or(false)
+}
+
+/// Makes the next exec of the current process transition into the given AppArmor profile.
+/// Requesting a profile on a host without AppArmor is an error unless `ignore_unavailable` is set.
+pub fn apply_profile(profile: &str, ignore_unavailable: bool) {
+	if !is_enabled() {
+		if ignore_unavailable {
+			warn!(
+				"AppArmor is not enabled on this host. Ignoring AppArmor profile {}!",
+				profile
+			);
+			return;
+		}
+		panic!(
+			"Container requested AppArmor profile {}, but AppArmor is not enabled on this host! Use --apparmor=ignore to run the container anyway",
+			profile
+		);
+	}
+
+	if profile == "unconfined" {
+		return;
+	}
+
+	// Newer kernels provide an AppArmor specific attr directory, which should be preferred
+	let attr_path = if Path::new("/proc/self/attr/apparmor/exec").exists() {
+		"/proc/self/attr/apparmor/exec"
+	} else {
+		"/proc/self/attr/exec"
+	};
+
+	debug!(
+		"Applying AppArmor profile {} through {}",
+		profile, attr_path
+	);
+	let mut attr_file = OpenOptions::new()
+		.write(true)
+		.open(attr_path)
+		.unwrap_or_else(|_| panic!("Could not open {}!", attr_path));
+	attr_file
+		.write_all(format!("exec {}", profile).as_bytes())
+		.unwrap_or_else(|_| panic!("Could not apply AppArmor profile {}!", profile));
+}
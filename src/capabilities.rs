@@ -0,0 +1,117 @@
+use oci_spec::runtime::Capability;
+use serde_json::Value;
+use std::fmt;
+
+/// The capability sets of `process.capabilities`
+pub const CAPABILITY_SETS: [&str; 5] = [
+	"bounding",
+	"effective",
+	"inheritable",
+	"permitted",
+	"ambient",
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CapError {
+	UnknownCapability(String),
+	NotAString(String),
+}
+
+impl fmt::Display for CapError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			CapError::UnknownCapability(name) => write!(f, "unknown capability {}", name),
+			CapError::NotAString(value) => write!(f, "capability {} is not a string", value),
+		}
+	}
+}
+
+/// Parses a list of capability names (e.g. `CAP_NET_BIND_SERVICE`). The result is sorted by
+/// name and free of duplicates, so that every set is applied in the same order.
+pub fn parse_capabilities(names: &[Value]) -> Result<Vec<Capability>, CapError> {
+	let mut caps = Vec::with_capacity(names.len());
+	for name in names {
+		let name_str = name
+			.as_str()
+			.ok_or_else(|| CapError::NotAString(name.to_string()))?;
+		let cap: Capability = serde_json::from_value(name.clone())
+			.map_err(|_| CapError::UnknownCapability(name_str.to_string()))?;
+		caps.push((name_str.to_string(), cap));
+	}
+	caps.sort_by(|a, b| a.0.cmp(&b.0));
+	caps.dedup_by(|a, b| a.0 == b.0);
+	Ok(caps.into_iter().map(|(_, cap)| cap).collect())
+}
+
+/// Checks all capability sets of the raw `process` object of a config and returns the first
+/// invalid entry along with its set
+pub fn check_capabilities(process: &Value) -> Result<(), (&'static str, CapError)> {
+	for set in CAPABILITY_SETS {
+		if let Some(names) = process
+			.pointer(&format!("/capabilities/{}", set))
+			.and_then(|names| names.as_array())
+		{
+			parse_capabilities(names).map_err(|err| (set, err))?;
+		}
+	}
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use serde_json::json;
+
+	fn names(value: Value) -> Vec<Value> {
+		value.as_array().unwrap().clone()
+	}
+
+	#[test]
+	fn unknown_capability_is_rejected() {
+		assert_eq!(
+			parse_capabilities(&names(json!(["CAP_CHOWN", "CAP_NET_BND"]))),
+			Err(CapError::UnknownCapability(String::from("CAP_NET_BND")))
+		);
+		// Names without the CAP_ prefix are no valid spec entries either
+		assert_eq!(
+			parse_capabilities(&names(json!(["NET_BIND_SERVICE"]))),
+			Err(CapError::UnknownCapability(String::from(
+				"NET_BIND_SERVICE"
+			)))
+		);
+		assert_eq!(
+			parse_capabilities(&names(json!([42]))),
+			Err(CapError::NotAString(String::from("42")))
+		);
+	}
+
+	#[test]
+	fn capabilities_are_sorted_and_deduplicated() {
+		assert_eq!(
+			parse_capabilities(&names(json!([
+				"CAP_SYS_ADMIN",
+				"CAP_CHOWN",
+				"CAP_SYS_ADMIN"
+			]))),
+			Ok(vec![Capability::Chown, Capability::SysAdmin])
+		);
+	}
+
+	#[test]
+	fn bad_capability_names_its_set() {
+		let process = json!({
+			"capabilities": {
+				"bounding": ["CAP_KILL"],
+				"effective": ["CAP_KILL", "CAP_NET_BND"]
+			}
+		});
+		assert_eq!(
+			check_capabilities(&process),
+			Err((
+				"effective",
+				CapError::UnknownCapability(String::from("CAP_NET_BND"))
+			))
+		);
+		assert_eq!(check_capabilities(&json!({"args": ["sh"]})), Ok(()));
+	}
+}
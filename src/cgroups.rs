@@ -0,0 +1,784 @@
+use oci_spec::runtime::{LinuxNetwork, LinuxResources, Spec};
+use serde::Serialize;
+use std::{
+	collections::HashMap,
+	convert::TryFrom,
+	ffi::OsString,
+	fs,
+	io::{self, ErrorKind},
+	os::unix::ffi::{OsStrExt, OsStringExt},
+	os::unix::fs::FileExt,
+	path::{Path, PathBuf},
+};
+
+pub const CGROUP_ROOT: &str = "/sys/fs/cgroup";
+
+/// Annotation that makes the OOM killer kill all tasks of the container at once (`true`)
+/// or only the task it selected (`false`, the kernel default)
+pub const OOM_GROUP_ANNOTATION: &str = "io.runh.memory.oom-group";
+
+/// Annotation that sets `memory.high` to the memory limit minus a margin, given in bytes
+/// (`67108864`) or as percentage of the limit (`10%`)
+pub const MEMORY_HIGH_MARGIN_ANNOTATION: &str = "io.runh.memory.high-margin";
+
+/// cgroup v1 controllers for `linux.resources.network`, which have no v2 counterpart
+const NETWORK_V1_CONTROLLERS: [&str; 2] = ["net_cls", "net_prio"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CgroupVersion {
+	V1,
+	V2,
+}
+
+pub fn get_cgroup_version() -> CgroupVersion {
+	if Path::new(CGROUP_ROOT).join("cgroup.controllers").exists() {
+		CgroupVersion::V2
+	} else {
+		CgroupVersion::V1
+	}
+}
+
+/// Returns the path of the cgroup v2 entry of a `/proc/<pid>/cgroup` file
+fn parse_proc_cgroup(content: &str) -> Option<PathBuf> {
+	let relative_path = content.lines().find_map(|line| line.strip_prefix("0::"))?;
+	Some(Path::new(CGROUP_ROOT).join(relative_path.trim_start_matches('/')))
+}
+
+/// Returns the path of the cgroup the current process is in
+pub fn get_own_cgroup() -> PathBuf {
+	let cgroup =
+		fs::read_to_string("/proc/self/cgroup").expect("Could not read /proc/self/cgroup!");
+	parse_proc_cgroup(&cgroup).expect("Could not find cgroup v2 entry in /proc/self/cgroup!")
+}
+
+/// Returns the path of the cgroup a process is in, or `None` if the process is gone
+pub fn get_process_cgroup(pid: i32) -> Option<PathBuf> {
+	parse_proc_cgroup(&fs::read_to_string(format!("/proc/{}/cgroup", pid)).ok()?)
+}
+
+/// Expands a systemd slice name to its path in the cgroup tree,
+/// e.g. `machine-runh.slice` to `machine.slice/machine-runh.slice`
+fn expand_slice(slice: &str) -> PathBuf {
+	let name = slice.strip_suffix(".slice").unwrap_or_else(|| {
+		panic!(
+			"Invalid cgroup parent {}! A systemd slice has to end with .slice",
+			slice
+		)
+	});
+	let mut path = PathBuf::new();
+	if name == "-" {
+		return path;
+	}
+	if name.is_empty() || name.starts_with('-') || name.ends_with('-') || name.contains("--") {
+		panic!("Invalid systemd slice name {}!", slice);
+	}
+	let mut prefix = String::new();
+	for component in name.split('-') {
+		if !prefix.is_empty() {
+			prefix.push('-');
+		}
+		prefix.push_str(component);
+		path.push(format!("{}.slice", prefix));
+	}
+	path
+}
+
+/// Resolves the cgroup of a container from `linux.cgroupsPath`. Paths are interpreted
+/// relative to the cgroup root. Without a path, containers are placed at `/runh/<id>`.
+/// A `cgroup_parent` is prefixed to the spec's path or replaces `/runh` in the default.
+/// With `systemd`, the parent is interpreted as a slice.
+pub fn get_cgroup_path(
+	spec: &Spec,
+	id: &str,
+	cgroup_parent: Option<&str>,
+	systemd: bool,
+) -> PathBuf {
+	let parent = match cgroup_parent {
+		Some(parent) if systemd => Some(expand_slice(parent)),
+		Some(parent) => Some(PathBuf::from(parent)),
+		None => None,
+	};
+	let relative_path = match (
+		spec.linux()
+			.as_ref()
+			.and_then(|linux| linux.cgroups_path().clone()),
+		parent,
+	) {
+		(Some(cgroups_path), Some(parent)) => {
+			parent.join(cgroups_path.strip_prefix("/").unwrap_or(&cgroups_path))
+		}
+		(Some(cgroups_path), None) => cgroups_path,
+		(None, Some(parent)) => parent.join(id),
+		(None, None) => PathBuf::from("/runh").join(id),
+	};
+	let relative_path = relative_path
+		.strip_prefix("/")
+		.unwrap_or(&relative_path)
+		.to_path_buf();
+	Path::new(CGROUP_ROOT).join(relative_path)
+}
+
+/// Saves the cgroup of a container, as the options it was resolved with are only known to `runh create`
+pub fn persist_cgroup_path(container_dir: &Path, cgroup_path: &Path) {
+	fs::write(
+		container_dir.join("cgroup"),
+		cgroup_path.as_os_str().as_bytes(),
+	)
+	.expect("Could not save cgroup path in container directory!");
+}
+
+/// Returns the cgroup `runh create` placed the container in
+pub fn get_container_cgroup_path(container_dir: &Path, spec: &Spec, id: &str) -> PathBuf {
+	fs::read(container_dir.join("cgroup"))
+		.map(|path| PathBuf::from(OsString::from_vec(path)))
+		.unwrap_or_else(|_| get_cgroup_path(spec, id, None, false))
+}
+
+/// Controllers the cgroup can use. A controller is only available if the parent cgroup
+/// delegated it through its `cgroup.subtree_control`.
+fn get_available_controllers(cgroup_path: &Path) -> Vec<String> {
+	fs::read_to_string(cgroup_path.join("cgroup.controllers"))
+		.map(|controllers| controllers.split_whitespace().map(String::from).collect())
+		.unwrap_or_default()
+}
+
+/// Enables all available controllers for the children of every ancestor of the cgroup.
+/// This is best effort, as delegated (rootless) cgroup trees usually do not allow it.
+fn enable_controllers(cgroup_path: &Path) {
+	let mut current = PathBuf::from(CGROUP_ROOT);
+	let relative_path = cgroup_path.strip_prefix(CGROUP_ROOT).unwrap_or(cgroup_path);
+	for component in relative_path.iter() {
+		let controllers = get_available_controllers(&current)
+			.iter()
+			.map(|controller| format!("+{}", controller))
+			.collect::<Vec<String>>()
+			.join(" ");
+		if !controllers.is_empty() {
+			if let Err(err) = fs::write(current.join("cgroup.subtree_control"), &controllers) {
+				debug!(
+					"Could not enable controllers {} in {:?}: {}",
+					controllers, current, err
+				);
+			}
+		}
+		current.push(component);
+	}
+}
+
+/// Creates the cgroup of a container and moves its init process into it. Like the limits in
+/// `write_resource`, a denied cgroup in rootless mode only produces a warning, unless `strict` is set.
+pub fn create_cgroup(cgroup_path: &Path, pid: i32, rootless: bool, strict: bool) {
+	if get_cgroup_version() != CgroupVersion::V2 {
+		warn!("runh only supports cgroup v2. Container will not be placed in its own cgroup!");
+		return;
+	}
+
+	let skip_denied = |err: &io::Error| {
+		if rootless && !strict && err.kind() == ErrorKind::PermissionDenied {
+			warn!(
+				"Not allowed to use cgroup {:?} in rootless mode: {}. Container will not be placed in its own cgroup!",
+				cgroup_path, err
+			);
+			true
+		} else {
+			false
+		}
+	};
+
+	debug!("Creating cgroup at {:?}", cgroup_path);
+	if let Err(err) = fs::create_dir_all(cgroup_path) {
+		if skip_denied(&err) {
+			return;
+		}
+		panic!("Could not create cgroup {:?}: {}", cgroup_path, err);
+	}
+	enable_controllers(cgroup_path);
+
+	if let Err(err) = fs::write(cgroup_path.join("cgroup.procs"), pid.to_string()) {
+		if !skip_denied(&err) {
+			panic!(
+				"Could not move pid {} into cgroup {:?}: {}",
+				pid, cgroup_path, err
+			);
+		}
+	}
+}
+
+/// Writes a single cgroup file. In rootless mode, controllers that were not delegated and
+/// denied writes only produce a warning, unless `strict` is set.
+fn write_resource(
+	cgroup_path: &Path,
+	controller: &str,
+	file: &str,
+	value: &str,
+	rootless: bool,
+	strict: bool,
+) {
+	if rootless
+		&& !strict
+		&& !get_available_controllers(cgroup_path)
+			.iter()
+			.any(|c| c == controller)
+	{
+		warn!(
+			"Controller {} is not delegated to the container cgroup. Skipping limit {}={}!",
+			controller, file, value
+		);
+		return;
+	}
+
+	debug!("Setting {}={} for cgroup {:?}", file, value, cgroup_path);
+	if let Err(err) = fs::write(cgroup_path.join(file), value) {
+		let denied = err.kind() == ErrorKind::PermissionDenied;
+		if rootless && !strict && denied {
+			warn!(
+				"Not allowed to set {}={} in rootless mode. Skipping limit!",
+				file, value
+			);
+		} else {
+			panic!(
+				"Could not set {}={} for cgroup {:?}: {}",
+				file, value, cgroup_path, err
+			);
+		}
+	}
+}
+
+/// Converts the cgroup v1 cpu shares [2-262144] to cgroup v2 cpu weights [1-10000]
+fn shares_to_weight(shares: u64) -> u64 {
+	1 + ((shares.clamp(2, 262144) - 2) * 9999) / 262142
+}
+
+/// Converts the swap limit of the spec, which includes the memory limit, to the v2
+/// `memory.swap.max`, which does not. A negative limit means unlimited.
+pub fn swap_to_v2(swap: i64, limit: Option<i64>) -> Result<i64, String> {
+	match limit {
+		Some(limit) if swap >= 0 && limit >= 0 => {
+			if swap < limit {
+				Err(format!(
+					"the swap limit of {} bytes is below the memory limit of {} bytes",
+					swap, limit
+				))
+			} else {
+				Ok(swap - limit)
+			}
+		}
+		_ => Ok(swap),
+	}
+}
+
+fn limit_to_string(limit: i64) -> String {
+	if limit < 0 {
+		String::from("max")
+	} else {
+		limit.to_string()
+	}
+}
+
+/// Path of the cgroup in the hierarchy of a single cgroup v1 controller
+fn get_v1_cgroup_path(cgroup_path: &Path, controller: &str) -> PathBuf {
+	let relative_path = cgroup_path.strip_prefix(CGROUP_ROOT).unwrap_or(cgroup_path);
+	Path::new(CGROUP_ROOT).join(controller).join(relative_path)
+}
+
+/// Applies `linux.resources.network`. runh otherwise only manages v2 cgroups, so the container
+/// is placed in the net_cls and net_prio hierarchies here. On v2, the controllers do not exist
+/// and the settings are skipped, which is an error with `strict`.
+fn apply_network(
+	cgroup_path: &Path,
+	network: &LinuxNetwork,
+	pid: i32,
+	rootless: bool,
+	strict: bool,
+) {
+	if get_cgroup_version() == CgroupVersion::V2 {
+		if strict {
+			panic!("linux.resources.network is not supported on cgroup v2!");
+		}
+		warn!("net_cls and net_prio are not available on cgroup v2. Skipping linux.resources.network!");
+		return;
+	}
+
+	let mut settings: Vec<(&str, &str, String)> = Vec::new();
+	if let Some(class_id) = network.class_id() {
+		settings.push(("net_cls", "net_cls.classid", class_id.to_string()));
+	}
+	for priority in network.priorities().iter().flatten() {
+		settings.push((
+			"net_prio",
+			"net_prio.ifpriomap",
+			format!("{} {}", priority.name(), priority.priority()),
+		));
+	}
+
+	for controller in NETWORK_V1_CONTROLLERS {
+		if !settings.iter().any(|(c, _, _)| *c == controller) {
+			continue;
+		}
+		let controller_path = get_v1_cgroup_path(cgroup_path, controller);
+		let result = fs::create_dir_all(&controller_path)
+			.and_then(|_| {
+				settings
+					.iter()
+					.filter(|(c, _, _)| *c == controller)
+					.try_for_each(|(_, file, value)| {
+						debug!(
+							"Setting {}={} for cgroup {:?}",
+							file, value, controller_path
+						);
+						fs::write(controller_path.join(file), value)
+					})
+			})
+			.and_then(|_| fs::write(controller_path.join("cgroup.procs"), pid.to_string()));
+		match result {
+			Ok(_) => {}
+			Err(err) if rootless && !strict && err.kind() == ErrorKind::PermissionDenied => warn!(
+				"Not allowed to use the {} controller in rootless mode. Skipping its limits!",
+				controller
+			),
+			Err(err) => panic!(
+				"Could not apply {} limits for cgroup {:?}: {}",
+				controller, controller_path, err
+			),
+		}
+	}
+}
+
+/// Lists the hugepage sizes of the host in the notation of the hugetlb controller, e.g. `2MB`
+fn get_hugepage_sizes() -> Vec<String> {
+	fs::read_dir("/sys/kernel/mm/hugepages")
+		.into_iter()
+		.flatten()
+		.flatten()
+		.filter_map(|entry| {
+			let size_kb: u64 = entry
+				.file_name()
+				.to_str()?
+				.strip_prefix("hugepages-")?
+				.strip_suffix("kB")?
+				.parse()
+				.ok()?;
+			Some(if size_kb >= 1024 * 1024 && size_kb % (1024 * 1024) == 0 {
+				format!("{}GB", size_kb / (1024 * 1024))
+			} else if size_kb >= 1024 && size_kb % 1024 == 0 {
+				format!("{}MB", size_kb / 1024)
+			} else {
+				format!("{}KB", size_kb)
+			})
+		})
+		.collect()
+}
+
+/// Applies `linux.resources` to the cgroup of a container
+pub fn apply_resources(
+	cgroup_path: &Path,
+	resources: &LinuxResources,
+	pid: i32,
+	rootless: bool,
+	strict: bool,
+) {
+	if let Some(network) = resources.network() {
+		apply_network(cgroup_path, network, pid, rootless, strict);
+	}
+
+	if get_cgroup_version() != CgroupVersion::V2 {
+		warn!("runh only supports cgroup v2. Resource limits are not applied!");
+		return;
+	}
+
+	if let Some(memory) = resources.memory() {
+		if let Some(limit) = memory.limit() {
+			write_resource(
+				cgroup_path,
+				"memory",
+				"memory.max",
+				&limit_to_string(limit),
+				rootless,
+				strict,
+			);
+		}
+		if let Some(reservation) = memory.reservation() {
+			write_resource(
+				cgroup_path,
+				"memory",
+				"memory.low",
+				&limit_to_string(reservation),
+				rootless,
+				strict,
+			);
+		}
+		if let Some(swap) = memory.swap() {
+			let swap_limit = swap_to_v2(swap, memory.limit())
+				.unwrap_or_else(|err| panic!("Invalid memory.swap: {}!", err));
+			write_resource(
+				cgroup_path,
+				"memory",
+				"memory.swap.max",
+				&limit_to_string(swap_limit),
+				rootless,
+				strict,
+			);
+		}
+	}
+
+	if let Some(cpu) = resources.cpu() {
+		// Zero shares leave the weight at its default, the kernel would reject a weight of 0
+		if let Some(shares) = cpu.shares().filter(|shares| *shares > 0) {
+			write_resource(
+				cgroup_path,
+				"cpu",
+				"cpu.weight",
+				&shares_to_weight(shares).to_string(),
+				rootless,
+				strict,
+			);
+		}
+		if cpu.quota().is_some() || cpu.period().is_some() {
+			let quota = cpu
+				.quota()
+				.map_or_else(|| String::from("max"), limit_to_string);
+			let period = cpu.period().unwrap_or(100000);
+			write_resource(
+				cgroup_path,
+				"cpu",
+				"cpu.max",
+				&format!("{} {}", quota, period),
+				rootless,
+				strict,
+			);
+		}
+		if let Some(cpus) = cpu.cpus() {
+			write_resource(cgroup_path, "cpuset", "cpuset.cpus", cpus, rootless, strict);
+		}
+		if let Some(mems) = cpu.mems() {
+			write_resource(cgroup_path, "cpuset", "cpuset.mems", mems, rootless, strict);
+		}
+	}
+
+	if let Some(pids) = resources.pids() {
+		let limit = if pids.limit() > 0 { pids.limit() } else { -1 };
+		write_resource(
+			cgroup_path,
+			"pids",
+			"pids.max",
+			&limit_to_string(limit),
+			rootless,
+			strict,
+		);
+	}
+
+	if let Some(hugepage_limits) = resources.hugepage_limits() {
+		let page_sizes = get_hugepage_sizes();
+		for hugepage_limit in hugepage_limits {
+			let page_size = hugepage_limit.page_size();
+			if !page_sizes.iter().any(|size| size == page_size) {
+				panic!(
+					"Invalid hugepage size {} in linux.resources.hugepageLimits! Available sizes: {}",
+					page_size,
+					page_sizes.join(", ")
+				);
+			}
+			write_resource(
+				cgroup_path,
+				"hugetlb",
+				&format!("hugetlb.{}.max", page_size),
+				&limit_to_string(hugepage_limit.limit()),
+				rootless,
+				strict,
+			);
+		}
+	}
+}
+
+/// Applies the OOM group annotation to the cgroup of a container. Without `memory.oom.group`,
+/// the OOM killer may kill a single task and leave a multi-process container half broken.
+pub fn apply_oom_group(cgroup_path: &Path, spec: &Spec, rootless: bool, strict: bool) {
+	let oom_group = match spec
+		.annotations()
+		.as_ref()
+		.and_then(|annotations| annotations.get(OOM_GROUP_ANNOTATION))
+	{
+		Some(value) => match value.as_str() {
+			"true" => "1",
+			"false" => "0",
+			_ => panic!(
+				"Annotation {} has to be true or false, not {}!",
+				OOM_GROUP_ANNOTATION, value
+			),
+		},
+		None => return,
+	};
+
+	if get_cgroup_version() != CgroupVersion::V2 {
+		warn!(
+			"memory.oom.group is only available on cgroup v2. Ignoring annotation {}!",
+			OOM_GROUP_ANNOTATION
+		);
+		return;
+	}
+	write_resource(
+		cgroup_path,
+		"memory",
+		"memory.oom.group",
+		oom_group,
+		rootless,
+		strict,
+	);
+}
+
+/// Computes `memory.high` for a memory limit and the margin of the high margin annotation
+pub fn memory_high_from_margin(margin: &str, limit: i64) -> Result<u64, String> {
+	let limit = u64::try_from(limit)
+		.map_err(|_| String::from("it requires a memory limit in linux.resources.memory"))?;
+	let margin = match margin.strip_suffix('%') {
+		Some(percent) => match percent.parse::<u64>() {
+			Ok(percent) if percent <= 100 => limit / 100 * percent + limit % 100 * percent / 100,
+			_ => {
+				return Err(format!(
+					"{} is not a percentage between 0% and 100%",
+					margin
+				))
+			}
+		},
+		None => margin
+			.parse()
+			.map_err(|_| format!("{} is neither a number of bytes nor a percentage", margin))?,
+	};
+	limit.checked_sub(margin).ok_or_else(|| {
+		format!(
+			"the margin of {} bytes exceeds the memory limit of {} bytes",
+			margin, limit
+		)
+	})
+}
+
+/// Applies the high margin annotation to the cgroup of a container. cgroup v2 has no way to
+/// disable the OOM killer like `memory.oom_control` on v1. Instead, a `memory.high` below
+/// `memory.max` makes the kernel throttle the container and reclaim its memory as soon as it
+/// grows past the margin, long before it reaches the limit, where the OOM killer steps in.
+/// This trades latency under memory pressure for fewer OOM kills.
+pub fn apply_memory_high_margin(cgroup_path: &Path, spec: &Spec, rootless: bool, strict: bool) {
+	let margin = match spec
+		.annotations()
+		.as_ref()
+		.and_then(|annotations| annotations.get(MEMORY_HIGH_MARGIN_ANNOTATION))
+	{
+		Some(margin) => margin,
+		None => return,
+	};
+	let limit = spec
+		.linux()
+		.as_ref()
+		.and_then(|linux| linux.resources().as_ref())
+		.and_then(|resources| resources.memory().as_ref())
+		.and_then(|memory| memory.limit())
+		.unwrap_or(-1);
+	let high = memory_high_from_margin(margin, limit).unwrap_or_else(|err| {
+		panic!(
+			"Invalid annotation {}: {}!",
+			MEMORY_HIGH_MARGIN_ANNOTATION, err
+		)
+	});
+
+	if get_cgroup_version() != CgroupVersion::V2 {
+		warn!(
+			"memory.high is only available on cgroup v2. Ignoring annotation {}!",
+			MEMORY_HIGH_MARGIN_ANNOTATION
+		);
+		return;
+	}
+	write_resource(
+		cgroup_path,
+		"memory",
+		"memory.high",
+		&high.to_string(),
+		rootless,
+		strict,
+	);
+}
+
+/// Resource usage of a cgroup. Values of controllers that are not enabled are missing,
+/// as are limits set to `max`.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize)]
+pub struct CgroupStats {
+	pub memory_current: Option<u64>,
+	pub memory_max: Option<u64>,
+	pub cpu_usage_usec: Option<u64>,
+	pub cpu_user_usec: Option<u64>,
+	pub cpu_system_usec: Option<u64>,
+	pub pids_current: Option<u64>,
+	pub pids_max: Option<u64>,
+}
+
+fn parse_single_value(content: Option<&str>) -> Option<u64> {
+	content?.trim().parse().ok()
+}
+
+fn parse_keyed_value(content: Option<&str>, key: &str) -> Option<u64> {
+	content?
+		.lines()
+		.find_map(|line| line.strip_prefix(key)?.strip_prefix(' ')?.parse().ok())
+}
+
+/// Reads the stats of a cgroup repeatedly. The stat files stay open between reads and are
+/// re-read from the start with pread, which saves opening them again for every sample.
+pub struct CgroupStatsReader {
+	cgroup_path: PathBuf,
+	files: HashMap<&'static str, fs::File>,
+	buffer: Vec<u8>,
+}
+
+impl CgroupStatsReader {
+	pub fn new(cgroup_path: &Path) -> Self {
+		CgroupStatsReader {
+			cgroup_path: cgroup_path.to_path_buf(),
+			files: HashMap::new(),
+			buffer: Vec::new(),
+		}
+	}
+
+	/// Reads a whole stat file. Files of controllers that are not enabled are retried
+	/// on the next read, as they may have been enabled in the meantime.
+	fn read_file(&mut self, file: &'static str) -> Option<String> {
+		if !self.files.contains_key(file) {
+			let opened = fs::File::open(self.cgroup_path.join(file)).ok()?;
+			self.files.insert(file, opened);
+		}
+		let opened = &self.files[file];
+
+		self.buffer.clear();
+		let mut chunk = [0u8; 4096];
+		loop {
+			match opened.read_at(&mut chunk, self.buffer.len() as u64) {
+				Ok(0) => break,
+				Ok(n) => self.buffer.extend_from_slice(&chunk[..n]),
+				Err(err) if err.kind() == ErrorKind::Interrupted => {}
+				Err(_) => {
+					// e.g. ENODEV once the cgroup was removed
+					self.files.remove(file);
+					return None;
+				}
+			}
+		}
+		Some(String::from_utf8_lossy(&self.buffer).into_owned())
+	}
+
+	/// Reads the current resource usage, or `None` once the cgroup is gone. The cache
+	/// is dropped in that case, so a cgroup created again at the same path is picked up.
+	pub fn read(&mut self) -> Option<CgroupStats> {
+		if !self.cgroup_path.exists() {
+			self.files.clear();
+			return None;
+		}
+
+		let cpu_stat = self.read_file("cpu.stat");
+		let cpu_stat = cpu_stat.as_deref();
+		Some(CgroupStats {
+			memory_current: parse_single_value(self.read_file("memory.current").as_deref()),
+			memory_max: parse_single_value(self.read_file("memory.max").as_deref()),
+			cpu_usage_usec: parse_keyed_value(cpu_stat, "usage_usec"),
+			cpu_user_usec: parse_keyed_value(cpu_stat, "user_usec"),
+			cpu_system_usec: parse_keyed_value(cpu_stat, "system_usec"),
+			pids_current: parse_single_value(self.read_file("pids.current").as_deref()),
+			pids_max: parse_single_value(self.read_file("pids.max").as_deref()),
+		})
+	}
+}
+
+/// Reads the current resource usage of a cgroup
+pub fn read_stats(cgroup_path: &Path) -> CgroupStats {
+	CgroupStatsReader::new(cgroup_path)
+		.read()
+		.unwrap_or_default()
+}
+
+/// Removes the cgroup of a container. The kernel only allows this once all processes
+/// have left the cgroup, so it is retried for a short time after the container was killed.
+pub fn remove_cgroup(cgroup_path: &Path) {
+	if get_cgroup_version() == CgroupVersion::V1 {
+		for controller in NETWORK_V1_CONTROLLERS {
+			remove_cgroup_dir(&get_v1_cgroup_path(cgroup_path, controller));
+		}
+	}
+	remove_cgroup_dir(cgroup_path);
+}
+
+fn remove_cgroup_dir(cgroup_path: &Path) {
+	if !cgroup_path.exists() {
+		return;
+	}
+
+	debug!("Removing cgroup at {:?}", cgroup_path);
+	for _ in 0..10 {
+		match fs::remove_dir(cgroup_path) {
+			Ok(_) => return,
+			Err(err) if err.raw_os_error() == Some(libc::EBUSY) => {
+				std::thread::sleep(std::time::Duration::from_millis(10))
+			}
+			Err(err) => {
+				warn!("Could not remove cgroup {:?}: {}", cgroup_path, err);
+				return;
+			}
+		}
+	}
+	warn!(
+		"Could not remove cgroup {:?} as it still contains processes!",
+		cgroup_path
+	);
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn parse_proc_cgroup_finds_v2_entry() {
+		assert_eq!(
+			parse_proc_cgroup("0::/machine.slice/runh-test.scope\n"),
+			Some(PathBuf::from(
+				"/sys/fs/cgroup/machine.slice/runh-test.scope"
+			))
+		);
+		// Hybrid hierarchies list the v1 controllers first
+		assert_eq!(
+			parse_proc_cgroup("12:pids:/user.slice\n1:name=systemd:/user.slice\n0::/runh/test\n"),
+			Some(PathBuf::from("/sys/fs/cgroup/runh/test"))
+		);
+		assert_eq!(
+			parse_proc_cgroup("0::/\n"),
+			Some(PathBuf::from(CGROUP_ROOT))
+		);
+	}
+
+	#[test]
+	fn parse_proc_cgroup_without_v2_entry() {
+		assert_eq!(parse_proc_cgroup("4:memory:/runh/test\n"), None);
+		assert_eq!(parse_proc_cgroup(""), None);
+	}
+
+	#[test]
+	fn shares_map_to_valid_weights() {
+		assert_eq!(shares_to_weight(1), 1);
+		assert_eq!(shares_to_weight(2), 1);
+		assert_eq!(shares_to_weight(1024), 39);
+		assert_eq!(shares_to_weight(262144), 10000);
+		assert_eq!(shares_to_weight(u64::MAX), 10000);
+	}
+
+	#[test]
+	fn swap_excludes_memory_limit() {
+		assert_eq!(swap_to_v2(3072, Some(1024)), Ok(2048));
+		assert_eq!(swap_to_v2(1024, Some(1024)), Ok(0));
+		assert_eq!(swap_to_v2(2048, None), Ok(2048));
+		assert_eq!(swap_to_v2(-1, Some(1024)), Ok(-1));
+		assert_eq!(swap_to_v2(2048, Some(-1)), Ok(2048));
+	}
+
+	#[test]
+	fn swap_below_memory_limit_is_rejected() {
+		assert!(swap_to_v2(512, Some(1024))
+			.unwrap_err()
+			.contains("below the memory limit"));
+		assert!(swap_to_v2(0, Some(1)).is_err());
+	}
+}
@@ -4,5 +4,13 @@ pub const INIT_READY_TO_EXECV: u8 = 0xAA;
 pub const INIT_REQ_SAVE_NETWORK_SETUP: u8 = 0x20;
 pub const INIT_REQ_SKIP_NETWORK_SETUP: u8 = 0x22;
 pub const CREATE_ACK_NETWORK_SETUP: u8 = 0x21;
+pub const CREATE_ACK_CGROUP_SETUP: u8 = 0x31;
 
 pub const OCI_STATE_VERSION: &str = "1.0.2";
+
+/// Version of the files runh keeps in a container directory. Bump it together with a new
+/// migration step in `state::migrate_container_dir` whenever their format changes.
+pub const CONTAINER_STATE_VERSION: u64 = 1;
+
+/// Default for --max-config-size. Real-world configs stay far below this.
+pub const DEFAULT_MAX_CONFIG_SIZE: u64 = 4 * 1024 * 1024;
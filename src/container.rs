@@ -1,9 +1,17 @@
 use std::cmp::Ordering;
+use std::io::BufReader;
+use std::path::Path;
 
 use getset::Getters;
 use oci_spec::runtime::Spec;
 use serde::{Deserialize, Serialize};
 
+use crate::capabilities;
+use crate::consts;
+use crate::error::{self, RunhError};
+use crate::spec::{self, ConfigError};
+use crate::validate;
+
 #[derive(Debug, Getters, Serialize, Deserialize)]
 /// A general OCI container implementation.
 pub struct OCIContainer {
@@ -20,6 +28,11 @@ pub struct OCIContainer {
 	#[get = "pub"]
 	/// OCI Runtime Specification of the container.
 	spec: Spec,
+
+	#[get = "pub"]
+	/// Format version of the container directory, missing in files written before it existed
+	#[serde(rename = "stateVersion", default)]
+	state_version: u64,
 }
 
 impl OCIContainer {
@@ -28,13 +41,39 @@ impl OCIContainer {
 		config.push("config.json");
 		let can_path = std::fs::canonicalize(bundle).expect("Unable to determine absolute path");
 
+		let config_value = match spec::load_config(&config) {
+			Ok(config_value) => config_value,
+			Err(ConfigError::TooLarge(limit)) => error::raise(RunhError::ConfigTooLarge {
+				path: config,
+				limit,
+			}),
+			Err(err) => panic!("Unable to load config file: {}", err),
+		};
+		// The typed spec drops fields it does not know, so these have to be caught beforehand
+		if let Some(field) = validate::find_unsupported_field(&config_value) {
+			error::raise(RunhError::UnsupportedSpecField(field.to_string()));
+		}
+		// The typed spec only reports that it could not be parsed, not which capability is wrong
+		if let Some(process) = config_value.get("process") {
+			if let Err((set, err)) = capabilities::check_capabilities(process) {
+				panic!("Invalid process.capabilities.{}: {}", set, err);
+			}
+		}
+
 		Self {
 			id,
 			bundle: can_path.to_str().unwrap().to_string(),
 			pidfile,
-			spec: Spec::load(config.to_str().unwrap()).expect("Unable to load config file"),
+			spec: serde_json::from_value(config_value).expect("Unable to load config file"),
+			state_version: consts::CONTAINER_STATE_VERSION,
 		}
 	}
+
+	/// Loads the container that `runh create` saved in the container directory
+	pub fn load(container_dir: &Path) -> Option<Self> {
+		let container_file = std::fs::File::open(container_dir.join("container.json")).ok()?;
+		serde_json::from_reader(BufReader::new(container_file)).ok()
+	}
 }
 
 impl Eq for OCIContainer {}
@@ -56,3 +95,34 @@ impl PartialEq for OCIContainer {
 		self.id == other.id
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// A container file as runh wrote it before the state version was introduced
+	const OLD_CONTAINER_FILE: &str = r#"{
+		"id": "old",
+		"bundle": "/var/lib/bundles/old",
+		"pidfile": "/run/runh/old/containerpid",
+		"spec": {"ociVersion": "1.0.2", "root": {"path": "rootfs"}}
+	}"#;
+
+	#[test]
+	fn old_container_file_gets_defaults() {
+		let container: OCIContainer = serde_json::from_str(OLD_CONTAINER_FILE).unwrap();
+		assert_eq!(*container.state_version(), 0);
+		assert_eq!(container.id(), "old");
+		assert_eq!(container.pidfile(), "/run/runh/old/containerpid");
+		assert!(container.spec().annotations().is_none());
+	}
+
+	#[test]
+	fn container_file_keeps_state_version() {
+		let container: OCIContainer = serde_json::from_str(OLD_CONTAINER_FILE).unwrap();
+		let mut value = serde_json::to_value(&container).unwrap();
+		value["stateVersion"] = consts::CONTAINER_STATE_VERSION.into();
+		let container: OCIContainer = serde_json::from_value(value).unwrap();
+		assert_eq!(*container.state_version(), consts::CONTAINER_STATE_VERSION);
+	}
+}
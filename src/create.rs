@@ -1,44 +1,208 @@
+use crate::cgroups;
+use crate::crilog;
+use crate::error::{self, RunhError};
+use crate::fds;
+use crate::format::{self, OutputFormat};
 use crate::hermit;
+use crate::hooks;
 use crate::mounts;
+use crate::namespaces;
+use crate::rdt;
+use crate::reaper;
 use crate::rootfs;
-use crate::state;
+use crate::rootless::RootlessMode;
+use crate::spec;
+use crate::state::{self, ContainerStatus};
 use command_fds::{CommandFdExt, FdMapping};
 use nix::fcntl::OFlag;
+use nix::mount::MntFlags;
 use nix::sys::socket;
 use nix::sys::socket::SockFlag;
 use nix::sys::stat::Mode;
 use nix::unistd::Gid;
 use nix::unistd::Uid;
+use serde::Serialize;
 use std::fs::File;
 use std::fs::OpenOptions;
 use std::io::prelude::*;
 use std::os::unix::fs;
 use std::os::unix::fs::OpenOptionsExt;
-use std::os::unix::io::AsRawFd;
+use std::os::unix::io::{AsRawFd, RawFd};
 use std::os::unix::net::UnixStream;
-use std::os::unix::prelude::CommandExt;
 use std::os::unix::prelude::FromRawFd;
 use std::os::unix::prelude::IntoRawFd;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
 
 use crate::container::OCIContainer;
 
-#[allow(clippy::too_many_arguments)]
-pub fn create_container(
-	project_dir: PathBuf,
-	id: Option<&str>,
-	bundle: Option<&str>,
-	pidfile: Option<&str>,
-	console_socket: Option<&str>,
-	hermit_env: Option<&str>,
-	debug_config: bool,
-	child_log_level: &str,
-) {
+/// Undoes the steps of `create_container` if it does not run to completion, so that a failed
+/// create leaves no residue behind (which would e.g. block the container ID). Every step that
+/// changes the host registers its cleanup action, which are run in reverse order on drop.
+struct CleanupGuard {
+	actions: Vec<(String, Box<dyn FnOnce()>)>,
+	armed: bool,
+}
+
+impl CleanupGuard {
+	fn new() -> Self {
+		Self {
+			actions: Vec::new(),
+			armed: true,
+		}
+	}
+
+	/// Registers a cleanup action. Actions run while unwinding, so they must not panic.
+	fn push<F: FnOnce() + 'static>(&mut self, description: &str, action: F) {
+		self.actions
+			.push((description.to_string(), Box::new(action)));
+	}
+
+	fn disarm(&mut self) {
+		self.armed = false;
+	}
+}
+
+impl Drop for CleanupGuard {
+	fn drop(&mut self) {
+		if !self.armed {
+			return;
+		}
+
+		warn!("Container creation failed. Cleaning up...");
+		while let Some((description, action)) = self.actions.pop() {
+			debug!("Cleanup: {}", description);
+			action();
+		}
+	}
+}
+
+/// Opens a stdio stream given as fd number or path. The fd is duplicated, so that the
+/// supervisor keeps its own copy. Paths are opened for reading (stdin) or appending.
+fn open_stdio(stdio: &str, name: &str, write: bool) -> File {
+	if let Ok(fd) = stdio.parse::<RawFd>() {
+		let fd = nix::unistd::dup(fd)
+			.unwrap_or_else(|err| panic!("Could not use fd {} as {}: {}", fd, name, err));
+		unsafe { File::from_raw_fd(fd) }
+	} else {
+		OpenOptions::new()
+			.read(!write)
+			.append(write)
+			.create(write)
+			.open(stdio)
+			.unwrap_or_else(|err| panic!("Could not open {} as {}: {}", stdio, name, err))
+	}
+}
+
+/// Result of a successful create for `--output json`
+#[derive(Serialize)]
+struct CreateResult<'a> {
+	id: &'a str,
+	pid: Option<i32>,
+}
+
+/// Prints the ID and init pid of a newly created container as JSON to stdout
+pub fn print_create_result(project_dir: PathBuf, id: &str) {
+	let container_state = state::get_container_state(project_dir, id)
+		.unwrap_or_else(|| error::raise(RunhError::ContainerNotFound(id.to_string())));
+	format::print_output(
+		&CreateResult {
+			id,
+			pid: container_state.pid,
+		},
+		OutputFormat::Json,
+		&[],
+	);
+}
+
+/// Everything `runh create` (and `runh run`) was asked to do besides the state root
+pub struct CreateOptions<'a> {
+	pub id: Option<&'a str>,
+	pub bundle: Option<&'a str>,
+	pub pidfile: Option<&'a str>,
+	pub console_socket: Option<&'a str>,
+	pub hermit_env: Option<&'a str>,
+	pub debug_config: bool,
+	pub child_log_level: &'a str,
+	pub preserve_fds: i32,
+	pub apparmor: &'a str,
+	pub init_wrapper: bool,
+	pub cri_log: Option<&'a str>,
+	pub strict_cgroups: bool,
+	pub no_pivot: bool,
+	pub restart: Option<&'a str>,
+	pub env_from_host: Option<&'a str>,
+	pub cgroup_parent: Option<&'a str>,
+	pub systemd_cgroup: bool,
+	pub rootfs_type: Option<&'a str>,
+	pub no_dev_setup: bool,
+	pub no_proc: bool,
+	pub no_sys: bool,
+	pub expose_config: Option<&'a str>,
+	pub keep_namespaces: bool,
+	pub stdio: [Option<&'a str>; 3],
+	pub setup_loopback: Option<bool>,
+	pub rootless_mode: RootlessMode,
+}
+
+pub fn create_container(project_dir: PathBuf, options: CreateOptions) {
+	let CreateOptions {
+		id,
+		bundle,
+		pidfile,
+		console_socket,
+		hermit_env,
+		debug_config,
+		child_log_level,
+		preserve_fds,
+		apparmor,
+		init_wrapper,
+		cri_log,
+		strict_cgroups,
+		no_pivot,
+		restart,
+		env_from_host,
+		cgroup_parent,
+		systemd_cgroup,
+		rootfs_type,
+		no_dev_setup,
+		no_proc,
+		no_sys,
+		expose_config,
+		keep_namespaces,
+		stdio,
+		setup_loopback,
+		rootless_mode,
+	} = options;
 	let _ = std::fs::create_dir(&project_dir);
 
+	// Creating the container directory is our claim on the container ID, so it has to happen
+	// atomically and before anything else is set up.
 	let container_dir = rootfs::resolve_in_rootfs(&PathBuf::from(id.unwrap()), &project_dir);
-	std::fs::create_dir(container_dir.clone()).expect("Unable to create container directory");
+	if let Err(err) = std::fs::create_dir(container_dir.clone()) {
+		if err.kind() == std::io::ErrorKind::AlreadyExists {
+			error::raise(RunhError::ContainerExists(id.unwrap().to_string()));
+		} else {
+			panic!("Unable to create container directory: {}", err);
+		}
+	}
+	let mut cleanup_guard = CleanupGuard::new();
+	let cleanup_dir = container_dir.clone();
+	cleanup_guard.push("remove container directory", move || {
+		if let Err(err) = std::fs::remove_dir_all(&cleanup_dir) {
+			error!(
+				"Could not remove container directory {:?}: {}",
+				cleanup_dir, err
+			);
+		}
+	});
+	state::set_status(
+		&project_dir,
+		id.unwrap(),
+		ContainerStatus::Creating,
+		"runh create",
+	);
+
 	let container = OCIContainer::new(
 		bundle.unwrap().to_string(),
 		id.unwrap().to_string(),
@@ -77,14 +241,71 @@ pub fn create_container(
 			.unwrap();
 	}
 
+	let rootless = rootless_mode.resolve(container.spec());
+
+	if let Some(linux_spec) = container.spec().linux() {
+		let creates_user_ns = linux_spec
+			.namespaces()
+			.as_ref()
+			.map_or(false, |namespaces| {
+				namespaces.iter().any(|ns| {
+					ns.typ() == oci_spec::runtime::LinuxNamespaceType::User && ns.path().is_none()
+				})
+			});
+		if creates_user_ns && rootless {
+			namespaces::check_subid_helpers(linux_spec);
+		}
+	}
+
 	// find rootfs
-	let bundle_rootfs_path = PathBuf::from(&container.spec().root().as_ref().unwrap().path());
-	let bundle_rootfs_path_abs = std::fs::canonicalize(if bundle_rootfs_path.is_absolute() {
-		bundle_rootfs_path
+	let bundle_rootfs_path = container
+		.spec()
+		.root()
+		.as_ref()
+		.expect("Container spec does not contain a root!")
+		.path();
+	let mut bundle_rootfs_path_abs =
+		rootfs::resolve_root_path(bundle_rootfs_path, Path::new(container.bundle()))
+			.unwrap_or_else(|err| {
+				panic!(
+					"Could not resolve path to rootfs {:?}: {}",
+					bundle_rootfs_path, err
+				)
+			});
+
+	// Roots that are not directories are mounted into the container directory and
+	// used like a directory root from then on
+	if bundle_rootfs_path_abs.is_dir() {
+		if let Some(rootfs_type) = rootfs_type {
+			warn!(
+				"Ignoring --rootfs-type {} as the root {:?} is a directory",
+				rootfs_type, bundle_rootfs_path_abs
+			);
+		}
 	} else {
-		PathBuf::from(bundle.unwrap()).join(bundle_rootfs_path)
-	})
-	.expect("Could not parse path to rootfs!");
+		let root_mount = container_dir.join("root");
+		rootfs::mount_root_source(
+			&bundle_rootfs_path_abs,
+			rootfs_type,
+			&root_mount,
+			container
+				.spec()
+				.root()
+				.as_ref()
+				.and_then(|root| root.readonly())
+				.unwrap_or(false),
+		);
+		let cleanup_root_mount = root_mount.clone();
+		cleanup_guard.push("unmount root", move || {
+			if let Err(err) = nix::mount::umount2(&cleanup_root_mount, MntFlags::MNT_DETACH) {
+				warn!(
+					"Could not unmount root at {:?}: {}",
+					cleanup_root_mount, err
+				);
+			}
+		});
+		bundle_rootfs_path_abs = root_mount;
+	}
 
 	//Check for args[0] and detect hermit container
 	let exec_args = &container
@@ -198,6 +419,15 @@ pub fn create_container(
 			Some(datastr.as_str()),
 		)
 		.unwrap_or_else(|_| panic!("Could not create overlay-fs at {:?}", overlay_root));
+		let cleanup_overlay = overlay_mergeddir.clone();
+		cleanup_guard.push("unmount hermit rootfs overlay", move || {
+			if let Err(err) = nix::mount::umount2(&cleanup_overlay, MntFlags::MNT_DETACH) {
+				warn!(
+					"Could not unmount overlay at {:?}: {}",
+					cleanup_overlay, err
+				);
+			}
+		});
 		rootfs_path_abs = std::fs::canonicalize(overlay_mergeddir).unwrap();
 	}
 
@@ -208,25 +438,35 @@ pub fn create_container(
 		.read(true)
 		.write(false)
 		.custom_flags(libc::O_CLOEXEC)
-		.open(config)
+		.open(&config)
 		.expect("Could not open spec file!");
 
+	// The preserved fds are inherited by the init process at their original numbers,
+	// so the fds used by runh init are placed right after them.
+	let first_child_fd = fds::FIRST_EXTRA_FD + preserve_fds;
+	let fifo_child_fd = first_child_fd;
+	let init_pipe_child_fd = first_child_fd + 1;
+	let spec_file_child_fd = first_child_fd + 2;
+	let log_pipe_child_fd = first_child_fd + 3;
+	let console_child_fd = first_child_fd + 4;
+	let exit_status_child_fd = first_child_fd + 5;
+
 	let mut child_fd_mappings = vec![
 		FdMapping {
 			parent_fd: fifo.as_raw_fd(),
-			child_fd: 3,
+			child_fd: fifo_child_fd,
 		},
 		FdMapping {
 			parent_fd: child_socket_fd,
-			child_fd: 4,
+			child_fd: init_pipe_child_fd,
 		},
 		FdMapping {
 			parent_fd: spec_file.as_raw_fd(),
-			child_fd: 5,
+			child_fd: spec_file_child_fd,
 		},
 		FdMapping {
 			parent_fd: child_log_fd,
-			child_fd: 6,
+			child_fd: log_pipe_child_fd,
 		},
 	];
 
@@ -243,27 +483,111 @@ pub fn create_container(
 		//	nix::unistd::dup(sock_stream_fd).expect("Could not duplicate unix stream fd!");
 		child_fd_mappings.push(FdMapping {
 			parent_fd: sock_stream_fd,
-			child_fd: 7,
+			child_fd: console_child_fd,
 		});
 		Some(sock_stream_fd)
 	} else {
 		None
 	};
 
-	let _ = std::process::Command::new("/proc/self/exe")
+	let mut init_cmd = std::process::Command::new("/proc/self/exe");
+	init_cmd
 		.arg("-l")
 		.arg(child_log_level)
 		.arg("--log-format")
 		.arg("json")
+		.arg("--max-config-size")
+		.arg(spec::max_config_size().to_string())
 		.arg("init")
-		.stdin(std::process::Stdio::null())
+		.stdin(std::process::Stdio::null());
+
+	// The init wrapper reaps the entrypoint inside of the container, so it gets the file to
+	// persist its exit status in before the container dir becomes unreachable
+	let exit_status_file = if init_wrapper || restart.is_some() {
+		let file = state::open_exit_status_file(&container_dir);
+		child_fd_mappings.push(FdMapping {
+			parent_fd: file.as_raw_fd(),
+			child_fd: exit_status_child_fd,
+		});
+		init_cmd.env("RUNH_EXIT_STATUS_FD", exit_status_child_fd.to_string());
+		Some(file)
+	} else {
+		None
+	};
+
+	if let Some(restart) = restart {
+		let restart_policy: reaper::RestartPolicy = restart
+			.parse()
+			.unwrap_or_else(|err| panic!("Invalid restart policy: {}", err));
+		init_cmd.env("RUNH_RESTART", restart_policy.to_string());
+	}
+
+	if let Some(env_from_host) = env_from_host {
+		init_cmd.env("RUNH_ENV_FROM_HOST", env_from_host);
+	}
+
+	if let Some(setup_loopback) = setup_loopback {
+		init_cmd.env("RUNH_SETUP_LOOPBACK", setup_loopback.to_string());
+	}
+
+	if let Some(expose_config) = expose_config {
+		let config_abs = std::fs::canonicalize(&config)
+			.unwrap_or_else(|err| panic!("Could not resolve path to config {:?}: {}", config, err));
+		init_cmd
+			.env("RUNH_EXPOSE_CONFIG", expose_config)
+			.env("RUNH_CONFIG_PATH", config_abs);
+	}
+
+	// The container process inherits the stdio of runh init
+	let terminal = container
+		.spec()
+		.process()
+		.as_ref()
+		.map_or(false, |process| process.terminal().unwrap_or(false));
+	if stdio.iter().any(Option::is_some) {
+		if terminal {
+			panic!("--stdin, --stdout and --stderr cannot be used for containers with a terminal!");
+		}
+		if cri_log.is_some() && (stdio[1].is_some() || stdio[2].is_some()) {
+			panic!("--stdout and --stderr cannot be combined with --cri-log!");
+		}
+	}
+	if let Some(stdin) = stdio[0] {
+		init_cmd.stdin(open_stdio(stdin, "stdin", false));
+	}
+	if let Some(stdout) = stdio[1] {
+		init_cmd.stdout(open_stdio(stdout, "stdout", true));
+	}
+	if let Some(stderr) = stdio[2] {
+		init_cmd.stderr(open_stdio(stderr, "stderr", true));
+	}
+
+	if let Some(cri_log_path) = cri_log {
+		if terminal {
+			warn!("Container uses a terminal, so its output is not written to the CRI log!");
+		} else {
+			debug!("Writing container output to CRI log at {}", cri_log_path);
+			let (container_stdout, container_stderr) = crilog::spawn_log_writer(cri_log_path);
+			init_cmd.stdout(container_stdout).stderr(container_stderr);
+		}
+	}
+
+	let _ = init_cmd
 		.fd_mappings(child_fd_mappings)
 		.expect("Unable to pass fifo fd to child!")
-		.env("RUNH_FIFOFD", "3")
-		.env("RUNH_INITPIPE", "4")
-		.env("RUNH_SPEC_FILE", "5")
-		.env("RUNH_LOG_PIPE", "6")
-		.env("RUNH_CONSOLE", "7")
+		.env("RUNH_FIFOFD", fifo_child_fd.to_string())
+		.env("RUNH_INITPIPE", init_pipe_child_fd.to_string())
+		.env("RUNH_SPEC_FILE", spec_file_child_fd.to_string())
+		.env("RUNH_LOG_PIPE", log_pipe_child_fd.to_string())
+		.env("RUNH_CONSOLE", console_child_fd.to_string())
+		.env("RUNH_PRESERVE_FDS", preserve_fds.to_string())
+		.env("RUNH_APPARMOR", apparmor)
+		.env("RUNH_INIT_WRAPPER", init_wrapper.to_string())
+		.env("RUNH_NO_PIVOT", no_pivot.to_string())
+		.env("RUNH_NO_DEV_SETUP", no_dev_setup.to_string())
+		.env("RUNH_NO_PROC", no_proc.to_string())
+		.env("RUNH_NO_SYS", no_sys.to_string())
+		.env("RUNH_ROOTLESS", rootless.to_string())
 		.env("RUNH_HERMIT_CONTAINER", is_hermit_container.to_string())
 		.spawn()
 		.expect("Unable to spawn runh init process");
@@ -271,6 +595,7 @@ pub fn create_container(
 	debug!("Started init process. Closing child fds in create process.");
 	nix::unistd::close(child_socket_fd).expect("Could not close child_socket_fd!");
 	nix::unistd::close(child_log_fd).expect("Could not close child_log_fd!");
+	drop(exit_status_file);
 	if let Some(stream_fd) = socket_fds {
 		nix::unistd::close(stream_fd).expect("Could not close console stream_fd!");
 	}
@@ -333,10 +658,77 @@ pub fn create_container(
 		.expect("Could not read from init pipe!");
 
 	let pid = i32::from_le_bytes(pid_buffer);
+
+	// The cgroup and resctrl group can only be removed once the init is gone,
+	// so their cleanup is registered before the one of the init process.
+	let cgroup_path =
+		cgroups::get_cgroup_path(container.spec(), id.unwrap(), cgroup_parent, systemd_cgroup);
+	cgroups::persist_cgroup_path(&container_dir, &cgroup_path);
+	let cleanup_cgroup = cgroup_path.clone();
+	cleanup_guard.push("remove cgroup", move || {
+		cgroups::remove_cgroup(&cleanup_cgroup)
+	});
+	let cleanup_id = id.unwrap().to_string();
+	cleanup_guard.push("remove resctrl group", move || {
+		rdt::remove_group(&cleanup_id)
+	});
+	// Registered before the kill, so that the hooks only run once the init is gone
+	let hook_state = state::State {
+		version: String::from(crate::consts::OCI_STATE_VERSION),
+		id: container.id().clone(),
+		status: ContainerStatus::Created,
+		pid: Some(pid),
+		bundle: container.bundle().clone(),
+		annotations: container.spec().annotations().clone(),
+		exit_status: state::ExitStatus::default(),
+	};
+	let cleanup_hook_dir = container_dir.clone();
+	let cleanup_hook_spec = container.spec().clone();
+	let cleanup_hook_state = hook_state.clone();
+	cleanup_guard.push("run poststop hooks", move || {
+		hooks::run_poststop_hooks(&cleanup_hook_dir, &cleanup_hook_spec, &cleanup_hook_state)
+	});
+	cleanup_guard.push("kill container init", move || {
+		let _ = nix::sys::signal::kill(
+			nix::unistd::Pid::from_raw(pid),
+			nix::sys::signal::Signal::SIGKILL,
+		);
+	});
+
 	if let Some(pid_file_path) = pidfile {
 		let mut file = std::fs::File::create(pid_file_path).expect("Could not create pid-File!");
+		let cleanup_pid_file = PathBuf::from(pid_file_path);
+		cleanup_guard.push("remove pid file", move || {
+			let _ = std::fs::remove_file(&cleanup_pid_file);
+		});
 		write!(file, "{}", pid).expect("Could not write to pid-file!");
 	}
+
+	cgroups::create_cgroup(&cgroup_path, pid, rootless, strict_cgroups);
+	if let Some(resources) = container
+		.spec()
+		.linux()
+		.as_ref()
+		.and_then(|linux| linux.resources().as_ref())
+	{
+		cgroups::apply_resources(&cgroup_path, resources, pid, rootless, strict_cgroups);
+	}
+	cgroups::apply_oom_group(&cgroup_path, container.spec(), rootless, strict_cgroups);
+	cgroups::apply_memory_high_margin(&cgroup_path, container.spec(), rootless, strict_cgroups);
+
+	if let Some(intel_rdt) = container
+		.spec()
+		.linux()
+		.as_ref()
+		.and_then(|linux| linux.intel_rdt().as_ref())
+	{
+		rdt::setup_group(id.unwrap(), intel_rdt, pid);
+	}
+
+	init_pipe
+		.write_all(&[crate::consts::CREATE_ACK_CGROUP_SETUP])
+		.expect("Unable to write to init-pipe!");
+
 	let mut sig_buffer = [0u8];
 
 	//Running pre-start hooks
@@ -353,6 +745,29 @@ pub fn create_container(
 		);
 	}
 
+	// The init has unshared all namespaces by now, including the cgroup namespace
+	if keep_namespaces {
+		if let Some(namespaces) = container
+			.spec()
+			.linux()
+			.as_ref()
+			.and_then(|linux| linux.namespaces().as_ref())
+		{
+			let pin_dir = container_dir.join("ns");
+			let cleanup_pin_dir = pin_dir.clone();
+			cleanup_guard.push("unpin namespaces", move || {
+				for pin in std::fs::read_dir(&cleanup_pin_dir)
+					.into_iter()
+					.flatten()
+					.flatten()
+				{
+					let _ = nix::mount::umount2(&pin.path(), MntFlags::MNT_DETACH);
+				}
+			});
+			namespaces::pin_namespaces(pid, namespaces, &pin_dir);
+		}
+	}
+
 	let state_location = container_dir.join("created");
 	let mut state_file = OpenOptions::new()
 		.read(true)
@@ -363,62 +778,9 @@ pub fn create_container(
 	write!(state_file, "{}", pid).expect("Could not write pid to state-file!");
 
 	debug!("Running prestart hooks...");
-	if let Some(hooks) = container.spec().hooks().as_ref() {
-		let state = state::State {
-			version: String::from(crate::consts::OCI_STATE_VERSION),
-			id: container.id().clone(),
-			status: String::from("created"),
-			pid: Some(pid),
-			bundle: container.bundle().clone(),
-			annotations: container.spec().annotations().clone(),
-		};
-
-		if let Some(prestart_hooks) = hooks.prestart().as_ref() {
-			for hook in prestart_hooks {
-				let mut cmd = std::process::Command::new(&hook.path());
-				if let Some(args) = &hook.args() {
-					if !args.is_empty() {
-						cmd.arg0(&args[0]);
-					}
-					if args.len() > 1 {
-						cmd.args(&args[1..]);
-					}
-				}
-				if let Some(env) = &hook.env() {
-					for var in env {
-						let (name, value) = var.split_once('=').unwrap_or_else(|| {
-							panic!("Could not parse environment variable: {}", var)
-						});
-						cmd.env(name, value);
-					}
-				}
-				if hook.timeout().is_some() {
-					warn!("The timeout set for prestart hook {:?} is currently unimplemented and will be ignored!", hook.path());
-				}
-				cmd.stderr(std::process::Stdio::piped());
-				cmd.stdin(std::process::Stdio::piped());
-				let mut child = cmd.spawn().unwrap_or_else(|_| {
-					panic!("Unable to spawn prestart hook process {:?}", hook.path())
-				});
-				write!(
-					child.stdin.take().unwrap(),
-					"{}",
-					serde_json::to_string(&state).unwrap()
-				)
-				.expect("Could not write container state to hook process stdin!");
-
-				let ret = child.wait_with_output().unwrap();
-				if !ret.status.success() {
-					panic!(
-						"prestart hook {:?} returned exit status {}. Stderr: {}",
-						hook.path(),
-						ret.status,
-						String::from_utf8(ret.stderr).unwrap()
-					);
-				}
-			}
-		}
-	}
+	hooks::run_prestart_hooks(container.spec(), &hook_state);
+	debug!("Running createRuntime hooks...");
+	hooks::run_create_runtime_hooks(container.spec(), &hook_state);
 
 	init_pipe
 		.write_all(&[crate::consts::CREATE_ACK_PRESTART_HOOKS])
@@ -485,4 +847,12 @@ pub fn create_container(
 	} else {
 		panic!("Received invalid signal from runh init!");
 	}
+
+	state::set_status(
+		&project_dir,
+		id.unwrap(),
+		ContainerStatus::Created,
+		"runh create",
+	);
+	cleanup_guard.disarm();
 }
@@ -0,0 +1,121 @@
+use command_fds::{CommandFdExt, FdMapping};
+use nix::fcntl::OFlag;
+use std::{
+	fs::{File, OpenOptions},
+	io::{Read, Write},
+	os::unix::io::{FromRawFd, RawFd},
+	sync::{Arc, Mutex},
+};
+use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
+
+/// Lines longer than this are split into partial (`P`) entries
+const MAX_LINE_SIZE: usize = 16 * 1024;
+
+const STDOUT_FD: RawFd = 3;
+const STDERR_FD: RawFd = 4;
+
+/// Spawns the `runh log-writer` process, which outlives `runh create` and writes the output of
+/// the container to `log_path`. Returns the write ends of the stdout and stderr pipes, which
+/// have to become the stdout and stderr of the container init.
+pub fn spawn_log_writer(log_path: &str) -> (File, File) {
+	let (stdout_read, stdout_write) =
+		nix::unistd::pipe2(OFlag::O_CLOEXEC).expect("Could not create pipe for container stdout!");
+	let (stderr_read, stderr_write) =
+		nix::unistd::pipe2(OFlag::O_CLOEXEC).expect("Could not create pipe for container stderr!");
+
+	std::process::Command::new("/proc/self/exe")
+		.arg("log-writer")
+		.arg(log_path)
+		.stdin(std::process::Stdio::null())
+		.stdout(std::process::Stdio::null())
+		.stderr(std::process::Stdio::null())
+		.fd_mappings(vec![
+			FdMapping {
+				parent_fd: stdout_read,
+				child_fd: STDOUT_FD,
+			},
+			FdMapping {
+				parent_fd: stderr_read,
+				child_fd: STDERR_FD,
+			},
+		])
+		.expect("Unable to pass container output pipes to log writer!")
+		.spawn()
+		.expect("Unable to spawn runh log-writer process");
+
+	nix::unistd::close(stdout_read).expect("Could not close stdout read end!");
+	nix::unistd::close(stderr_read).expect("Could not close stderr read end!");
+	unsafe {
+		(
+			File::from_raw_fd(stdout_write),
+			File::from_raw_fd(stderr_write),
+		)
+	}
+}
+
+fn write_entry(log_file: &Mutex<File>, stream: &str, tag: &str, line: &[u8]) {
+	let timestamp = OffsetDateTime::now_utc().format(&Rfc3339).unwrap();
+	let mut entry = format!("{} {} {} ", timestamp, stream, tag).into_bytes();
+	entry.extend_from_slice(line);
+	entry.push(b'\n');
+	// A single write per entry, so lines of stdout and stderr never interleave
+	let _ = log_file.lock().unwrap().write_all(&entry);
+}
+
+/// Copies everything from `pipe` to the log file in the CRI log format
+/// `TIMESTAMP STREAM TAG MESSAGE`, where TAG is `F` for full and `P` for partial lines.
+fn forward_stream(mut pipe: File, stream: &str, log_file: &Mutex<File>) {
+	let mut buffer = [0u8; 4096];
+	let mut line: Vec<u8> = Vec::new();
+	loop {
+		let bytes_read = match pipe.read(&mut buffer) {
+			Ok(0) => break,
+			Ok(bytes_read) => bytes_read,
+			Err(err) if err.kind() == std::io::ErrorKind::Interrupted => continue,
+			Err(_) => break,
+		};
+
+		for byte in &buffer[..bytes_read] {
+			if *byte == b'\n' {
+				write_entry(log_file, stream, "F", &line);
+				line.clear();
+			} else {
+				line.push(*byte);
+				if line.len() >= MAX_LINE_SIZE {
+					write_entry(log_file, stream, "P", &line);
+					line.clear();
+				}
+			}
+		}
+	}
+
+	if !line.is_empty() {
+		write_entry(log_file, stream, "F", &line);
+	}
+}
+
+/// Entry point of `runh log-writer`. Runs until the container closed both stdout and stderr.
+pub fn run_log_writer(log_path: &str) {
+	let log_file = Arc::new(Mutex::new(
+		OpenOptions::new()
+			.create(true)
+			.append(true)
+			.open(log_path)
+			.unwrap_or_else(|err| {
+				panic!("Could not open container log file {}: {}", log_path, err)
+			}),
+	));
+
+	let stdout_pipe = unsafe { File::from_raw_fd(STDOUT_FD) };
+	let stdout_log = log_file.clone();
+	let stdout_forwarder =
+		std::thread::spawn(move || forward_stream(stdout_pipe, "stdout", &stdout_log));
+
+	let stderr_pipe = unsafe { File::from_raw_fd(STDERR_FD) };
+	forward_stream(stderr_pipe, "stderr", &log_file);
+
+	stdout_forwarder
+		.join()
+		.expect("stdout forwarder did panic!");
+}
@@ -1,14 +1,21 @@
 use nix::mount::MntFlags;
 // use nix::sched::CloneFlags;
 
+use crate::cgroups;
+use crate::container::OCIContainer;
+use crate::error::{self, RunhError};
+use crate::hooks;
 use crate::kill;
+use crate::mountinfo;
+use crate::rdt;
+use crate::rootfs;
 // use crate::network;
-use crate::state;
+use crate::state::{self, ContainerStatus};
+use crate::syscall::traced_syscall;
 use std::fs;
 // use std::fs::File;
-// use std::io::BufReader;
 // use std::os::unix::prelude::AsRawFd;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 // fn reset_network_namespace(container_dir: &PathBuf) -> Result<(), Box<dyn std::error::Error>> {
 // 	let network_file_path = container_dir.join("hermit_network.json");
@@ -26,26 +33,136 @@ use std::path::PathBuf;
 // 	Ok(())
 // }
 
-pub fn delete_container(project_dir: PathBuf, id: Option<&str>, force: bool) {
+/// Lists the mount points at or below `path` in the order they were created
+fn get_mounts_below(path: &Path, include_path: bool) -> Vec<PathBuf> {
+	mountinfo::read_mountinfo(None)
+		.into_iter()
+		.map(|mount| mount.mount_point)
+		.filter(|mount_point| {
+			mount_point.starts_with(path) && (include_path || mount_point != path)
+		})
+		.collect()
+}
+
+/// Unmounts all mounts at or below `path` (or only strictly below it if `include_path` is false),
+/// deepest mounts first, so that no container mounts are leaked into the host.
+fn unmount_all_below(path: &Path, include_path: bool) {
+	// mountinfo lists mounts in the order they were created, so reversing it ensures that
+	// stacked mounts on the same mount point are removed top to bottom.
+	let mut mount_points = get_mounts_below(path, include_path);
+	mount_points.reverse();
+	mount_points.sort_by_key(|mount_point| std::cmp::Reverse(mount_point.components().count()));
+
+	for mount_point in mount_points {
+		debug!("Unmounting {:?}", mount_point);
+		if let Err(err) = traced_syscall(
+			"umount2",
+			format_args!("{:?}, MNT_DETACH", mount_point),
+			|| nix::mount::umount2(&mount_point, MntFlags::MNT_DETACH),
+		) {
+			warn!("Could not unmount {:?}: {}", mount_point, err);
+		}
+	}
+}
+
+fn get_bundle_rootfs(container_dir: &Path) -> Option<PathBuf> {
+	let container = OCIContainer::load(container_dir)?;
+	rootfs::resolve_root_path(
+		container.spec().root().as_ref()?.path(),
+		Path::new(container.bundle()),
+	)
+	.ok()
+}
+
+/// Reports mounts that survived unmounting, e.g. because a submount was busy.
+/// Returns whether any mount was left behind.
+fn report_leaked_mounts(path: &Path, include_path: bool) -> bool {
+	let leaked_mounts = get_mounts_below(path, include_path);
+	for mount_point in &leaked_mounts {
+		error!(
+			"Mount at {:?} is still present after unmounting!",
+			mount_point
+		);
+	}
+	!leaked_mounts.is_empty()
+}
+
+/// Removes the state directory of a container together with everything runh put there (fifos,
+/// pidfiles, pinned namespaces, ...). The directory is canonicalized first, so that a symlink
+/// can never make runh delete anything outside of the state root.
+fn remove_container_dir(project_dir: &Path, container_dir: &Path) {
+	let state_root = fs::canonicalize(project_dir)
+		.unwrap_or_else(|err| panic!("Could not resolve state root {:?}: {}", project_dir, err));
+	let resolved_dir = fs::canonicalize(container_dir).unwrap_or_else(|err| {
+		panic!(
+			"Could not resolve container directory {:?}: {}",
+			container_dir, err
+		)
+	});
+	if resolved_dir.parent() != Some(state_root.as_path()) {
+		panic!(
+			"Refusing to delete {:?}, which is not a container directory in the state root {:?}!",
+			resolved_dir, state_root
+		);
+	}
+
+	fs::remove_dir_all(&resolved_dir).unwrap_or_else(|err| {
+		panic!(
+			"Unable to delete container directory {:?}: {}",
+			resolved_dir, err
+		)
+	});
+}
+
+/// Deletes a stopped container. With `fail_on_mount_leak`, the deletion is aborted before any
+/// files are removed if mounts below the rootfs or the container directory could not be removed.
+pub fn delete_container(
+	project_dir: PathBuf,
+	id: Option<&str>,
+	force: bool,
+	fail_on_mount_leak: bool,
+) {
 	if let Some(container_state) = state::get_container_state(project_dir.clone(), id.unwrap()) {
-		if container_state.status != "stopped" {
+		if container_state.status != ContainerStatus::Stopped {
 			if !force {
-				panic!("Tried to delete a container that is not stopped!");
-			} else if container_state.status != "creating" {
+				error::raise(RunhError::InvalidState {
+					id: id.unwrap().to_string(),
+					status: container_state.status,
+					expected: ContainerStatus::Stopped.to_string(),
+				});
+			} else if container_state.status != ContainerStatus::Creating {
 				warn!("Container is still running. Force-deleting...");
-				kill::kill_container(project_dir.clone(), id, Some("SIGKILL"), false);
+				kill::kill_container(project_dir.clone(), id, Some("SIGKILL"), false, None);
 			} else {
 				warn!("Container has not finished creation. Force-deleting...");
 			}
+		} else {
+			info!(
+				"Deleting container {}, its init {}",
+				id.unwrap(),
+				container_state.exit_status
+			);
 		}
 
 		let container_dir = project_dir.join(id.unwrap());
 
-		let rootfs_overlay_dir = container_dir.join("rootfs/merged");
-		if rootfs_overlay_dir.exists() {
-			nix::mount::umount2(&rootfs_overlay_dir, MntFlags::MNT_DETACH).unwrap_or_else(|_| {
-				panic!("Could not unmount overlay at {:?}", rootfs_overlay_dir)
-			});
+		// The bundle rootfs itself belongs to the container manager, so only mounts created
+		// below it are removed. Everything in the container dir (e.g. the hermit overlay) is ours.
+		let bundle_rootfs = get_bundle_rootfs(&container_dir);
+		if let Some(bundle_rootfs) = &bundle_rootfs {
+			unmount_all_below(bundle_rootfs, false);
+		}
+		unmount_all_below(&container_dir, true);
+
+		let mut leaked_mounts = report_leaked_mounts(&container_dir, true);
+		if let Some(bundle_rootfs) = &bundle_rootfs {
+			leaked_mounts |= report_leaked_mounts(bundle_rootfs, false);
+		}
+		if leaked_mounts && fail_on_mount_leak {
+			panic!(
+				"Could not delete container {}! Some of its mounts could not be removed",
+				id.unwrap()
+			);
 		}
 
 		// match reset_network_namespace(&container_dir) {
@@ -53,8 +170,18 @@ pub fn delete_container(project_dir: PathBuf, id: Option<&str>, force: bool) {
 		// 	Err(err) => warn!("Failed to reset network namespace! Error: {}", err),
 		// }
 
+		rdt::remove_group(id.unwrap());
+		if let Some(container) = OCIContainer::load(&container_dir) {
+			hooks::run_poststop_hooks(&container_dir, container.spec(), &container_state);
+			cgroups::remove_cgroup(&cgroups::get_container_cgroup_path(
+				&container_dir,
+				container.spec(),
+				id.unwrap(),
+			));
+		}
+
 		// delete all temporary files
-		fs::remove_dir_all(container_dir).expect("Unable to delete container");
+		remove_container_dir(&project_dir, &container_dir);
 
 	//Additionally to deleting all the files, we should also delete all remaining processes spawned by the container init process.
 	//However, without cgroup support there is currently no real way to do this as we do not know when (and how) the init process will be killed
@@ -65,3 +192,85 @@ pub fn delete_container(project_dir: PathBuf, id: Option<&str>, force: bool) {
 		);
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::rootfs::tests::{in_mount_namespace, mount_tmpfs};
+
+	#[test]
+	fn leaked_mounts_are_reported() {
+		in_mount_namespace(|base| {
+			let leaked = base.join("leaked");
+			mount_tmpfs(&leaked);
+
+			assert!(report_leaked_mounts(base, false));
+			nix::mount::umount2(&leaked, MntFlags::MNT_DETACH).unwrap();
+			assert!(!report_leaked_mounts(base, false));
+		});
+	}
+
+	#[test]
+	fn nested_mounts_are_unmounted() {
+		in_mount_namespace(|base| {
+			let outer = base.join("rootfs");
+			mount_tmpfs(&outer);
+			mount_tmpfs(&outer.join("proc"));
+			mount_tmpfs(&outer.join("proc/sys"));
+			// Stacked on the same mount point
+			mount_tmpfs(&outer.join("dev"));
+			mount_tmpfs(&outer.join("dev"));
+			assert_eq!(get_mounts_below(base, false).len(), 5);
+
+			unmount_all_below(base, false);
+			assert_eq!(get_mounts_below(base, true), vec![base.to_path_buf()]);
+		});
+	}
+
+	#[test]
+	fn mount_at_path_is_kept_without_include_path() {
+		in_mount_namespace(|base| {
+			let rootfs = base.join("rootfs");
+			mount_tmpfs(&rootfs);
+			mount_tmpfs(&rootfs.join("tmp"));
+
+			unmount_all_below(&rootfs, false);
+			assert_eq!(get_mounts_below(&rootfs, true), vec![rootfs.clone()]);
+			unmount_all_below(&rootfs, true);
+			assert!(get_mounts_below(&rootfs, true).is_empty());
+		});
+	}
+
+	#[test]
+	fn container_dir_is_removed_and_state_root_kept() {
+		let state_root = tempfile::tempdir().unwrap();
+		let container_dir = state_root.path().join("test");
+		fs::create_dir_all(container_dir.join("ns")).unwrap();
+		fs::write(container_dir.join("container.json"), "{}").unwrap();
+		fs::write(container_dir.join("containerpid"), "1").unwrap();
+		fs::write(container_dir.join("ns/net"), "").unwrap();
+		nix::unistd::mkfifo(
+			&container_dir.join("exec.fifo"),
+			nix::sys::stat::Mode::S_IRWXU,
+		)
+		.unwrap();
+		std::os::unix::fs::symlink("/", container_dir.join("bundle")).unwrap();
+		fs::write(state_root.path().join("other"), "").unwrap();
+
+		remove_container_dir(state_root.path(), &container_dir);
+		assert!(!container_dir.exists());
+		assert!(state_root.path().is_dir());
+		assert!(state_root.path().join("other").exists());
+	}
+
+	#[test]
+	#[should_panic(expected = "Refusing to delete")]
+	fn container_dir_outside_of_state_root_is_refused() {
+		let state_root = tempfile::tempdir().unwrap();
+		let outside = tempfile::tempdir().unwrap();
+		let container_dir = state_root.path().join("test");
+		std::os::unix::fs::symlink(outside.path(), &container_dir).unwrap();
+
+		remove_container_dir(state_root.path(), &container_dir);
+	}
+}
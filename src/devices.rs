@@ -1,6 +1,4 @@
-use std::{
-	convert::TryInto, fs::OpenOptions, os::unix::prelude::OpenOptionsExt, path::Path, path::PathBuf,
-};
+use std::{convert::TryInto, path::Path, path::PathBuf};
 
 use nix::{
 	mount::MsFlags,
@@ -11,7 +9,13 @@ use oci_spec::runtime;
 
 use crate::{mounts, rootfs};
 
-pub fn create_devices(spec_devices: &Option<Vec<runtime::LinuxDevice>>, rootfs: &Path) {
+/// Creates the default devices and those of the config in the /dev of the container. Without
+/// privileges, mknod is not allowed, so in `rootless` mode the host devices are bind-mounted.
+pub fn create_devices(
+	spec_devices: &Option<Vec<runtime::LinuxDevice>>,
+	rootfs: &Path,
+	rootless: bool,
+) {
 	let mut default_devices = vec![
 		runtime::LinuxDeviceBuilder::default()
 			.path(PathBuf::from("/dev/null"))
@@ -96,6 +100,15 @@ pub fn create_devices(spec_devices: &Option<Vec<runtime::LinuxDevice>>, rootfs:
 		if !destination_resolved.starts_with(&rootfs) {
 			panic!("Device at {:?} cannot be mounted into rootfs!", dev.path());
 		}
+		if rootless {
+			mount_device(
+				rootfs,
+				dev.path(),
+				dev.major().try_into().unwrap(),
+				dev.minor().try_into().unwrap(),
+			);
+			continue;
+		}
 		mounts::create_all_dirs(&PathBuf::from(
 			&destination_resolved.parent().unwrap_or_else(|| {
 				panic!(
@@ -220,19 +233,7 @@ fn mount_device(rootfs: &Path, destination_rel: &Path, major: u64, minor: u64) {
 		verify_device(&destination, major, minor);
 		return;
 	}
-	if !destination.exists() {
-		let _ = OpenOptions::new()
-			.mode(0o755)
-			.create(true)
-			.write(true)
-			.open(&destination)
-			.unwrap_or_else(|_| {
-				panic!(
-					"Could not create destination for bind mount at {:?}",
-					destination
-				)
-			});
-	}
+	mounts::create_bind_placeholder(&destination, false);
 
 	mounts::mount_with_flags(
 		"bind",
@@ -248,6 +249,49 @@ fn mount_device(rootfs: &Path, destination_rel: &Path, major: u64, minor: u64) {
 	);
 }
 
+/// Annotation that makes runh bind-mount the host /dev into the container (`true`) instead of
+/// creating the configured device nodes
+pub const HOST_DEV_ANNOTATION: &str = "io.runh.dev.bind-host";
+
+/// Whether the host /dev should be bind-mounted into the container, which is only done when
+/// it is requested explicitly through the annotation
+pub fn wants_host_dev(spec: &runtime::Spec) -> bool {
+	let bind_host = spec
+		.annotations()
+		.as_ref()
+		.and_then(|annotations| annotations.get(HOST_DEV_ANNOTATION))
+		.map_or(false, |value| value == "true");
+	if bind_host {
+		info!(
+			"Annotation {}=true, bind-mounting the host /dev",
+			HOST_DEV_ANNOTATION
+		);
+	}
+	bind_host
+}
+
+/// Bind-mounts the host /dev onto the /dev of the container. The bind is not recursive, so the
+/// host devpts, shm and mqueue mounts stay outside and the spec mounts below /dev take their place.
+pub fn bind_host_dev(rootfs: &Path) {
+	let destination = rootfs::resolve_in_rootfs(Path::new("/dev"), rootfs);
+	if !destination.exists() {
+		mounts::create_all_dirs(&destination);
+	}
+
+	mounts::mount_with_flags(
+		"bind",
+		Path::new("/dev"),
+		Path::new("/dev"),
+		&destination,
+		mounts::MountOptions {
+			mount_flags: MsFlags::MS_BIND,
+			propagation_flags: MsFlags::empty(),
+			data: None,
+		},
+		None,
+	);
+}
+
 pub fn setup_dev_symlinks(rootfs: &Path) {
 	// if PathBuf::from("/proc/kcore").exists() {
 	// 	nix::unistd::symlinkat("/proc/kcore", None, &rootfs.join("dev/core"))
@@ -0,0 +1,79 @@
+use crate::cgroups;
+use crate::container::OCIContainer;
+use crate::error::{self, RunhError};
+use crate::mountinfo;
+use crate::state;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+/// Namespace links of a process, e.g. `net` -> `net:[4026531992]`
+fn get_namespace_links(pid: i32) -> BTreeMap<String, String> {
+	std::fs::read_dir(format!("/proc/{}/ns", pid))
+		.into_iter()
+		.flatten()
+		.flatten()
+		.filter_map(|entry| {
+			let link = std::fs::read_link(entry.path()).ok()?;
+			Some((
+				entry.file_name().to_string_lossy().into_owned(),
+				link.to_string_lossy().into_owned(),
+			))
+		})
+		.collect()
+}
+
+/// Collects everything runh knows about a running init into a JSON object
+fn dump_process(pid: i32) -> Value {
+	let status = std::fs::read_to_string(format!("/proc/{}/status", pid))
+		.map_err(|err| warn!("Could not read status of process {}: {}", pid, err))
+		.ok();
+	// The process may exit at any time, which must not abort the dump
+	let mounts = std::fs::read_to_string(format!("/proc/{}/mountinfo", pid))
+		.ok()
+		.map(|mountinfo| {
+			mountinfo
+				.lines()
+				.filter_map(|line| mountinfo::parse_line(line).ok())
+				.collect::<Vec<_>>()
+		});
+	json!({
+		"pid": pid,
+		"status": status,
+		"mountinfo": mounts,
+		"namespaces": get_namespace_links(pid),
+	})
+}
+
+/// Prints the spec, state, init process details and cgroup stats of a container as a single
+/// JSON object, to be attached to bug reports
+pub fn print_debug_dump(project_dir: PathBuf, id: &str) {
+	let container_dir = project_dir.join(id);
+	let container = OCIContainer::load(&container_dir)
+		.unwrap_or_else(|| error::raise(RunhError::ContainerNotFound(id.to_string())));
+	let container_state = state::get_container_state(project_dir, id);
+	let cgroup_path = cgroups::get_container_cgroup_path(&container_dir, container.spec(), id);
+
+	let process = match container_state.as_ref() {
+		Some(container_state) if container_state.status != state::ContainerStatus::Stopped => {
+			container_state.pid.map(dump_process)
+		}
+		_ => None,
+	};
+
+	let dump = json!({
+		"id": id,
+		"runhVersion": clap::crate_version!(),
+		"container": container,
+		"state": container_state,
+		"process": process,
+		"cgroup": {
+			"path": cgroup_path,
+			"stats": cgroups::read_stats(&cgroup_path),
+		},
+	});
+	println!(
+		"{}",
+		serde_json::to_string_pretty(&dump).expect("Could not serialize debug dump!")
+	);
+}
@@ -0,0 +1,158 @@
+use crate::state::ContainerStatus;
+use std::{
+	fmt,
+	fs::File,
+	io::Write,
+	os::unix::io::{FromRawFd, RawFd},
+	panic::PanicInfo,
+	path::PathBuf,
+};
+
+/// Errors that callers of runh may want to tell apart. runh reports failures by panicking,
+/// so these are raised with `raise` and picked up again by the panic hook.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RunhError {
+	ContainerExists(String),
+	ContainerNotFound(String),
+	InvalidState {
+		id: String,
+		status: ContainerStatus,
+		expected: String,
+	},
+	BindSourceMissing {
+		source: PathBuf,
+		destination: PathBuf,
+		reason: String,
+	},
+	UnsupportedSpecField(String),
+	InvalidId {
+		id: String,
+		reason: String,
+	},
+	StateRootUnwritable {
+		path: PathBuf,
+		source: String,
+	},
+	SubidHelpersMissing {
+		binary: String,
+		reason: String,
+	},
+	ConfigTooLarge {
+		path: PathBuf,
+		limit: u64,
+	},
+	Internal(String),
+}
+
+impl RunhError {
+	/// Stable identifier of the error category for the machine-readable error output
+	pub fn code(&self) -> &'static str {
+		match self {
+			RunhError::ContainerExists(_) => "container_exists",
+			RunhError::ContainerNotFound(_) => "container_not_found",
+			RunhError::InvalidState { .. } => "invalid_state",
+			RunhError::BindSourceMissing { .. } => "bind_source_missing",
+			RunhError::UnsupportedSpecField(_) => "unsupported_spec_field",
+			RunhError::InvalidId { .. } => "invalid_id",
+			RunhError::StateRootUnwritable { .. } => "state_root_unwritable",
+			RunhError::SubidHelpersMissing { .. } => "subid_helpers_missing",
+			RunhError::ConfigTooLarge { .. } => "config_too_large",
+			RunhError::Internal(_) => "internal",
+		}
+	}
+}
+
+impl fmt::Display for RunhError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			RunhError::ContainerExists(id) => write!(f, "container with id {} already exists", id),
+			RunhError::ContainerNotFound(id) => {
+				write!(f, "container with id {} does not exist", id)
+			}
+			RunhError::InvalidState {
+				id,
+				status,
+				expected,
+			} => write!(
+				f,
+				"container {} is {} but has to be {} for this operation",
+				id, status, expected
+			),
+			RunhError::BindSourceMissing {
+				source,
+				destination,
+				reason,
+			} => write!(
+				f,
+				"source {:?} of the bind mount at {:?} could not be accessed: {}",
+				source, destination, reason
+			),
+			RunhError::UnsupportedSpecField(field) => {
+				write!(f, "spec field {} is not supported on Linux", field)
+			}
+			RunhError::InvalidId { id, reason } => {
+				write!(f, "invalid container id {:?}: {}", id, reason)
+			}
+			RunhError::StateRootUnwritable { path, source } => write!(
+				f,
+				"state root {:?} is not writable: {}. Run runh as root or point --root to a \
+				 writable directory, e.g. below $XDG_RUNTIME_DIR",
+				path, source
+			),
+			RunhError::SubidHelpersMissing { binary, reason } => write!(
+				f,
+				"{} is required to map more than the own id in rootless mode, but {}. Install \
+				 the shadow-utils/uidmap package",
+				binary, reason
+			),
+			RunhError::ConfigTooLarge { path, limit } => write!(
+				f,
+				"config {:?} is larger than {} bytes. Raise the limit with --max-config-size",
+				path, limit
+			),
+			RunhError::Internal(message) => write!(f, "{}", message),
+		}
+	}
+}
+
+impl std::error::Error for RunhError {}
+
+/// Aborts the current command with the given error
+pub fn raise(err: RunhError) -> ! {
+	std::panic::panic_any(err)
+}
+
+/// Recovers the error from a panic. Panics with a plain message are reported as internal errors.
+pub fn from_panic(panic_info: &PanicInfo) -> RunhError {
+	let payload = panic_info.payload();
+	if let Some(err) = payload.downcast_ref::<RunhError>() {
+		err.clone()
+	} else if let Some(message) = payload.downcast_ref::<&str>() {
+		RunhError::Internal(message.to_string())
+	} else if let Some(message) = payload.downcast_ref::<String>() {
+		RunhError::Internal(message.clone())
+	} else {
+		RunhError::Internal(panic_info.to_string())
+	}
+}
+
+/// Extends the current panic hook to additionally write the error as JSON object to `fd`.
+/// The fd is closed afterwards, so the reader sees EOF once the error is complete.
+pub fn install_error_fd_hook(fd: RawFd, container_id: Option<String>) {
+	let previous_hook = std::panic::take_hook();
+	std::panic::set_hook(Box::new(move |panic_info| {
+		previous_hook(panic_info);
+
+		let err = from_panic(panic_info);
+		let report = serde_json::json!({
+			"code": err.code(),
+			"message": err.to_string(),
+			"container_id": container_id,
+		});
+		let mut error_file = unsafe { File::from_raw_fd(fd) };
+		if let Err(write_err) = writeln!(error_file, "{}", report).and_then(|_| error_file.flush())
+		{
+			eprintln!("Could not write error to fd {}: {}", fd, write_err);
+		}
+	}));
+}
@@ -0,0 +1,55 @@
+use nix::fcntl::{fcntl, FcntlArg, FdFlag};
+use std::os::unix::prelude::RawFd;
+
+/// The first fd number after stdin, stdout and stderr
+pub const FIRST_EXTRA_FD: RawFd = 3;
+
+const CLOSE_RANGE_CLOEXEC: libc::c_uint = 1 << 2;
+
+fn get_open_fds() -> Vec<RawFd> {
+	match std::fs::read_dir("/proc/self/fd") {
+		Ok(entries) => entries
+			.filter_map(|entry| entry.ok())
+			.filter_map(|entry| entry.file_name().to_str()?.parse().ok())
+			.collect(),
+		Err(err) => {
+			warn!("Could not list open fds through /proc/self/fd: {}", err);
+			Vec::new()
+		}
+	}
+}
+
+/// Keeps the `preserve_fds` fds following stderr open across exec.
+pub fn preserve_fds(preserve_fds: RawFd) {
+	for fd in FIRST_EXTRA_FD..FIRST_EXTRA_FD + preserve_fds {
+		debug!("Preserving fd {} for the container process", fd);
+		fcntl(fd, FcntlArg::F_SETFD(FdFlag::empty()))
+			.unwrap_or_else(|err| panic!("Could not preserve fd {}: {}", fd, err));
+	}
+}
+
+/// Marks every fd starting at `first_fd` as close-on-exec, so that none of the fds used by
+/// runh (log pipe, init pipe, spec file, ...) leak into the container process.
+/// Uses close_range where available and falls back to iterating /proc/self/fd otherwise.
+pub fn set_cloexec_from(first_fd: RawFd) {
+	let ret = unsafe {
+		libc::syscall(
+			libc::SYS_close_range,
+			first_fd as libc::c_uint,
+			libc::c_uint::MAX,
+			CLOSE_RANGE_CLOEXEC,
+		)
+	};
+	if ret == 0 {
+		return;
+	}
+
+	debug!(
+		"close_range failed ({}), marking fds as close-on-exec through /proc/self/fd",
+		std::io::Error::last_os_error()
+	);
+	for fd in get_open_fds().into_iter().filter(|fd| *fd >= first_fd) {
+		// The fd used for reading /proc/self/fd is already closed again, so errors are expected
+		let _ = fcntl(fd, FcntlArg::F_SETFD(FdFlag::FD_CLOEXEC));
+	}
+}
@@ -0,0 +1,142 @@
+use nix::sched::CloneFlags;
+use nix::sys::wait::{waitpid, WaitStatus};
+use nix::unistd::ForkResult;
+use serde::*;
+use std::{collections::BTreeMap, fs, path::Path};
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Features {
+	kernel_version: String,
+	cgroup_version: String,
+	freezer: bool,
+	idmapped_mounts: bool,
+	seccomp: bool,
+	libseccomp: bool,
+	user_namespaces: bool,
+	namespaces: BTreeMap<String, bool>,
+}
+
+fn get_kernel_version() -> (String, u32, u32) {
+	let uname = nix::sys::utsname::uname().expect("Could not query the kernel version!");
+	let release = uname.release().to_string_lossy().to_string();
+	let mut numbers = release
+		.split(|c: char| !c.is_ascii_digit())
+		.map(|n| n.parse::<u32>().unwrap_or(0));
+	let major = numbers.next().unwrap_or(0);
+	let minor = numbers.next().unwrap_or(0);
+	(release, major, minor)
+}
+
+fn get_cgroup_version() -> &'static str {
+	if Path::new("/sys/fs/cgroup/cgroup.controllers").exists() {
+		"v2"
+	} else if Path::new("/sys/fs/cgroup/unified/cgroup.controllers").exists() {
+		"hybrid"
+	} else if Path::new("/sys/fs/cgroup").exists() {
+		"v1"
+	} else {
+		"none"
+	}
+}
+
+fn has_freezer(cgroup_version: &str, kernel: (u32, u32)) -> bool {
+	match cgroup_version {
+		// The v2 freezer is part of the core cgroup interface since Linux 5.2
+		"v2" => kernel >= (5, 2),
+		"v1" | "hybrid" => Path::new("/sys/fs/cgroup/freezer").exists(),
+		_ => false,
+	}
+}
+
+fn has_seccomp() -> bool {
+	fs::read_to_string("/proc/self/status")
+		.map(|status| status.lines().any(|line| line.starts_with("Seccomp:")))
+		.unwrap_or(false)
+}
+
+fn has_libseccomp() -> bool {
+	[
+		"/lib",
+		"/lib64",
+		"/usr/lib",
+		"/usr/lib64",
+		"/lib/x86_64-linux-gnu",
+		"/usr/lib/x86_64-linux-gnu",
+		"/lib/aarch64-linux-gnu",
+		"/usr/lib/aarch64-linux-gnu",
+	]
+	.iter()
+	.any(|dir| Path::new(dir).join("libseccomp.so.2").exists())
+}
+
+fn has_user_namespaces() -> bool {
+	let max_namespaces = fs::read_to_string("/proc/sys/user/max_user_namespaces")
+		.ok()
+		.and_then(|max| max.trim().parse::<u64>().ok())
+		.unwrap_or(0);
+	// Debian-based kernels can additionally disable unprivileged user namespaces
+	let unprivileged_allowed = fs::read_to_string("/proc/sys/kernel/unprivileged_userns_clone")
+		.map(|allowed| allowed.trim() != "0")
+		.unwrap_or(true);
+	max_namespaces > 0 && unprivileged_allowed
+}
+
+/// Tries to unshare the given namespace in a short-lived child process, so the
+/// namespaces of runh itself stay untouched.
+fn can_create_namespace(flag: CloneFlags) -> bool {
+	match unsafe { nix::unistd::fork() } {
+		Ok(ForkResult::Child) => {
+			let code = if nix::sched::unshare(flag).is_ok() {
+				0
+			} else {
+				1
+			};
+			unsafe { libc::_exit(code) };
+		}
+		Ok(ForkResult::Parent { child }) => {
+			matches!(waitpid(child, None), Ok(WaitStatus::Exited(_, 0)))
+		}
+		Err(err) => {
+			warn!("Could not fork to probe namespace {:?}: {}", flag, err);
+			false
+		}
+	}
+}
+
+pub fn get_features() -> Features {
+	let (kernel_version, major, minor) = get_kernel_version();
+	let cgroup_version = get_cgroup_version();
+
+	let namespaces = [
+		("cgroup", CloneFlags::CLONE_NEWCGROUP),
+		("ipc", CloneFlags::CLONE_NEWIPC),
+		("mount", CloneFlags::CLONE_NEWNS),
+		("network", CloneFlags::CLONE_NEWNET),
+		("pid", CloneFlags::CLONE_NEWPID),
+		("user", CloneFlags::CLONE_NEWUSER),
+		("uts", CloneFlags::CLONE_NEWUTS),
+	]
+	.iter()
+	.map(|(name, flag)| (name.to_string(), can_create_namespace(*flag)))
+	.collect();
+
+	Features {
+		kernel_version,
+		cgroup_version: cgroup_version.to_string(),
+		freezer: has_freezer(cgroup_version, (major, minor)),
+		// Idmapped mounts were introduced with Linux 5.12
+		idmapped_mounts: (major, minor) >= (5, 12),
+		seccomp: has_seccomp(),
+		libseccomp: has_libseccomp(),
+		user_namespaces: has_user_namespaces(),
+		namespaces,
+	}
+}
+
+pub fn print_features() {
+	println!(
+		"{}",
+		serde_json::to_string_pretty(&get_features()).expect("Could not serialize feature report!")
+	);
+}
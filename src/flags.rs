@@ -22,3 +22,34 @@ pub fn get_cloneflag(typ: runtime::LinuxNamespaceType) -> CloneFlags {
 		runtime::LinuxNamespaceType::Uts => CloneFlags::CLONE_NEWUTS,
 	}
 }
+
+fn get_namespace_name(typ: runtime::LinuxNamespaceType) -> &'static str {
+	match typ {
+		runtime::LinuxNamespaceType::Cgroup => "cgroup",
+		runtime::LinuxNamespaceType::Ipc => "ipc",
+		runtime::LinuxNamespaceType::Mount => "mount",
+		runtime::LinuxNamespaceType::Network => "network",
+		runtime::LinuxNamespaceType::Pid => "pid",
+		runtime::LinuxNamespaceType::User => "user",
+		runtime::LinuxNamespaceType::Uts => "uts",
+	}
+}
+
+/// Logs the computed clone flags along with the namespaces that will be created and joined
+pub fn log_cloneflags(namespaces: &[runtime::LinuxNamespace], cloneflags: CloneFlags) {
+	let (joined, created): (Vec<_>, Vec<_>) = namespaces.iter().partition(|ns| ns.path().is_some());
+	let names = |namespaces: Vec<&runtime::LinuxNamespace>| {
+		namespaces
+			.into_iter()
+			.map(|ns| get_namespace_name(ns.typ()))
+			.collect::<Vec<_>>()
+			.join(", ")
+	};
+	debug!(
+		"clone flags {:#x} ({:?}), creating namespaces [{}], joining namespaces [{}]",
+		cloneflags.bits(),
+		cloneflags,
+		names(created),
+		names(joined)
+	);
+}
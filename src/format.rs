@@ -0,0 +1,100 @@
+use serde::Serialize;
+use serde_json::Value;
+use std::str::FromStr;
+
+/// How query commands render their results
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+	Table,
+	Json,
+}
+
+impl FromStr for OutputFormat {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s {
+			"table" => Ok(OutputFormat::Table),
+			"json" => Ok(OutputFormat::Json),
+			_ => Err(format!("Unknown output format {}", s)),
+		}
+	}
+}
+
+/// Renders a single table cell. Strings are printed without quotes, missing values as empty cells
+/// and nested values as compact JSON.
+fn to_cell(value: Option<&Value>) -> String {
+	match value {
+		None | Some(Value::Null) => String::new(),
+		Some(Value::String(s)) => s.clone(),
+		Some(value) => value.to_string(),
+	}
+}
+
+fn print_table(headers: &[&str], rows: &[Vec<String>]) {
+	let widths: Vec<usize> = headers
+		.iter()
+		.enumerate()
+		.map(|(i, header)| {
+			rows.iter()
+				.map(|row| row[i].len())
+				.chain(std::iter::once(header.len()))
+				.max()
+				.unwrap()
+		})
+		.collect();
+	let print_row = |cells: Vec<&str>| {
+		let line = cells
+			.iter()
+			.zip(&widths)
+			.map(|(cell, width)| format!("{: <width$}", cell, width = width))
+			.collect::<Vec<_>>()
+			.join("   ");
+		println!("{}", line.trim_end());
+	};
+
+	print_row(headers.to_vec());
+	for row in rows {
+		print_row(row.iter().map(String::as_str).collect());
+	}
+}
+
+/// Prints `value` to stdout. As table, a list is printed with one row per element and the given
+/// `(header, field)` columns, a single object with one row per field.
+pub fn print_output<T: Serialize>(value: &T, format: OutputFormat, columns: &[(&str, &str)]) {
+	// Serialized directly, so the JSON keeps the field order of `T`
+	if format == OutputFormat::Json {
+		println!(
+			"{}",
+			serde_json::to_string(value).expect("Could not serialize output!")
+		);
+		return;
+	}
+
+	let value = serde_json::to_value(value).expect("Could not serialize output!");
+
+	match &value {
+		Value::Array(elements) => {
+			let headers: Vec<&str> = columns.iter().map(|(header, _)| *header).collect();
+			let rows: Vec<Vec<String>> = elements
+				.iter()
+				.map(|element| {
+					columns
+						.iter()
+						.map(|(_, field)| to_cell(element.get(field)))
+						.collect()
+				})
+				.collect();
+			print_table(&headers, &rows);
+		}
+		Value::Object(fields) => {
+			let rows: Vec<Vec<String>> = columns
+				.iter()
+				.filter(|(_, field)| fields.contains_key(*field))
+				.map(|(header, field)| vec![header.to_string(), to_cell(fields.get(*field))])
+				.collect();
+			print_table(&["FIELD", "VALUE"], &rows);
+		}
+		value => println!("{}", to_cell(Some(value))),
+	}
+}
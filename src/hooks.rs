@@ -0,0 +1,184 @@
+use crate::state::{ContainerStatus, State};
+use oci_spec::runtime::{Hook, Spec};
+use std::io::{ErrorKind, Write};
+use std::os::unix::process::CommandExt;
+use std::path::Path;
+
+/// Marks that the poststop hooks of a container already ran
+const POSTSTOP_MARKER: &str = "poststop";
+
+/// Runs a single hook with the container state on its stdin
+fn run_hook(hook: &Hook, state: &State) -> Result<(), String> {
+	let mut cmd = std::process::Command::new(&hook.path());
+	if let Some(args) = &hook.args() {
+		if !args.is_empty() {
+			cmd.arg0(&args[0]);
+		}
+		if args.len() > 1 {
+			cmd.args(&args[1..]);
+		}
+	}
+	if let Some(env) = &hook.env() {
+		for var in env {
+			let (name, value) = var
+				.split_once('=')
+				.ok_or_else(|| format!("Could not parse environment variable: {}", var))?;
+			cmd.env(name, value);
+		}
+	}
+	if hook.timeout().is_some() {
+		warn!(
+			"The timeout set for hook {:?} is currently unimplemented and will be ignored!",
+			hook.path()
+		);
+	}
+	cmd.stderr(std::process::Stdio::piped());
+	cmd.stdin(std::process::Stdio::piped());
+	let mut child = cmd
+		.spawn()
+		.map_err(|err| format!("Unable to spawn hook process {:?}: {}", hook.path(), err))?;
+	// Hooks do not have to read the state, so one that exits or closes its stdin is no error
+	if let Err(err) = write!(
+		child.stdin.take().unwrap(),
+		"{}",
+		serde_json::to_string(state).unwrap()
+	) {
+		if err.kind() != ErrorKind::BrokenPipe {
+			return Err(format!(
+				"Could not write container state to hook stdin: {}",
+				err
+			));
+		}
+		debug!("Hook {:?} did not read the container state", hook.path());
+	}
+
+	let ret = child
+		.wait_with_output()
+		.map_err(|err| format!("Could not wait for hook {:?}: {}", hook.path(), err))?;
+	if !ret.status.success() {
+		return Err(format!(
+			"hook {:?} returned exit status {}. Stderr: {}",
+			hook.path(),
+			ret.status,
+			String::from_utf8_lossy(&ret.stderr)
+		));
+	}
+	Ok(())
+}
+
+/// Runs hooks during the creation of a container. A failing hook aborts the creation.
+fn run_create_hooks(kind: &str, hooks: Option<&Vec<Hook>>, state: &State) {
+	for hook in hooks.into_iter().flatten() {
+		if let Err(err) = run_hook(hook, state) {
+			panic!("{} {}", kind, err);
+		}
+	}
+}
+
+/// Runs the prestart hooks of a container. A failing hook aborts the creation.
+pub fn run_prestart_hooks(spec: &Spec, state: &State) {
+	let prestart_hooks = spec
+		.hooks()
+		.as_ref()
+		.and_then(|hooks| hooks.prestart().as_ref());
+	run_create_hooks("prestart", prestart_hooks, state);
+}
+
+/// Runs the createRuntime hooks of a container in the runtime namespace. They run once the init
+/// has created all namespaces but before it pivots into the rootfs, so that e.g. CNI plugins can
+/// configure the network namespace at /proc/<pid>/ns/net. A failing hook aborts the creation.
+pub fn run_create_runtime_hooks(spec: &Spec, state: &State) {
+	let create_runtime_hooks = spec
+		.hooks()
+		.as_ref()
+		.and_then(|hooks| hooks.create_runtime().as_ref());
+	run_create_hooks("createRuntime", create_runtime_hooks, state);
+}
+
+/// Runs the poststop hooks of a container, at most once per container. The OCI spec requires them
+/// to run whenever a container stops, including failed creates, so that e.g. its network is torn
+/// down. Failing hooks are only logged, so that the remaining cleanup still happens.
+pub fn run_poststop_hooks(container_dir: &Path, spec: &Spec, state: &State) {
+	let marker = container_dir.join(POSTSTOP_MARKER);
+	if marker.exists() {
+		debug!("Poststop hooks of container {} already ran", state.id);
+		return;
+	}
+	// The marker is written first, so that a hook that fails halfway is not repeated either
+	if let Err(err) = std::fs::write(&marker, "") {
+		warn!("Could not mark poststop hooks as run: {}", err);
+	}
+
+	let mut state = state.clone();
+	state.status = ContainerStatus::Stopped;
+	let poststop_hooks = spec
+		.hooks()
+		.as_ref()
+		.and_then(|hooks| hooks.poststop().as_ref());
+	for hook in poststop_hooks.into_iter().flatten() {
+		if let Err(err) = run_hook(hook, &state) {
+			warn!("poststop {}", err);
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use oci_spec::runtime::HookBuilder;
+	use std::collections::HashMap;
+
+	fn shell_hook(script: &str) -> Hook {
+		HookBuilder::default()
+			.path("/bin/sh")
+			.args(vec![
+				String::from("sh"),
+				String::from("-c"),
+				String::from(script),
+			])
+			.build()
+			.unwrap()
+	}
+
+	/// A state larger than the pipe buffer, so that writing it blocks until the hook reads
+	/// it or closes its stdin
+	fn large_state() -> State {
+		State {
+			version: String::from("1.0.2"),
+			id: String::from("test"),
+			status: ContainerStatus::Created,
+			pid: Some(4242),
+			bundle: String::from("/var/lib/bundles/test"),
+			annotations: Some(HashMap::from([(
+				String::from("large"),
+				"x".repeat(1 << 20),
+			)])),
+			exit_status: Default::default(),
+		}
+	}
+
+	#[test]
+	fn hook_that_ignores_stdin_succeeds() {
+		assert_eq!(
+			run_hook(&shell_hook("exec <&-; exit 0"), &large_state()),
+			Ok(())
+		);
+	}
+
+	#[test]
+	fn exit_status_is_checked_without_reading_stdin() {
+		let err = run_hook(
+			&shell_hook("exec <&-; echo failed >&2; exit 3"),
+			&large_state(),
+		)
+		.unwrap_err();
+		assert!(err.contains("exit status: 3"), "{}", err);
+		assert!(err.contains("failed"), "{}", err);
+	}
+
+	#[test]
+	fn hook_reads_state_from_stdin() {
+		let hook = shell_hook("grep -q '\"id\":\"test\"'");
+		assert_eq!(run_hook(&hook, &large_state()), Ok(()));
+	}
+}
@@ -1,3 +1,5 @@
+use std::convert::TryFrom;
+use std::fmt;
 use std::fs::OpenOptions;
 use std::io::{Read, Write};
 use std::os::unix::prelude::{IntoRawFd, OpenOptionsExt};
@@ -9,7 +11,14 @@ use std::{
 	os::unix::prelude::{FromRawFd, RawFd},
 };
 
-use crate::{console, devices, hermit, mounts};
+use crate::personality::{self, Personality};
+use crate::reaper;
+use crate::scheduling::{self, SchedulingConfig};
+use crate::selinux;
+use crate::spec;
+use crate::syscall::traced_syscall;
+use crate::sysctl;
+use crate::{apparmor, console, devices, fds, hermit, mounts};
 use crate::{flags, paths, rootfs};
 use crate::{namespaces, network};
 use capctl::prctl;
@@ -40,6 +49,12 @@ struct InitConfig {
 	rootfs: String,
 	bundle_rootfs: String,
 	is_hermit_container: bool,
+	scheduling: SchedulingConfig,
+	personality: Option<Personality>,
+	/// Checked `uidMappings`, sorted by container id
+	uid_mappings: Vec<runtime::LinuxIdMapping>,
+	/// Checked `gidMappings`, sorted by container id
+	gid_mappings: Vec<runtime::LinuxIdMapping>,
 }
 
 #[derive(Clone, Debug)]
@@ -50,6 +65,63 @@ struct SetupArgs {
 
 const STACK_SIZE: usize = 16384 * 2;
 
+/// Reasons for not executing the container process
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExecError {
+	ArgListTooLong { size: usize, limit: usize },
+}
+
+impl fmt::Display for ExecError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			ExecError::ArgListTooLong { size, limit } => write!(
+				f,
+				"Argument list too long! The container args and environment take up {} bytes, but ARG_MAX is {} bytes",
+				size, limit
+			),
+		}
+	}
+}
+
+impl std::error::Error for ExecError {}
+
+/// Verifies that argv and envp of the container process fit into ARG_MAX, so that users get
+/// a precise error message instead of an E2BIG from the final exec call.
+fn check_arg_list_size(exec_args: &[String]) -> Result<(), ExecError> {
+	let arg_max = match nix::unistd::sysconf(nix::unistd::SysconfVar::ARG_MAX) {
+		Ok(Some(arg_max)) => arg_max as usize,
+		_ => {
+			warn!("Could not determine ARG_MAX, skipping argument list size check!");
+			return Ok(());
+		}
+	};
+
+	let pointer_size = std::mem::size_of::<*const libc::c_char>();
+	let args_size: usize = exec_args
+		.iter()
+		.map(|arg| arg.len() + 1 + pointer_size)
+		.sum();
+	let env_size: usize = env::vars_os()
+		.map(|(name, value)| name.len() + value.len() + 2 + pointer_size)
+		.sum();
+	// Both arrays are terminated by a NULL pointer
+	let total_size = args_size + env_size + 2 * pointer_size;
+
+	if total_size > arg_max {
+		return Err(ExecError::ArgListTooLong {
+			size: total_size,
+			limit: arg_max,
+		});
+	}
+	Ok(())
+}
+
+/// Splits a `process.env` entry at its first `=`. As the OCI spec demands, the value is used
+/// literally: `$VAR` references, further `=` and whitespace are passed to the container as-is.
+fn split_env_entry(entry: &str) -> Option<(&str, &str)> {
+	entry.split_once('=')
+}
+
 pub fn init_container() {
 	// This implements the init process functionality,
 	// analogous to https://github.com/opencontainers/runc/blob/master/libcontainer/nsenter/nsexec.c
@@ -120,13 +192,30 @@ pub fn init_container() {
 		.parse()
 		.expect("RUNH_SPEC_FILE was not an integer!");
 	let spec_file = unsafe { File::from_raw_fd(spec_fd) };
-	let spec: Spec = serde_json::from_reader(&spec_file).expect("Unable to read spec file!");
+	let spec_value = spec::read_config(&spec_file)
+		.unwrap_or_else(|err| panic!("Unable to read spec file: {}", err));
+	let spec: Spec =
+		serde_json::from_value(spec_value.clone()).expect("Unable to parse spec file!");
+	let scheduling: SchedulingConfig = spec_value
+		.get("process")
+		.map(|process| {
+			serde_json::from_value(process.clone())
+				.expect("Unable to parse scheduling options of the spec process!")
+		})
+		.unwrap_or_default();
+	let personality: Option<Personality> =
+		spec_value.pointer("/linux/personality").map(|personality| {
+			serde_json::from_value(personality.clone())
+				.expect("Unable to parse linux.personality of the spec!")
+		});
 
 	let linux_spec = spec.linux().as_ref().unwrap();
 
 	debug!("generate clone-flags");
 	let cloneflags = if let Some(namespaces) = &linux_spec.namespaces() {
-		flags::generate_cloneflags(namespaces)
+		let cloneflags = flags::generate_cloneflags(namespaces);
+		flags::log_cloneflags(namespaces, cloneflags);
+		cloneflags
 	} else {
 		CloneFlags::empty()
 	};
@@ -165,11 +254,15 @@ pub fn init_container() {
 			rootfs: rootfs_path,
 			bundle_rootfs: bundle_rootfs_path,
 			is_hermit_container,
+			scheduling,
+			personality,
+			uid_mappings: Vec::new(),
+			gid_mappings: Vec::new(),
 		},
 	});
 }
 
-fn init_stage_parent(args: SetupArgs) -> isize {
+fn init_stage_parent(mut args: SetupArgs) -> isize {
 	let linux_spec = args.config.spec.linux().as_ref().unwrap();
 
 	debug!("Enter init_stage parent");
@@ -180,6 +273,14 @@ fn init_stage_parent(args: SetupArgs) -> isize {
 		namespaces::join_namespaces(namespaces)
 	}
 
+	// The mappings are also used to shift the ownership of recursiveChown mounts
+	let check_id_mappings = |kind, mappings: &Option<Vec<runtime::LinuxIdMapping>>| {
+		namespaces::check_id_mappings(kind, mappings.as_deref().unwrap_or_default())
+			.unwrap_or_else(|err| panic!("Invalid id mappings: {}", err))
+	};
+	args.config.uid_mappings = check_id_mappings("uidMappings", linux_spec.uid_mappings());
+	args.config.gid_mappings = check_id_mappings("gidMappings", linux_spec.gid_mappings());
+
 	//TODO: Unshare user namespace if requested (needs additional clone)
 	if args.config.cloneflags.contains(CloneFlags::CLONE_NEWUSER) {
 		unimplemented!("User namespaces are currently not supported by runh!")
@@ -197,7 +298,10 @@ fn init_stage_parent(args: SetupArgs) -> isize {
 	);
 	let mut flags = args.config.cloneflags;
 	flags.remove(CloneFlags::CLONE_NEWCGROUP);
-	nix::sched::unshare(flags).expect("could not unshare non-user namespaces!");
+	traced_syscall("unshare", format_args!("{:?}", flags), || {
+		nix::sched::unshare(flags)
+	})
+	.expect("could not unshare non-user namespaces!");
 
 	// Fork again into new PID-Namespace and send PID to parent
 	let stack = vec![0; STACK_SIZE].leak();
@@ -226,6 +330,16 @@ fn init_stage_child(args: SetupArgs) -> ! {
 	let _ = prctl::set_name("runh:INIT");
 	debug!("Welcome to the container! This is PID {}", Pid::this());
 
+	if let Some(initial_cpus) = args
+		.config
+		.scheduling
+		.exec_cpu_affinity
+		.as_ref()
+		.and_then(|affinity| affinity.initial.as_ref())
+	{
+		scheduling::set_cpu_affinity(initial_cpus);
+	}
+
 	// Set SID, UID, GID
 	let _ = nix::unistd::setsid().expect("Could not set session ID");
 	nix::unistd::setuid(Uid::from_raw(0)).expect("Could not set user ID");
@@ -233,11 +347,24 @@ fn init_stage_child(args: SetupArgs) -> ! {
 
 	// TODO: Call setgroups if !is_rootless_euid && is_setgroup (?)
 
+	// runh create moves us into the container cgroup once it knows our PID. This has to
+	// happen before the cgroup namespace is created, so its root is the container cgroup.
+	let mut sig_buffer = [0u8];
+	nix::unistd::read(args.init_pipe, &mut sig_buffer).expect("Could not read from init pipe!");
+	if sig_buffer[0] != crate::consts::CREATE_ACK_CGROUP_SETUP {
+		panic!(
+			"Received invalid signal from runh create! Expected {:x}, got {:x}",
+			crate::consts::CREATE_ACK_CGROUP_SETUP,
+			sig_buffer[0]
+		);
+	}
+
 	// Unshare Cgroup namespace if requested to
 	if args.config.cloneflags.contains(CloneFlags::CLONE_NEWCGROUP) {
-		// TODO: Synchronize with runh create for cgroup setup
-		nix::sched::unshare(CloneFlags::CLONE_NEWCGROUP)
-			.expect("could not unshare cgroups namespace!");
+		traced_syscall("unshare", format_args!("CLONE_NEWCGROUP"), || {
+			nix::sched::unshare(CloneFlags::CLONE_NEWCGROUP)
+		})
+		.expect("could not unshare cgroups namespace!");
 	}
 
 	// In runc's case, this is the point where control is transferred back to the go runtime
@@ -259,6 +386,51 @@ fn init_stage_child(args: SetupArgs) -> ! {
 		None
 	};
 
+	let preserve_fds: RawFd = env::var("RUNH_PRESERVE_FDS")
+		.unwrap_or_else(|_| "0".to_string())
+		.parse()
+		.expect("RUNH_PRESERVE_FDS was not an integer!");
+
+	let ignore_apparmor = env::var("RUNH_APPARMOR").map_or(false, |mode| mode == "ignore");
+
+	let restart_policy: Option<reaper::RestartPolicy> =
+		env::var("RUNH_RESTART").ok().map(|policy| {
+			policy
+				.parse()
+				.expect("RUNH_RESTART was not a valid restart policy!")
+		});
+
+	// Restarting the entrypoint needs a supervisor inside the container, so a restart
+	// policy always implies the init wrapper
+	let use_init_wrapper = restart_policy.is_some()
+		|| env::var("RUNH_INIT_WRAPPER").map_or(false, |wrapper| wrapper == "true");
+
+	let exit_status_file = env::var("RUNH_EXIT_STATUS_FD").ok().map(|fd| {
+		let fd: RawFd = fd.parse().expect("RUNH_EXIT_STATUS_FD was not an integer!");
+		unsafe { File::from_raw_fd(fd) }
+	});
+
+	let no_pivot = env::var("RUNH_NO_PIVOT").map_or(false, |no_pivot| no_pivot == "true");
+
+	let no_dev_setup = env::var("RUNH_NO_DEV_SETUP").map_or(false, |no_dev| no_dev == "true");
+
+	let no_proc = env::var("RUNH_NO_PROC").map_or(false, |no_proc| no_proc == "true");
+
+	let no_sys = env::var("RUNH_NO_SYS").map_or(false, |no_sys| no_sys == "true");
+
+	let rootless = env::var("RUNH_ROOTLESS").map_or(false, |rootless| rootless == "true");
+
+	let setup_loopback: Option<bool> = env::var("RUNH_SETUP_LOOPBACK")
+		.ok()
+		.map(|setup_loopback| setup_loopback == "true");
+
+	let expose_config = env::var("RUNH_EXPOSE_CONFIG").ok().map(|destination| {
+		(
+			PathBuf::from(env::var("RUNH_CONFIG_PATH").expect("RUNH_CONFIG_PATH not set!")),
+			PathBuf::from(destination),
+		)
+	});
+
 	let mut console_fd = 0;
 
 	if args
@@ -276,6 +448,17 @@ fn init_stage_child(args: SetupArgs) -> ! {
 			.expect("RUNH_CONSOLE was not an integer!");
 	}
 
+	// runh init inherits the host environment of runh create, so allowed host variables
+	// have to be saved before the environment is replaced
+	let host_env: Vec<(String, String)> = env::var("RUNH_ENV_FROM_HOST")
+		.map(|keys| {
+			keys.split(',')
+				.filter(|key| !key.is_empty())
+				.filter_map(|key| env::var(key).ok().map(|value| (key.to_string(), value)))
+				.collect()
+		})
+		.unwrap_or_default();
+
 	unsafe {
 		libc::clearenv();
 	}
@@ -285,8 +468,7 @@ fn init_stage_child(args: SetupArgs) -> ! {
 		if let Some(env) = &process.env() {
 			debug!("Load environment variables from config");
 			for var in env {
-				let (name, value) = var
-					.split_once('=')
+				let (name, value) = split_env_entry(var)
 					.unwrap_or_else(|| panic!("Could not parse environment variable: {}", var));
 				if !name.is_empty() {
 					std::env::set_var(name, value);
@@ -295,6 +477,17 @@ fn init_stage_child(args: SetupArgs) -> ! {
 		}
 	}
 
+	// Variables from the config take precedence over the host environment
+	for (name, value) in host_env {
+		if env::var_os(&name).is_none() {
+			debug!(
+				"Passing host environment variable {} to the container",
+				name
+			);
+			env::set_var(name, value);
+		}
+	}
+
 	//TODO: Create new session keyring if requested
 	//TODO: Setup network and routing
 	let mut setup_network = false;
@@ -319,7 +512,20 @@ fn init_stage_child(args: SetupArgs) -> ! {
 	}
 	let tokio_runtime = tokio::runtime::Runtime::new().expect("Could not spawn new tokio runtime!");
 
-	if setup_network {
+	// Network hooks (e.g. CNI plugins) configure lo together with the other interfaces
+	let has_network_hooks = args.config.spec.hooks().as_ref().map_or(false, |hooks| {
+		hooks
+			.prestart()
+			.as_ref()
+			.map_or(false, |hooks| !hooks.is_empty())
+			|| hooks
+				.create_runtime()
+				.as_ref()
+				.map_or(false, |hooks| !hooks.is_empty())
+	});
+	if setup_network && !setup_loopback.unwrap_or(!has_network_hooks) {
+		debug!("Leaving the loopback interface of the new network namespace down");
+	} else if setup_network {
 		tokio_runtime
 			.block_on(network::set_lo_up())
 			.expect("Could not setup network lo interface!");
@@ -332,23 +538,42 @@ fn init_stage_child(args: SetupArgs) -> ! {
 	rootfs::mount_rootfs(&args.config.spec, &rootfs_path);
 
 	//Setup mounts and devices
-	let setup_dev = if let Some(mounts) = args.config.spec.mounts() {
-		mounts::configure_mounts(
-			mounts,
-			&rootfs_path,
-			&bundle_rootfs_path,
-			args.config.spec.linux().as_ref().unwrap().mount_label(),
-		)
-	} else {
-		true
-	};
+	let bind_host_dev = !no_dev_setup && devices::wants_host_dev(&args.config.spec);
+	let setup_dev = mounts::configure_mounts(
+		args.config.spec.mounts().as_deref().unwrap_or_default(),
+		&rootfs_path,
+		&bundle_rootfs_path,
+		args.config.spec.linux().as_ref().unwrap().mount_label(),
+		linux_spec
+			.namespaces()
+			.as_ref()
+			.map(|namespaces| {
+				namespaces
+					.iter()
+					.any(|ns| ns.typ() == runtime::LinuxNamespaceType::Pid)
+			})
+			.unwrap_or(false),
+		args.config.cloneflags.contains(CloneFlags::CLONE_NEWCGROUP),
+		&args.config.uid_mappings,
+		&args.config.gid_mappings,
+		rootless,
+		bind_host_dev,
+		no_proc,
+		no_sys,
+	);
 
-	if setup_dev {
-		devices::create_devices(linux_spec.devices(), &rootfs_path);
+	// runh does not inject any mounts, but populates /dev unless it is bind-mounted. The host
+	// /dev already contains ptmx and the symlinks.
+	if setup_dev && !no_dev_setup {
+		devices::create_devices(linux_spec.devices(), &rootfs_path, rootless);
 		devices::setup_ptmx(&rootfs_path);
 		devices::setup_dev_symlinks(&rootfs_path);
 	}
 
+	if let Some((config, destination)) = &expose_config {
+		mounts::mount_config(config, destination, &rootfs_path);
+	}
+
 	if args.config.is_hermit_container {
 		devices::mount_hermit_devices(&rootfs_path);
 		devices::create_tun(
@@ -358,7 +583,7 @@ fn init_stage_child(args: SetupArgs) -> ! {
 		);
 	}
 
-	//Run pre-start hooks
+	//Run pre-start and createRuntime hooks, which have to happen before the pivot_root
 	debug!("Signalling parent to run pre-start hooks");
 	let mut init_pipe = unsafe { File::from_raw_fd(args.init_pipe) };
 	init_pipe
@@ -448,10 +673,11 @@ fn init_stage_child(args: SetupArgs) -> ! {
 		)
 	});
 
-	//TODO: Run create hooks
+	//TODO: Run createContainer hooks
 
 	if args.config.cloneflags.contains(CloneFlags::CLONE_NEWNS) {
-		rootfs::pivot_root(&rootfs_path);
+		rootfs::pivot_root(&rootfs_path, no_pivot)
+			.unwrap_or_else(|err| panic!("Could not enter the rootfs: {}", err));
 	} else {
 		nix::unistd::chroot(".").expect("Could not chroot into current directory!");
 		nix::unistd::chdir("/").expect("Could not chdir to / after chroot!");
@@ -484,9 +710,14 @@ fn init_stage_child(args: SetupArgs) -> ! {
 			.unwrap()
 			.console_size()
 			.as_ref()
-			.map(|b| nix::pty::Winsize {
-				ws_row: b.height() as u16,
-				ws_col: b.width() as u16,
+			// A zero-sized console would leave programs without usable dimensions, so the
+			// default window size of the pty is kept instead
+			.filter(|size| size.height() > 0 && size.width() > 0)
+			.map(|size| nix::pty::Winsize {
+				ws_row: u16::try_from(size.height())
+					.unwrap_or_else(|_| panic!("Console height {} is too large!", size.height())),
+				ws_col: u16::try_from(size.width())
+					.unwrap_or_else(|_| panic!("Console width {} is too large!", size.width())),
 				ws_xpixel: 0,
 				ws_ypixel: 0,
 			});
@@ -500,7 +731,22 @@ fn init_stage_child(args: SetupArgs) -> ! {
 
 		if let Some(root) = args.config.spec.root() {
 			if root.readonly().unwrap_or(false) {
-				rootfs::set_rootfs_read_only();
+				let writable_mounts: Vec<PathBuf> = args
+					.config
+					.spec
+					.mounts()
+					.iter()
+					.flatten()
+					.filter(|mount| {
+						!mount
+							.options()
+							.iter()
+							.flatten()
+							.any(|option| option == "ro")
+					})
+					.map(|mount| mount.destination().clone())
+					.collect();
+				rootfs::set_rootfs_read_only(&writable_mounts);
 			}
 		}
 		let _ = nix::sys::stat::umask(nix::sys::stat::Mode::from_bits(0o022).unwrap());
@@ -511,25 +757,29 @@ fn init_stage_child(args: SetupArgs) -> ! {
 		nix::unistd::sethostname(hostname).expect("Could not set hostname!");
 	}
 
-	//TODO: Apply apparmor profile
-	//TODO: Write sysctl keys
-	if let Some(sysctl) = args.config.spec.linux().as_ref().unwrap().sysctl().as_ref() {
-		for (key, value) in sysctl {
-			let key_path = key.replace('.', "/");
-			let full_path = PathBuf::from("/proc/sys").join(key_path);
-			let mut sysctl_file = OpenOptions::new()
-				.mode(0o644)
-				.create(true)
-				.write(true)
-				.open(&full_path)
-				.unwrap_or_else(|_| panic!("Could not create sysctl entry at {:?}", full_path));
-			sysctl_file.write_all(value.as_bytes()).unwrap_or_else(|_| {
-				panic!(
-					"Could not write value {} to sysctl entry at {:?}",
-					value, full_path
-				)
-			});
-		}
+	if let Some(profile) = args
+		.config
+		.spec
+		.process()
+		.as_ref()
+		.unwrap()
+		.apparmor_profile()
+	{
+		apparmor::apply_profile(profile, ignore_apparmor);
+	}
+
+	if let Some(label) = args.config.spec.process().as_ref().unwrap().selinux_label() {
+		selinux::set_exec_label(label);
+	}
+
+	if let Some(sysctl) = linux_spec.sysctl().as_ref() {
+		let namespaces: Vec<runtime::LinuxNamespaceType> = linux_spec
+			.namespaces()
+			.iter()
+			.flatten()
+			.map(|ns| ns.typ())
+			.collect();
+		sysctl::apply_sysctls(sysctl, &namespaces);
 	}
 
 	//TODO: Manage readonly and mask paths
@@ -615,6 +865,32 @@ fn init_stage_child(args: SetupArgs) -> ! {
 	info!("Found args-executable: {:?}", exec_path_abs);
 	info!("Running command {}", exec_args.join(" "));
 
+	if let Err(err) = check_arg_list_size(&exec_args) {
+		panic!("Could not execute the container process: {}", err);
+	}
+
+	fds::preserve_fds(preserve_fds);
+	fds::set_cloexec_from(fds::FIRST_EXTRA_FD + preserve_fds);
+
+	if let Some(personality) = &args.config.personality {
+		personality::set_personality(personality);
+	}
+	if let Some(scheduler) = &args.config.scheduling.scheduler {
+		scheduling::set_scheduler(scheduler);
+	}
+	if let Some(io_priority) = &args.config.scheduling.io_priority {
+		scheduling::set_io_priority(io_priority);
+	}
+	if let Some(final_cpus) = args
+		.config
+		.scheduling
+		.exec_cpu_affinity
+		.as_ref()
+		.and_then(|affinity| affinity.final_cpus.as_ref())
+	{
+		scheduling::set_cpu_affinity(final_cpus);
+	}
+
 	//Tell runh create we are ready to execv
 	init_pipe
 		.write_all(&[crate::consts::INIT_READY_TO_EXECV])
@@ -625,9 +901,16 @@ fn init_stage_child(args: SetupArgs) -> ! {
 	//Close log pipe. All log calls after this should fail due to the log file being closed.
 	if let Some(log_pipe_fd) = log_pipe_fd {
 		debug!("Closing log pipe...");
-		nix::unistd::close(log_pipe_fd).expect("Could not close log pipe fd!");
+		// The logger has already warned about an invalid log pipe, so EBADF is expected here
+		if let Err(err) = nix::unistd::close(log_pipe_fd) {
+			if err != nix::errno::Errno::EBADF {
+				panic!("Could not close log pipe fd: {}", err);
+			}
+		}
 	}
 
+	// Opening the exec fifo for writing blocks until `runh start` opens it for reading.
+	// This is the point where the container waits between create and start.
 	let mut exec_fifo = OpenOptions::new()
 		.custom_flags(libc::O_CLOEXEC)
 		.read(false)
@@ -636,7 +919,6 @@ fn init_stage_child(args: SetupArgs) -> ! {
 		.expect("Could not open exec fifo!");
 
 	write!(exec_fifo, "\0").expect("Could not write to exec fifo!");
-	drop(exec_fifo);
 
 	let mut cmd = std::process::Command::new(exec_path_abs);
 	cmd.arg0(exec_args.get(0).unwrap());
@@ -644,8 +926,65 @@ fn init_stage_child(args: SetupArgs) -> ! {
 		cmd.args(exec_args.get(1..).unwrap());
 	}
 	cmd.envs(std::env::vars());
+
+	if use_init_wrapper {
+		// The wrapper stays alive, so it cannot keep the fifo open until the exec
+		drop(exec_fifo);
+		reaper::run_as_init(cmd, restart_policy, exit_status_file);
+	}
+
+	// The fifo is close-on-exec, so `runh start` sees EOF once the entrypoint runs.
+	// If the exec fails instead, the reason is sent through the fifo.
 	let error = cmd.exec();
 
 	//This point should not be reached on successful exec
+	let _ = write!(exec_fifo, "exec failed: {}", error);
+	drop(exec_fifo);
 	panic!("exec failed with error {}", error)
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn env_values_are_literal() {
+		assert_eq!(split_env_entry("HOME=$HOME"), Some(("HOME", "$HOME")));
+		assert_eq!(
+			split_env_entry("PATH=${PATH}:/opt/bin"),
+			Some(("PATH", "${PATH}:/opt/bin"))
+		);
+		assert_eq!(split_env_entry("OPTS=a=1 b=2"), Some(("OPTS", "a=1 b=2")));
+		assert_eq!(
+			split_env_entry("GREETING=  hello  world "),
+			Some(("GREETING", "  hello  world "))
+		);
+		assert_eq!(
+			split_env_entry("QUOTED=\"$(id)\""),
+			Some(("QUOTED", "\"$(id)\""))
+		);
+	}
+
+	#[test]
+	fn env_entries_without_value() {
+		assert_eq!(split_env_entry("EMPTY="), Some(("EMPTY", "")));
+		assert_eq!(split_env_entry("=value"), Some(("", "value")));
+		assert_eq!(split_env_entry("NO_VALUE"), None);
+	}
+
+	#[test]
+	fn arg_list_size_is_checked() {
+		assert_eq!(check_arg_list_size(&["/bin/true".to_string()]), Ok(()));
+
+		let arg_max = nix::unistd::sysconf(nix::unistd::SysconfVar::ARG_MAX)
+			.unwrap()
+			.unwrap() as usize;
+		match check_arg_list_size(&["/bin/true".to_string(), "x".repeat(arg_max)]) {
+			Err(ExecError::ArgListTooLong { size, limit }) => {
+				assert!(size > limit);
+				assert_eq!(limit, arg_max);
+			}
+			result => panic!("Expected ArgListTooLong, got {:?}", result),
+		}
+	}
+}
@@ -0,0 +1,82 @@
+use oci_spec::runtime::Spec;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+
+use crate::format::{self, OutputFormat};
+use crate::mounts;
+use crate::rootfs;
+use crate::spec;
+
+/// A mount of the config as runh init would perform it
+#[derive(Serialize)]
+struct PlannedMount {
+	destination: PathBuf,
+	/// The destination after resolving symlinks in the rootfs
+	resolved: PathBuf,
+	#[serde(rename = "type")]
+	typ: String,
+	source: PathBuf,
+	flags: String,
+	propagation: String,
+	data: Option<String>,
+	/// Destinations that resolve outside of the rootfs are not mounted at all
+	skipped: bool,
+}
+
+const MOUNT_COLUMNS: [(&str, &str); 7] = [
+	("DESTINATION", "resolved"),
+	("TYPE", "type"),
+	("SOURCE", "source"),
+	("FLAGS", "flags"),
+	("PROPAGATION", "propagation"),
+	("DATA", "data"),
+	("SKIPPED", "skipped"),
+];
+
+/// Prints the mounts of a bundle in the order runh init performs them, which is the order of
+/// the config, with their resolved destinations, mount flags and data strings. Nothing is
+/// mounted, so this can be used to debug mount problems without creating a container.
+pub fn print_mount_plan(bundle: &str, output_format: OutputFormat) {
+	let bundle = std::fs::canonicalize(bundle)
+		.unwrap_or_else(|err| panic!("Could not resolve bundle {}: {}", bundle, err));
+	let config = spec::load_config(&bundle.join("config.json"))
+		.unwrap_or_else(|err| panic!("Could not load config of bundle {:?}: {}", bundle, err));
+	let spec: Spec = serde_json::from_value(config).expect("Unable to parse config!");
+
+	let rootfs = rootfs::resolve_root_path(
+		spec.root()
+			.as_ref()
+			.expect("Container spec does not contain a root!")
+			.path(),
+		&bundle,
+	)
+	.unwrap_or_else(|err| panic!("Could not resolve the rootfs of {:?}: {}", bundle, err));
+
+	let planned_mounts: Vec<PlannedMount> = spec
+		.mounts()
+		.iter()
+		.flatten()
+		.map(|mount| {
+			let options = mounts::resolve_mount_options(mount);
+			let mut source = PathBuf::from(mount.source().clone().unwrap_or_default());
+			if !source.is_absolute() {
+				source = rootfs.join(source);
+			}
+			let resolved = rootfs::resolve_in_rootfs(mount.destination(), &rootfs);
+			PlannedMount {
+				destination: mount.destination().clone(),
+				skipped: !resolved.starts_with(&rootfs),
+				resolved: resolved
+					.strip_prefix(&rootfs)
+					.map_or(resolved.clone(), |relative| Path::new("/").join(relative)),
+				typ: mount.typ().clone().unwrap_or_default(),
+				source,
+				flags: format!("{:?}", options.mount_flags),
+				propagation: format!("{:?}", options.propagation_flags),
+				data: options.data,
+			}
+		})
+		.collect();
+
+	format::print_output(&planned_mounts, output_format, &MOUNT_COLUMNS);
+}
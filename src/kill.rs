@@ -1,12 +1,98 @@
+use nix::sys::signal::Signal;
 use nix::unistd::Pid;
-use std::{convert::TryFrom, path::PathBuf, str::FromStr};
+use std::{
+	convert::TryFrom,
+	os::unix::fs::MetadataExt,
+	path::{Path, PathBuf},
+	str::FromStr,
+};
 
-use crate::state;
+use crate::cgroups::{self, CgroupVersion};
+use crate::container::OCIContainer;
+use crate::state::{self, ContainerStatus};
+use crate::syscall::traced_syscall;
 
-pub fn kill_container(project_dir: PathBuf, id: Option<&str>, sig: Option<&str>, all: bool) {
-	let container_state = state::get_container_state(project_dir, id.unwrap())
+/// Parses a signal given either as number or as name. Names are matched
+/// case-insensitively and with or without the `SIG` prefix, so `15`, `term`,
+/// `Term` and `SIGTERM` all resolve to the same signal.
+pub fn parse_signal(sig: &str) -> Signal {
+	try_parse_signal(sig).unwrap_or_else(|| panic!("Could not parse signal {}", sig))
+}
+
+/// Like `parse_signal`, but returns `None` for unknown signals
+pub fn try_parse_signal(sig: &str) -> Option<Signal> {
+	if let Ok(sig_nr) = sig.parse::<i32>() {
+		Signal::try_from(sig_nr).ok()
+	} else {
+		let signal_upper = sig.to_ascii_uppercase();
+		let signal_str = if !signal_upper.starts_with("SIG") {
+			format!("SIG{}", signal_upper)
+		} else {
+			signal_upper
+		};
+		Signal::from_str(signal_str.as_str()).ok()
+	}
+}
+
+fn get_pid_namespace(pid: i32) -> std::fs::Metadata {
+	std::fs::metadata(format!("/proc/{}/ns/pid", pid))
+		.unwrap_or_else(|err| panic!("Could not query PID namespace of process {}: {}", pid, err))
+}
+
+/// Makes sure a process belongs to the container before it gets signalled. A process belongs to
+/// the container if it is in the cgroup of the container or one below it. Without a cgroup
+/// (i.e. on cgroup v1), membership is determined by the PID namespace instead, which has to be
+/// a separate one for the check to mean anything.
+fn verify_container_process(container_dir: &Path, id: &str, init_pid: i32, pid: i32) {
+	let container = OCIContainer::load(container_dir)
+		.unwrap_or_else(|| panic!("Could not load container {}!", id));
+	let cgroup_path = cgroups::get_container_cgroup_path(container_dir, container.spec(), id);
+	if cgroups::get_cgroup_version() == CgroupVersion::V2 && cgroup_path.exists() {
+		let process_cgroup = cgroups::get_process_cgroup(pid)
+			.unwrap_or_else(|| panic!("Could not determine the cgroup of process {}!", pid));
+		if !process_cgroup.starts_with(&cgroup_path) {
+			panic!(
+				"Process {} does not belong to the container! It is in cgroup {:?} instead of {:?}",
+				pid, process_cgroup, cgroup_path
+			);
+		}
+		return;
+	}
+
+	let init_ns = get_pid_namespace(init_pid);
+	let own_ns = get_pid_namespace(std::process::id() as i32);
+	if init_ns.dev() == own_ns.dev() && init_ns.ino() == own_ns.ino() {
+		panic!(
+			"Refusing to signal process {} as the container shares the PID namespace of the host!",
+			pid
+		);
+	}
+
+	let process_ns = get_pid_namespace(pid);
+	if process_ns.dev() != init_ns.dev() || process_ns.ino() != init_ns.ino() {
+		panic!("Process {} does not belong to the container!", pid);
+	}
+}
+
+/// Sends a signal to a single process
+pub fn send_signal(pid: i32, signal: Signal) -> nix::Result<()> {
+	traced_syscall("kill", format_args!("{}, {}", pid, signal), || {
+		nix::sys::signal::kill(Pid::from_raw(pid), signal)
+	})
+}
+
+pub fn kill_container(
+	project_dir: PathBuf,
+	id: Option<&str>,
+	sig: Option<&str>,
+	all: bool,
+	target_pid: Option<i32>,
+) {
+	let container_state = state::get_container_state(project_dir.clone(), id.unwrap())
 		.unwrap_or_else(|| panic!("Could not query state for container {}", id.unwrap()));
-	if container_state.status != "created" && container_state.status != "running" {
+	if container_state.status != ContainerStatus::Created
+		&& container_state.status != ContainerStatus::Running
+	{
 		panic!("Cannot send signals to non-running containers!")
 	}
 
@@ -14,21 +100,21 @@ pub fn kill_container(project_dir: PathBuf, id: Option<&str>, sig: Option<&str>,
 		unimplemented!("Sending signals to all container processes is currently unimplemented!");
 	}
 
-	let pid = container_state.pid.unwrap();
-	let signal = if let Ok(sig_nr) = sig.unwrap().parse::<i32>() {
-		nix::sys::signal::Signal::try_from(sig_nr)
-			.unwrap_or_else(|_| panic!("Could not parse signal number {}", sig.unwrap()))
+	let init_pid = container_state.pid.unwrap();
+	let pid = if let Some(target_pid) = target_pid {
+		verify_container_process(
+			&project_dir.join(id.unwrap()),
+			id.unwrap(),
+			init_pid,
+			target_pid,
+		);
+		target_pid
 	} else {
-		let signal_str = if !sig.unwrap().starts_with("SIG") {
-			format!("SIG{}", sig.unwrap())
-		} else {
-			sig.unwrap().to_owned()
-		};
-		nix::sys::signal::Signal::from_str(signal_str.as_str())
-			.unwrap_or_else(|_| panic!("Could not parse signal string {}", sig.unwrap()))
+		init_pid
 	};
+	let signal = parse_signal(sig.unwrap());
 
-	nix::sys::signal::kill(Pid::from_raw(pid), signal).unwrap_or_else(|_| {
+	send_signal(pid, signal).unwrap_or_else(|_| {
 		panic!(
 			"Could not send signal {} to container process ID  {}!",
 			sig.unwrap(),
@@ -36,3 +122,50 @@ pub fn kill_container(project_dir: PathBuf, id: Option<&str>, sig: Option<&str>,
 		)
 	});
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn signal_forms() {
+		for (sig, expected) in [
+			("15", Signal::SIGTERM),
+			("term", Signal::SIGTERM),
+			("Term", Signal::SIGTERM),
+			("TERM", Signal::SIGTERM),
+			("SIGTERM", Signal::SIGTERM),
+			("sigterm", Signal::SIGTERM),
+			("SigTerm", Signal::SIGTERM),
+			("9", Signal::SIGKILL),
+			("kill", Signal::SIGKILL),
+			("SIGHUP", Signal::SIGHUP),
+			("usr1", Signal::SIGUSR1),
+			("winch", Signal::SIGWINCH),
+		] {
+			assert_eq!(try_parse_signal(sig), Some(expected), "{}", sig);
+		}
+	}
+
+	#[test]
+	fn unknown_signals() {
+		for sig in [
+			"",
+			"0",
+			"-1",
+			"1000",
+			"TERMINATE",
+			"SIG",
+			"SIGSIGTERM",
+			"15abc",
+		] {
+			assert_eq!(try_parse_signal(sig), None, "{}", sig);
+		}
+	}
+
+	#[test]
+	#[should_panic(expected = "Could not parse signal foo")]
+	fn parse_signal_panics_on_unknown_signal() {
+		parse_signal("foo");
+	}
+}
@@ -1,4 +1,7 @@
 use crate::container::OCIContainer;
+use crate::format::{self, OutputFormat};
+use crate::state::{self, ContainerStatus};
+use serde::Serialize;
 use std::convert::TryInto;
 use std::ffi::CStr;
 use std::fs::OpenOptions;
@@ -37,19 +40,34 @@ fn get_unix_username(uid: u32) -> Option<String> {
 	}
 }
 
-pub fn list_containers(project_dir: PathBuf) {
-	println!(
-		"{0: <12} {1: <12} {2: <12} {3: <12} {4: <12} {5: <12}",
-		"ID", "PID", "STATUS", "BUNDLE", "CREATED", "OWNER"
-	);
+/// A row of `runh list`. The fields are named like their counterparts of the container state.
+#[derive(Serialize, Debug)]
+struct ContainerListEntry {
+	id: String,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pid: Option<i32>,
+	status: ContainerStatus,
+	bundle: String,
+	created: String,
+	owner: String,
+}
+
+const LIST_COLUMNS: [(&str, &str); 6] = [
+	("ID", "id"),
+	("PID", "pid"),
+	("STATUS", "status"),
+	("BUNDLE", "bundle"),
+	("CREATED", "created"),
+	("OWNER", "owner"),
+];
 
+pub fn list_containers(project_dir: PathBuf, format: OutputFormat) {
+	let mut entries = Vec::new();
 	if project_dir.is_dir() {
-		for entry in std::fs::read_dir(project_dir).unwrap() {
+		for entry in std::fs::read_dir(&project_dir).unwrap() {
 			let dir = entry.unwrap();
 			let mut fname = dir.path().clone();
 			fname.push("container.json");
-			let mut uts = dir.path();
-			uts.push("uts");
 
 			if let Ok(mut file) = OpenOptions::new().read(true).write(false).open(fname) {
 				let mut contents = String::new();
@@ -64,23 +82,28 @@ pub fn list_containers(project_dir: PathBuf) {
 					OffsetDateTime::UNIX_EPOCH
 				};
 				let user = get_unix_username(metadata.uid()).unwrap_or_else(|| "".to_string());
-				let status = if uts.exists() { "RUNNING" } else { "CREATED" };
 				let format_desc =
 					format_description::parse("[year]-[month]-[day] [hour]:[minute]:[second]")
 						.unwrap();
 
-				if let Ok(container) = serde_json::from_str::<OCIContainer>(&contents) {
-					println!(
-						"{0: <12} {1: <12} {2: <12} {3: <12} {4: <12} {5: <12}",
-						dir.file_name().into_string().unwrap(),
-						"",
-						status,
-						container.bundle(),
-						created.format(&format_desc).unwrap(),
-						user
-					);
+				if serde_json::from_str::<OCIContainer>(&contents).is_err() {
+					continue;
+				}
+				let id = dir.file_name().into_string().unwrap();
+				if let Some(state) = state::get_container_state(project_dir.clone(), &id) {
+					entries.push(ContainerListEntry {
+						id,
+						pid: state.pid,
+						status: state.status,
+						bundle: state.bundle,
+						created: created.format(&format_desc).unwrap(),
+						owner: user,
+					});
 				}
 			}
 		}
 	}
+
+	entries.sort_by(|a, b| a.id.cmp(&b.id));
+	format::print_output(&entries, format, &LIST_COLUMNS);
 }
@@ -1,19 +1,76 @@
+use libflate::gzip::Encoder;
 use log::{set_boxed_logger, set_max_level, Level, LevelFilter, Metadata, Record};
+use nix::fcntl::{fcntl, FcntlArg, OFlag};
 use serde::Deserialize;
 use serde::Serialize;
 use serde_json::to_string;
+use std::ffi::OsString;
 use std::fs::File;
 use std::fs::OpenOptions;
-use std::io::Write;
-use std::os::unix::prelude::FromRawFd;
-use std::path::PathBuf;
+use std::io::{self, Write};
+use std::os::unix::prelude::{FromRawFd, RawFd};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicU8, Ordering};
+use std::sync::mpsc::{sync_channel, SyncSender, TrySendError};
 use std::sync::Mutex;
+use std::thread::JoinHandle;
 use time::format_description::well_known::Rfc3339;
 use time::OffsetDateTime;
 
+#[derive(Clone, Copy)]
 enum LogFormat {
-	Text,
-	Json,
+	Text = 0,
+	Json = 1,
+}
+
+/// Format of the installed logger. `log` only allows installing a logger once, so
+/// the format lives outside of it to be changeable with `set_format`.
+static LOG_FORMAT: AtomicU8 = AtomicU8::new(LogFormat::Text as u8);
+
+fn current_format() -> LogFormat {
+	match LOG_FORMAT.load(Ordering::Relaxed) {
+		1 => LogFormat::Json,
+		_ => LogFormat::Text,
+	}
+}
+
+/// Switches the format of all following log lines to `json` or `text` (for any other value)
+pub fn set_format(log_format: &str) {
+	let log_format = match log_format {
+		"json" => LogFormat::Json,
+		_ => LogFormat::Text,
+	};
+	LOG_FORMAT.store(log_format as u8, Ordering::Relaxed);
+}
+
+/// Set if stdout is reserved for the result of the command, see `use_stderr`
+static LOG_TO_STDERR: AtomicBool = AtomicBool::new(false);
+
+/// Makes log lines that are not written to a log file go to stderr instead of stdout, so
+/// that stdout only carries the machine-readable result of the command.
+pub fn use_stderr() {
+	LOG_TO_STDERR.store(true, Ordering::Relaxed);
+}
+
+/// Prints a log line that could not be written to a log file
+fn print_line(line: &str) {
+	if LOG_TO_STDERR.load(Ordering::Relaxed) {
+		eprintln!("{}", line);
+	} else {
+		println!("{}", line);
+	}
+}
+
+/// Changes the maximum level of all following log lines. Unknown levels select `info`.
+pub fn set_level(log_level: &str) {
+	set_max_level(match log_level {
+		"error" => LevelFilter::Error,
+		"debug" => LevelFilter::Debug,
+		"off" => LevelFilter::Off,
+		"trace" => LevelFilter::Trace,
+		"warn" => LevelFilter::Warn,
+		_ => LevelFilter::Info,
+	});
 }
 
 #[derive(Serialize, Deserialize)]
@@ -23,49 +80,196 @@ pub struct LogEntry {
 	pub time: String,
 }
 
+/// Number of rotated backups of a debug log that are kept (`log-<time>.json.1` to `.5`)
+const DEBUG_LOG_BACKUPS: u32 = 5;
+
+/// Appends `suffix` to the file name of `path`
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+	let mut name = OsString::from(path);
+	name.push(suffix);
+	PathBuf::from(name)
+}
+
+fn write_compressed(path: &Path, tmp_path: &Path) -> io::Result<()> {
+	let mut input = File::open(path)?;
+	let mut encoder = Encoder::new(File::create(tmp_path)?)?;
+	io::copy(&mut input, &mut encoder)?;
+	encoder.finish().into_result()?;
+	std::fs::rename(tmp_path, with_suffix(path, ".gz"))?;
+	std::fs::remove_file(path)
+}
+
+/// Gzip-compresses a rotated log to `<path>.gz` and removes the uncompressed file. The archive
+/// only appears under its final name once it is complete, so if the compression fails or runh
+/// exits in the middle of it, the uncompressed backup is kept instead.
+fn compress_log(path: &Path) {
+	let tmp_path = with_suffix(path, ".gz.tmp");
+	if let Err(err) = write_compressed(path, &tmp_path) {
+		let _ = std::fs::remove_file(&tmp_path);
+		print_line(&format!(
+			"ERROR in logger: Could not compress rotated log {:?}: {}",
+			path, err
+		));
+	}
+}
+
+/// The `--debug-log` file in the state root. Once it grows beyond `max_size`, it is rotated to
+/// `<path>.1` (shifting older backups up to `DEBUG_LOG_BACKUPS`) and, with `compress`,
+/// gzip-compressed by a background thread, so the logging mutex is only held for the renames.
+struct DebugLogFile {
+	path: PathBuf,
+	file: File,
+	written: u64,
+	max_size: Option<u64>,
+	compress: bool,
+	compression: Option<JoinHandle<()>>,
+}
+
+impl DebugLogFile {
+	fn create(path: PathBuf, max_size: Option<u64>, compress: bool) -> io::Result<Self> {
+		let file = OpenOptions::new().create(true).write(true).open(&path)?;
+		Ok(Self {
+			path,
+			written: file.metadata()?.len(),
+			file,
+			max_size,
+			compress,
+			compression: None,
+		})
+	}
+
+	/// Lines are never split between two files, so the rotation happens after a whole line
+	fn write_line(&mut self, line: &str) -> io::Result<()> {
+		writeln!(self.file, "{}", line)?;
+		self.written += line.len() as u64 + 1;
+		if self
+			.max_size
+			.map_or(false, |max_size| self.written >= max_size)
+		{
+			self.rotate()?;
+		}
+		Ok(())
+	}
+
+	fn rotate(&mut self) -> io::Result<()> {
+		// The running compression still reads `.1`, which is about to be renamed
+		self.finish_compression();
+
+		let backup = |n: u32, suffix: &str| with_suffix(&self.path, &format!(".{}{}", n, suffix));
+		for suffix in ["", ".gz"] {
+			let oldest = backup(DEBUG_LOG_BACKUPS, suffix);
+			if oldest.exists() {
+				std::fs::remove_file(oldest)?;
+			}
+		}
+		for n in (1..DEBUG_LOG_BACKUPS).rev() {
+			for suffix in ["", ".gz"] {
+				let from = backup(n, suffix);
+				if from.exists() {
+					std::fs::rename(from, backup(n + 1, suffix))?;
+				}
+			}
+		}
+		let rotated = backup(1, "");
+		std::fs::rename(&self.path, &rotated)?;
+
+		self.file = OpenOptions::new()
+			.create(true)
+			.write(true)
+			.truncate(true)
+			.open(&self.path)?;
+		self.written = 0;
+		if self.compress {
+			self.compression = Some(std::thread::spawn(move || compress_log(&rotated)));
+		}
+		Ok(())
+	}
+
+	/// Waits for the compression of the last rotated backup
+	fn finish_compression(&mut self) {
+		if let Some(compression) = self.compression.take() {
+			let _ = compression.join();
+		}
+	}
+}
+
 struct RunhLogger<W: Write + Send + 'static> {
 	log_file: Mutex<Option<W>>,
-	log_file_internal: Mutex<Option<W>>,
-	log_format: LogFormat,
+	log_file_internal: Mutex<Option<DebugLogFile>>,
+	max_msg_len: Option<usize>,
+}
+
+/// Cuts a message down to at most `max_len` bytes (plus a suffix noting the dropped bytes).
+/// The cut is moved back to the previous char boundary so the result stays valid UTF-8.
+fn truncate_message(msg: String, max_len: usize) -> String {
+	if msg.len() <= max_len {
+		return msg;
+	}
+	let mut end = max_len;
+	while !msg.is_char_boundary(end) {
+		end -= 1;
+	}
+	format!("{}...[truncated {} bytes]", &msg[..end], msg.len() - end)
+}
+
+/// Formats a log record. Returns the (possibly truncated) message itself
+/// and the full log line in the configured format.
+fn format_record(record: &Record, max_msg_len: Option<usize>) -> (String, String) {
+	let msg = format!("{}", record.args());
+	let msg = match max_msg_len {
+		Some(max_len) => truncate_message(msg, max_len),
+		None => msg,
+	};
+	let message = match current_format() {
+		LogFormat::Text => {
+			format!("[{}] {}", record.level(), msg)
+		}
+		LogFormat::Json => to_string(&LogEntry {
+			level: record.level().as_str().to_ascii_lowercase(),
+			msg: msg.clone(),
+			time: OffsetDateTime::now_utc().format(&Rfc3339).unwrap(),
+		})
+		.unwrap(),
+	};
+	(msg, message)
 }
 
 impl<W: Write + Send + 'static> log::Log for RunhLogger<W> {
-	fn enabled(&self, _metadata: &Metadata) -> bool {
-		true
+	fn enabled(&self, metadata: &Metadata) -> bool {
+		metadata.level() <= log::max_level()
 	}
 
 	fn log(&self, record: &Record) {
 		let mut file_lock = self.log_file.lock().unwrap();
 		if self.enabled(record.metadata()) {
-			let message = match self.log_format {
-				LogFormat::Text => {
-					format!("[{}] {}", record.level(), record.args())
-				}
-				LogFormat::Json => to_string(&LogEntry {
-					level: record.level().as_str().to_ascii_lowercase(),
-					msg: format!("{}", record.args()),
-					time: OffsetDateTime::now_utc().format(&Rfc3339).unwrap(),
-				})
-				.unwrap(),
-			};
+			let (msg, message) = format_record(record, self.max_msg_len);
 			if let Some(file) = &mut *file_lock {
 				if let Err(err) = writeln!(file, "{}", message) {
-					println!("ERROR in logger: {} Writing to stdout instead!", err);
+					print_line(&format!(
+						"ERROR in logger: {} Writing to stdout instead!",
+						err
+					));
 					self.print_level(record.level());
-					println!(" {}", record.args());
+					print_line(&format!(" {}", msg));
 				}
 			} else {
 				self.print_level(record.level());
-				println!(" {}", record.args());
+				print_line(&format!(" {}", msg));
 			}
 			let mut file_lock_backup = self.log_file_internal.lock().unwrap();
 			if let Some(file_backup) = &mut *file_lock_backup {
-				writeln!(file_backup, "{}", message).expect("Could not write to backup log file!");
+				file_backup
+					.write_line(&message)
+					.expect("Could not write to backup log file!");
 			}
 		}
 	}
 
-	fn flush(&self) {}
+	fn flush(&self) {
+		if let Some(file_backup) = &mut *self.log_file_internal.lock().unwrap() {
+			file_backup.finish_compression();
+		}
+	}
 }
 
 impl<W: Write + Send + 'static> RunhLogger<W> {
@@ -73,6 +277,11 @@ impl<W: Write + Send + 'static> RunhLogger<W> {
 	/// get its own color. This helper function
 	/// prints the log level with its associated color.
 	fn print_level(&self, level: Level) {
+		// The colors are only meant for an interactive terminal on stdout
+		if LOG_TO_STDERR.load(Ordering::Relaxed) {
+			eprint!("[{}]", level);
+			return;
+		}
 		match level {
 			Level::Info => {
 				green!("[{}]", level);
@@ -93,60 +302,253 @@ impl<W: Write + Send + 'static> RunhLogger<W> {
 	}
 }
 
-pub fn init(
-	project_dir: PathBuf,
-	log_path: Option<&str>,
-	log_format: Option<&str>,
-	log_level: Option<&str>,
-	internal_log: bool,
-) {
+/// Number of log lines the non-blocking logger buffers before it starts dropping lines
+const ASYNC_LOG_CAPACITY: usize = 1024;
+
+enum AsyncLogMessage {
+	Line(String),
+	Flush(SyncSender<()>),
+}
+
+/// Non-blocking logger backend. Log lines are passed to a dedicated writer thread through a
+/// bounded channel. If the writer cannot keep up, lines are dropped and counted instead of
+/// blocking the logging thread.
+struct AsyncRunhLogger {
+	sender: SyncSender<AsyncLogMessage>,
+	dropped: AtomicU64,
+	/// Wait for the writer thread on a full queue instead of dropping the line
+	block_on_full: bool,
+	max_msg_len: Option<usize>,
+}
+
+impl AsyncRunhLogger {
+	fn new<W: Write + Send + 'static>(
+		mut log_file: Option<W>,
+		mut log_file_internal: Option<DebugLogFile>,
+		max_msg_len: Option<usize>,
+		block_on_full: bool,
+	) -> Self {
+		let (sender, receiver) = sync_channel(ASYNC_LOG_CAPACITY);
+		std::thread::spawn(move || {
+			for log_message in receiver {
+				match log_message {
+					AsyncLogMessage::Line(line) => {
+						if let Some(file) = &mut log_file {
+							if let Err(err) = writeln!(file, "{}", line) {
+								print_line(&format!(
+									"ERROR in logger: {} Writing to stdout instead!",
+									err
+								));
+								print_line(&line);
+							}
+						} else {
+							print_line(&line);
+						}
+						if let Some(file_backup) = &mut log_file_internal {
+							file_backup
+								.write_line(&line)
+								.expect("Could not write to backup log file!");
+						}
+					}
+					AsyncLogMessage::Flush(done) => {
+						if let Some(file_backup) = &mut log_file_internal {
+							file_backup.finish_compression();
+						}
+						let _ = done.send(());
+					}
+				}
+			}
+		});
+
+		Self {
+			sender,
+			dropped: AtomicU64::new(0),
+			block_on_full,
+			max_msg_len,
+		}
+	}
+}
+
+impl log::Log for AsyncRunhLogger {
+	fn enabled(&self, metadata: &Metadata) -> bool {
+		metadata.level() <= log::max_level()
+	}
+
+	fn log(&self, record: &Record) {
+		if self.enabled(record.metadata()) {
+			let dropped = self.dropped.swap(0, Ordering::Relaxed);
+			if dropped > 0 {
+				let note = format!("[WARN] Logger dropped {} lines", dropped);
+				if self.sender.try_send(AsyncLogMessage::Line(note)).is_err() {
+					self.dropped.fetch_add(dropped, Ordering::Relaxed);
+				}
+			}
+
+			let (_, message) = format_record(record, self.max_msg_len);
+			if self.block_on_full {
+				// Only fails if the writer thread is gone, in which case nothing can be logged anymore
+				let _ = self.sender.send(AsyncLogMessage::Line(message));
+			} else if let Err(TrySendError::Full(_)) =
+				self.sender.try_send(AsyncLogMessage::Line(message))
+			{
+				self.dropped.fetch_add(1, Ordering::Relaxed);
+			}
+		}
+	}
+
+	/// Blocks until the writer thread has written all lines that were logged before and
+	/// compressed the last rotated debug log
+	fn flush(&self) {
+		let (done_sender, done_receiver) = sync_channel(1);
+		if self
+			.sender
+			.send(AsyncLogMessage::Flush(done_sender))
+			.is_ok()
+		{
+			let _ = done_receiver.recv();
+		}
+	}
+}
+
+/// Checks that the given fd is open and was opened with write access,
+/// so that we do not end up hitting the error path on every log line.
+pub fn is_writable_fd(fd: RawFd) -> bool {
+	if fcntl(fd, FcntlArg::F_GETFD).is_err() {
+		return false;
+	}
+	match fcntl(fd, FcntlArg::F_GETFL) {
+		Ok(flags) => {
+			let access_mode = OFlag::from_bits_truncate(flags) & OFlag::O_ACCMODE;
+			access_mode == OFlag::O_WRONLY || access_mode == OFlag::O_RDWR
+		}
+		Err(_) => false,
+	}
+}
+
+/// Annotation that selects the log format for all runh commands operating on a container
+pub const LOG_FORMAT_ANNOTATION: &str = "io.runh.log-format";
+
+/// Reads the log format annotation of a container. The spec is taken from the bundle for
+/// `runh create` and from the container directory for all later commands.
+pub fn get_annotated_log_format(
+	project_dir: &Path,
+	container_id: Option<&str>,
+	bundle: Option<&str>,
+) -> Option<&'static str> {
+	let annotations = if let Some(bundle) = bundle {
+		crate::spec::load_config(&Path::new(bundle).join("config.json"))
+			.ok()?
+			.get("annotations")?
+			.clone()
+	} else {
+		let container_file =
+			std::fs::read_to_string(project_dir.join(container_id?).join("container.json")).ok()?;
+		serde_json::from_str::<serde_json::Value>(&container_file)
+			.ok()?
+			.get("spec")?
+			.get("annotations")?
+			.clone()
+	};
+
+	match annotations.get(LOG_FORMAT_ANNOTATION)?.as_str()? {
+		"json" => Some("json"),
+		"text" => Some("text"),
+		_ => None,
+	}
+}
+
+/// Expands the `%id` placeholder in a log path with the ID of the container the current
+/// command operates on. Commands without a container ID use `runh` instead.
+fn expand_log_path(log_path: &str, container_id: Option<&str>) -> String {
+	log_path.replace("%id", container_id.unwrap_or("runh"))
+}
+
+/// Configuration of the logger, see `init`
+pub struct LoggerOptions<'a> {
+	pub log_path: Option<&'a str>,
+	pub log_format: Option<&'a str>,
+	pub log_level: Option<&'a str>,
+	/// Write all log lines to a file in the state root as well (`--debug-log`)
+	pub internal_log: bool,
+	/// Rotate the file of `internal_log` once it reaches this size
+	pub internal_log_max_size: Option<u64>,
+	/// Gzip-compress rotated files of `internal_log`
+	pub compress_rotated: bool,
+	pub max_msg_len: Option<usize>,
+	pub async_backend: bool,
+	pub block_on_full: bool,
+}
+
+pub fn init(project_dir: PathBuf, container_id: Option<&str>, options: LoggerOptions) {
+	let LoggerOptions {
+		log_path,
+		log_format,
+		log_level,
+		internal_log,
+		internal_log_max_size,
+		compress_rotated,
+		max_msg_len,
+		async_backend,
+		block_on_full,
+	} = options;
 	let mut has_log_pipe = false;
+	let mut invalid_log_pipe: Option<RawFd> = None;
 	let log_file = log_path
-		.map(|path| std::fs::File::create(path).expect("Could not create new log file!"))
+		.map(|path| {
+			std::fs::File::create(expand_log_path(path, container_id))
+				.expect("Could not create new log file!")
+		})
 		.or_else(|| {
 			if let Ok(log_fd) = std::env::var("RUNH_LOG_PIPE") {
 				let pipe_fd: i32 = log_fd.parse().expect("RUNH_LOG_PIPE was not an integer!");
-				has_log_pipe = true;
-				unsafe { Some(File::from_raw_fd(pipe_fd)) }
+				if is_writable_fd(pipe_fd) {
+					has_log_pipe = true;
+					unsafe { Some(File::from_raw_fd(pipe_fd)) }
+				} else {
+					invalid_log_pipe = Some(pipe_fd);
+					None
+				}
 			} else {
 				None
 			}
 		});
-	let log_format = log_format.map_or(LogFormat::Text, |fmt| match fmt {
-		"json" => LogFormat::Json,
-		_ => LogFormat::Text,
-	});
+	set_format(log_format.unwrap_or("text"));
 
-	let logger: RunhLogger<File> = RunhLogger {
-		log_file: Mutex::new(log_file),
-		log_file_internal: Mutex::new(if has_log_pipe || !internal_log {
-			None
-		} else {
-			Some(
-				OpenOptions::new()
-					.create(true)
-					.write(true)
-					.open(project_dir.join(format!(
-						"log-{}.json",
-						OffsetDateTime::now_utc().format(&Rfc3339).unwrap()
-					)))
-					.expect("Could not open tmp log file!"),
+	let log_file_internal = if has_log_pipe || !internal_log {
+		None
+	} else {
+		Some(
+			DebugLogFile::create(
+				project_dir.join(format!(
+					"log-{}.json",
+					OffsetDateTime::now_utc().format(&Rfc3339).unwrap()
+				)),
+				internal_log_max_size,
+				compress_rotated,
 			)
-		}),
-		log_format,
+			.expect("Could not open tmp log file!"),
+		)
 	};
 
-	set_boxed_logger(Box::new(logger)).expect("Can't initialize logger");
-	let max_level: LevelFilter = match log_level {
-		Some("error") => LevelFilter::Error,
-		Some("debug") => LevelFilter::Debug,
-		Some("off") => LevelFilter::Off,
-		Some("trace") => LevelFilter::Trace,
-		Some("warn") => LevelFilter::Warn,
-		Some("info") => LevelFilter::Info,
-		_ => LevelFilter::Info,
-	};
-	set_max_level(max_level);
+	if async_backend {
+		let logger = AsyncRunhLogger::new(log_file, log_file_internal, max_msg_len, block_on_full);
+		set_boxed_logger(Box::new(logger)).expect("Can't initialize logger");
+	} else {
+		let logger: RunhLogger<File> = RunhLogger {
+			log_file: Mutex::new(log_file),
+			log_file_internal: Mutex::new(log_file_internal),
+			max_msg_len,
+		};
+		set_boxed_logger(Box::new(logger)).expect("Can't initialize logger");
+	}
+	set_level(log_level.unwrap_or("info"));
+
+	if let Some(pipe_fd) = invalid_log_pipe {
+		warn!(
+			"RUNH_LOG_PIPE fd {} is not open for writing! Logging to console instead...",
+			pipe_fd
+		);
+	}
 
 	debug!("Runh logger initialized!");
 }
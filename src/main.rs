@@ -3,6 +3,9 @@ extern crate colour;
 #[macro_use]
 extern crate log;
 
+mod apparmor;
+mod capabilities;
+mod cgroups;
 mod container;
 mod create;
 mod delete;
@@ -10,24 +13,47 @@ mod kill;
 //mod exec;
 mod console;
 mod consts;
+mod crilog;
 mod devices;
+mod dump;
+mod error;
+mod fds;
+mod features;
 mod flags;
+mod format;
 mod hermit;
+mod hooks;
 mod init;
+mod inspect;
 mod list;
 mod logging;
+mod metrics;
+mod mountinfo;
 mod mounts;
 mod namespaces;
 mod network;
 mod paths;
+mod personality;
 mod pull;
+mod rdt;
+mod reaper;
 mod rootfs;
+mod rootless;
+mod run;
+mod scheduling;
+mod selinux;
 mod spec;
 mod start;
 mod state;
+mod stop;
+mod syscall;
+mod sysctl;
+mod validate;
+mod wait;
 
 use crate::create::*;
 use crate::delete::*;
+use crate::features::*;
 //use crate::exec::*;
 use crate::init::*;
 use crate::kill::*;
@@ -36,9 +62,9 @@ use crate::pull::*;
 use crate::spec::*;
 use crate::start::*;
 use crate::state::*;
-use clap::{crate_authors, crate_description, crate_version, App, AppSettings, Arg, SubCommand};
-use std::fs::DirBuilder;
-use std::os::unix::fs::DirBuilderExt;
+use clap::{
+	crate_authors, crate_description, crate_version, App, AppSettings, Arg, ArgMatches, SubCommand,
+};
 use std::{env, path::PathBuf};
 
 fn parse_matches(app: App) {
@@ -46,37 +72,98 @@ fn parse_matches(app: App) {
 
 	let project_dir = PathBuf::from(matches.value_of("ROOT").unwrap());
 
-	if !project_dir.exists() {
-		DirBuilder::new()
-			.recursive(true)
-			.mode(0o755)
-			.create(&project_dir)
-			.unwrap_or_else(|_| panic!("Could not create root directory at {:?}", &project_dir));
+	let container_id = matches
+		.subcommand()
+		.1
+		.and_then(|sub_m| sub_m.value_of("CONTAINER_ID"));
+
+	if let Some(error_fd) = matches.value_of("ERROR_FD") {
+		let error_fd = error_fd.parse().expect("--error-fd was not an integer!");
+		if !logging::is_writable_fd(error_fd) {
+			panic!("--error-fd {} is not a writable file descriptor!", error_fd);
+		}
+		error::install_error_fd_hook(error_fd, container_id.map(String::from));
 	}
 
-	if let ("state", Some(sub_m)) = matches.subcommand() {
-		logging::init(
-			project_dir.clone(),
-			matches.value_of("LOG_PATH"),
-			matches.value_of("LOG_FORMAT"),
-			if matches.value_of("LOG_PATH").is_none() {
-				Some("error") //Suppress all output when only logging to stdout as we only want to print the state json.
-			} else {
-				matches.value_of("LOG_LEVEL")
-			},
-			matches.is_present("DEBUG_LOG"),
+	// Every command that takes an id joins it onto the state root
+	if let Some(id) = container_id {
+		validate::validate_container_id(id);
+	}
+
+	ensure_state_root(&project_dir);
+
+	if let Some(max_config_size) = matches.value_of("MAX_CONFIG_SIZE") {
+		spec::set_max_config_size(
+			max_config_size
+				.parse()
+				.expect("--max-config-size was not an unsigned integer!"),
 		);
-		print_container_state(project_dir, sub_m.value_of("CONTAINER_ID").unwrap());
-		return;
+	}
+
+	let log_max_msg_len: Option<usize> = matches.value_of("LOG_MAX_MSG_LEN").map(|len| {
+		len.parse()
+			.expect("--log-max-message-length was not an unsigned integer!")
+	});
+
+	// The log format annotation of a container takes precedence over --log-format
+	let log_format = logging::get_annotated_log_format(
+		&project_dir,
+		container_id,
+		matches
+			.subcommand_matches("create")
+			.or_else(|| matches.subcommand_matches("run"))
+			.and_then(|sub_m| sub_m.value_of("BUNDLE")),
+	)
+	.or_else(|| matches.value_of("LOG_FORMAT"));
+
+	// Commands that print their results to stdout suppress all other output when only logging to stdout
+	let prints_result = matches!(
+		matches.subcommand_name(),
+		Some("state")
+			| Some("features")
+			| Some("validate")
+			| Some("metrics")
+			| Some("wait")
+			| Some("debug-dump")
+			| Some("inspect-mounts")
+	);
+	let log_level = if prints_result && matches.value_of("LOG_PATH").is_none() {
+		Some("error")
+	} else {
+		matches.value_of("LOG_LEVEL")
 	};
 
+	// create and run --detach can print their result to stdout, so the logs have to make way
+	let prints_create_result = matches
+		.subcommand_matches("create")
+		.or_else(|| {
+			matches
+				.subcommand_matches("run")
+				.filter(|sub_m| sub_m.is_present("DETACH"))
+		})
+		.map_or(false, |sub_m| sub_m.value_of("OUTPUT") == Some("json"));
+	if prints_create_result {
+		logging::use_stderr();
+	}
+
 	// initialize logger
 	logging::init(
 		project_dir.clone(),
-		matches.value_of("LOG_PATH"),
-		matches.value_of("LOG_FORMAT"),
-		matches.value_of("LOG_LEVEL"),
-		matches.is_present("DEBUG_LOG"),
+		container_id,
+		logging::LoggerOptions {
+			log_path: matches.value_of("LOG_PATH"),
+			log_format,
+			log_level,
+			internal_log: matches.is_present("DEBUG_LOG"),
+			internal_log_max_size: matches.value_of("DEBUG_LOG_MAX_SIZE").map(|size| {
+				size.parse()
+					.expect("--debug-log-max-size was not an unsigned integer!")
+			}),
+			compress_rotated: matches.is_present("LOG_COMPRESS"),
+			max_msg_len: log_max_msg_len,
+			async_backend: matches.value_of("LOG_BACKEND") == Some("async"),
+			block_on_full: matches.is_present("LOG_BLOCK_ON_FULL"),
+		},
 	);
 	info!("Welcome to runh {}", crate_version!());
 	debug!(
@@ -93,30 +180,83 @@ fn parse_matches(app: App) {
 				.map(|a| a.to_string())
 				.collect(),
 		),
-		("create", Some(sub_m)) => create_container(
-			project_dir,
-			sub_m.value_of("CONTAINER_ID"),
-			sub_m.value_of("BUNDLE"),
-			sub_m.value_of("PID_FILE"),
-			sub_m.value_of("CONSOLE_SOCKET"),
-			matches.value_of("HERMIT_ENV_PATH"),
-			matches.is_present("DEBUG_CONFIG"),
-			matches.value_of("LOG_LEVEL").unwrap(),
-		),
+		("create", Some(sub_m)) => {
+			create_from_matches(project_dir.clone(), &matches, sub_m);
+			if prints_create_result {
+				print_create_result(project_dir, sub_m.value_of("CONTAINER_ID").unwrap());
+			}
+		}
+		("run", Some(sub_m)) => {
+			run::run_container(
+				project_dir.clone(),
+				sub_m.value_of("CONTAINER_ID"),
+				sub_m.is_present("DETACH"),
+				sub_m.value_of("FORWARD_SIGNAL"),
+				|| create_from_matches(project_dir.clone(), &matches, sub_m),
+			);
+			if prints_create_result {
+				print_create_result(project_dir, sub_m.value_of("CONTAINER_ID").unwrap());
+			}
+		}
 		("delete", Some(sub_m)) => delete_container(
 			project_dir,
 			sub_m.value_of("CONTAINER_ID"),
 			sub_m.is_present("FORCE"),
+			sub_m.is_present("FAIL_ON_MOUNT_LEAK"),
 		),
 		("kill", Some(sub_m)) => kill_container(
 			project_dir,
 			sub_m.value_of("CONTAINER_ID"),
 			sub_m.value_of("SIGNAL"),
 			sub_m.is_present("ALL"),
+			sub_m
+				.value_of("PID")
+				.map(|pid| pid.parse().expect("--pid was not an integer!")),
 		),
 		("start", Some(sub_m)) => start_container(project_dir, sub_m.value_of("CONTAINER_ID")),
+		("state", Some(sub_m)) => print_container_state(
+			project_dir,
+			sub_m.value_of("CONTAINER_ID").unwrap(),
+			parse_format(sub_m),
+		),
+		("stop", Some(sub_m)) => {
+			let id = sub_m.value_of("CONTAINER_ID").unwrap();
+			let timeout: u64 = sub_m
+				.value_of("TIMEOUT")
+				.unwrap()
+				.parse()
+				.expect("--timeout was not an unsigned integer!");
+			let outcome =
+				stop::stop_container(project_dir, id, std::time::Duration::from_secs(timeout));
+			info!("Container {} {}", id, outcome);
+		}
+		("features", Some(_)) => print_features(),
+		("debug-dump", Some(sub_m)) => {
+			dump::print_debug_dump(project_dir, sub_m.value_of("CONTAINER_ID").unwrap())
+		}
+		("wait", Some(sub_m)) => {
+			wait::wait_container(project_dir, sub_m.value_of("CONTAINER_ID").unwrap())
+		}
+		("metrics", Some(sub_m)) => metrics::print_metrics(
+			project_dir,
+			sub_m.value_of("CONTAINER_ID").unwrap(),
+			sub_m.value_of("INTERVAL").map(|interval| {
+				std::time::Duration::from_secs(
+					interval
+						.parse()
+						.expect("--interval was not an unsigned integer!"),
+				)
+			}),
+		),
+		("validate", Some(sub_m)) => {
+			validate::print_bundle_problems(sub_m.value_of("BUNDLE").unwrap())
+		}
+		("inspect-mounts", Some(sub_m)) => {
+			inspect::print_mount_plan(sub_m.value_of("BUNDLE").unwrap(), parse_format(sub_m))
+		}
 		("init", Some(_)) => init_container(),
-		("list", Some(_)) => list_containers(project_dir),
+		("log-writer", Some(sub_m)) => crilog::run_log_writer(sub_m.value_of("LOG_FILE").unwrap()),
+		("list", Some(sub_m)) => list_containers(project_dir, parse_format(sub_m)),
 		("pull", Some(sub_m)) => {
 			if let Some(str) = sub_m.value_of("IMAGE") {
 				pull_registry(
@@ -136,9 +276,252 @@ fn parse_matches(app: App) {
 		}
 	}
 }
+
+fn parse_format(sub_m: &ArgMatches) -> format::OutputFormat {
+	sub_m.value_of("FORMAT").unwrap().parse().unwrap()
+}
+
+fn format_arg<'a, 'b>(default: &'a str) -> Arg<'a, 'b> {
+	Arg::with_name("FORMAT")
+		.long("format")
+		.short("f")
+		.takes_value(true)
+		.possible_values(&["table", "json"])
+		.default_value(default)
+		.help("Render the output as human-readable table or as JSON")
+}
+
+fn create_from_matches(project_dir: PathBuf, matches: &ArgMatches, sub_m: &ArgMatches) {
+	create_container(
+		project_dir,
+		CreateOptions {
+			id: sub_m.value_of("CONTAINER_ID"),
+			bundle: sub_m.value_of("BUNDLE"),
+			pidfile: sub_m.value_of("PID_FILE"),
+			console_socket: sub_m.value_of("CONSOLE_SOCKET"),
+			hermit_env: matches.value_of("HERMIT_ENV_PATH"),
+			debug_config: matches.is_present("DEBUG_CONFIG"),
+			child_log_level: matches.value_of("LOG_LEVEL").unwrap(),
+			preserve_fds: sub_m
+				.value_of("PRESERVE_FDS")
+				.unwrap()
+				.parse()
+				.expect("--preserve-fds was not an integer!"),
+			apparmor: sub_m.value_of("APPARMOR").unwrap(),
+			init_wrapper: sub_m.is_present("INIT"),
+			cri_log: sub_m.value_of("CRI_LOG"),
+			strict_cgroups: sub_m.is_present("STRICT_CGROUPS"),
+			no_pivot: sub_m.is_present("NO_PIVOT"),
+			restart: sub_m.value_of("RESTART"),
+			env_from_host: sub_m.value_of("ENV_FROM_HOST"),
+			cgroup_parent: sub_m.value_of("CGROUP_PARENT"),
+			systemd_cgroup: matches.is_present("SYSTEMD_CGROUP"),
+			rootfs_type: sub_m.value_of("ROOTFS_TYPE"),
+			no_dev_setup: sub_m.is_present("NO_DEV_SETUP"),
+			no_proc: sub_m.is_present("NO_PROC"),
+			no_sys: sub_m.is_present("NO_SYS"),
+			expose_config: sub_m.value_of("EXPOSE_CONFIG"),
+			keep_namespaces: sub_m.is_present("KEEP_NAMESPACES"),
+			stdio: [
+				sub_m.value_of("STDIN"),
+				sub_m.value_of("STDOUT"),
+				sub_m.value_of("STDERR"),
+			],
+			setup_loopback: if sub_m.is_present("SETUP_LOOPBACK") {
+				Some(true)
+			} else if sub_m.is_present("NO_SETUP_LOOPBACK") {
+				Some(false)
+			} else {
+				None
+			},
+			rootless_mode: matches.value_of("ROOTLESS").unwrap().parse().unwrap(),
+		},
+	);
+}
+
+/// Arguments shared by `create` and `run`
+fn create_subcommand<'a, 'b>(name: &str) -> App<'a, 'b> {
+	SubCommand::with_name(name)
+		.version(crate_version!())
+		.arg(
+			Arg::with_name("CONTAINER_ID")
+				.takes_value(true)
+				.required(true)
+				.help("Id of the container"),
+		)
+		.arg(
+			Arg::with_name("BUNDLE")
+				.long("bundle")
+				.short("b")
+				.takes_value(true)
+				.required(true)
+				.help("Path to the root of the bundle directory"),
+		)
+		.arg(
+			Arg::with_name("PID_FILE")
+				.long("pid-file")
+				.takes_value(true)
+				.required(false)
+				.help("File to write the process id to"),
+		)
+		.arg(
+			Arg::with_name("CONSOLE_SOCKET")
+				.long("console-socket")
+				.takes_value(true)
+				.help("Path to an AF_UNIX socket for console IO")
+		)
+		.arg(
+			Arg::with_name("PRESERVE_FDS")
+				.long("preserve-fds")
+				.takes_value(true)
+				.default_value("0")
+				.help("Pass N additional file descriptors to the container (stdio + 3 + N in total)")
+		)
+		.arg(
+			Arg::with_name("APPARMOR")
+				.long("apparmor")
+				.takes_value(true)
+				.default_value("require")
+				.possible_values(&["require", "ignore"])
+				.help("Whether to fail or to continue if the container requests an AppArmor profile on a host without AppArmor")
+		)
+		.arg(
+			Arg::with_name("INIT")
+				.long("init")
+				.takes_value(false)
+				.help("Run a minimal init as PID 1 that forwards signals to the entrypoint and reaps zombies")
+		)
+		.arg(
+			Arg::with_name("CRI_LOG")
+				.long("cri-log")
+				.takes_value(true)
+				.help("Write stdout and stderr of the container to this file in CRI log format. Ignored for terminal containers")
+		)
+		.arg(
+			Arg::with_name("STRICT_CGROUPS")
+				.long("strict-cgroups")
+				.takes_value(false)
+				.help("Fail instead of skipping resource limits that cannot be applied in rootless mode")
+		)
+		.arg(
+			Arg::with_name("NO_PIVOT")
+				.long("no-pivot")
+				.takes_value(false)
+				.help("Do not use pivot_root to enter the rootfs, but move it over / and chroot into it")
+		)
+		.arg(
+			Arg::with_name("RESTART")
+				.long("restart")
+				.takes_value(true)
+				.value_name("POLICY")
+				.help("Restart the entrypoint if it fails shortly after its start. POLICY is on-failure:N[:GRACE_SECONDS], grace defaults to 10s. Implies --init")
+		)
+		.arg(
+			Arg::with_name("ENV_FROM_HOST")
+				.long("env-from-host")
+				.takes_value(true)
+				.value_name("KEY[,KEY...]")
+				.help("Copy the given host environment variables into the container, unless the config sets them"),
+		)
+		.arg(
+			Arg::with_name("CGROUP_PARENT")
+				.long("cgroup-parent")
+				.takes_value(true)
+				.value_name("PATH")
+				.help("Place the container cgroup below PATH, regardless of the config"),
+		)
+		.arg(
+			Arg::with_name("ROOTFS_TYPE")
+				.long("rootfs-type")
+				.takes_value(true)
+				.value_name("TYPE")
+				.help("Filesystem type to mount root.path with if it is not a directory (e.g. a block device)"),
+		)
+		.arg(
+			Arg::with_name("NO_DEV_SETUP")
+				.long("no-dev-setup")
+				.takes_value(false)
+				.help("Do not create the default devices, /dev/ptmx and the /dev symlinks, e.g. if the config already provides them through its mounts"),
+		)
+		.arg(
+			Arg::with_name("NO_PROC")
+				.long("no-proc")
+				.takes_value(false)
+				.help("Skip the proc mounts of the config, e.g. if the rootfs already provides /proc"),
+		)
+		.arg(
+			Arg::with_name("NO_SYS")
+				.long("no-sys")
+				.takes_value(false)
+				.help("Skip the sysfs mounts of the config, e.g. if the rootfs already provides /sys"),
+		)
+		.arg(
+			Arg::with_name("EXPOSE_CONFIG")
+				.long("expose-config")
+				.takes_value(true)
+				.value_name("PATH")
+				.help("Bind-mount the config.json of the bundle read-only at PATH inside the container"),
+		)
+		.arg(
+			Arg::with_name("KEEP_NAMESPACES")
+				.long("keep-namespaces")
+				.takes_value(false)
+				.help("Pin the created namespaces to files in the container state dir, so they can be entered with nsenter until the container is deleted"),
+		)
+		.arg(
+			Arg::with_name("STDIN")
+				.long("stdin")
+				.takes_value(true)
+				.value_name("FD|PATH")
+				.help("Let the container read its stdin from the given fd or file instead of the stdio of runh"),
+		)
+		.arg(
+			Arg::with_name("STDOUT")
+				.long("stdout")
+				.takes_value(true)
+				.value_name("FD|PATH")
+				.help("Let the container write its stdout to the given fd or file instead of the stdio of runh"),
+		)
+		.arg(
+			Arg::with_name("STDERR")
+				.long("stderr")
+				.takes_value(true)
+				.value_name("FD|PATH")
+				.help("Let the container write its stderr to the given fd or file instead of the stdio of runh"),
+		)
+		.arg(
+			Arg::with_name("SETUP_LOOPBACK")
+				.long("setup-loopback")
+				.takes_value(false)
+				.conflicts_with("NO_SETUP_LOOPBACK")
+				.help("Bring up lo with 127.0.0.1/8 in a new network namespace. This is the default unless the config has prestart or createRuntime hooks, which usually set up the network"),
+		)
+		.arg(
+			Arg::with_name("NO_SETUP_LOOPBACK")
+				.long("no-setup-loopback")
+				.takes_value(false)
+				.help("Leave lo down in a new network namespace"),
+		)
+		.arg(
+			Arg::with_name("OUTPUT")
+				.long("output")
+				.takes_value(true)
+				.possible_values(&["text", "json"])
+				.default_value("text")
+				.help("With json, print the id and pid of the container as JSON to stdout once it is created (or started by run --detach) and log to stderr instead"),
+		)
+}
+
 pub fn main() {
 	std::panic::set_hook(Box::new(|panic_info| {
-		error!("PANIC: {}", panic_info);
+		match panic_info.payload().downcast_ref::<error::RunhError>() {
+			Some(err) => match panic_info.location() {
+				Some(location) => error!("PANIC: {} at {}", err, location),
+				None => error!("PANIC: {}", err),
+			},
+			None => error!("PANIC: {}", panic_info),
+		}
+		log::logger().flush();
 	}));
 
 	let app = App::new("runh")
@@ -165,7 +548,7 @@ pub fn main() {
 			Arg::with_name("LOG_PATH")
 				.long("log")
 				.takes_value(true)
-				.help("set the log file path"),
+				.help("set the log file path. A %id in the path is replaced by the container ID"),
 		)
 		.arg(
 			Arg::with_name("LOG_FORMAT")
@@ -174,12 +557,52 @@ pub fn main() {
 				.possible_values(&["text", "json"])
 				.help("set the log format"),
 		)
+		.arg(
+			Arg::with_name("LOG_MAX_MSG_LEN")
+				.long("log-max-message-length")
+				.takes_value(true)
+				.help("Truncate log messages that are longer than this number of bytes")
+		)
+		.arg(
+			Arg::with_name("LOG_BACKEND")
+				.long("log-backend")
+				.default_value("sync")
+				.possible_values(&["sync", "async"])
+				.help("Write logs directly or through a writer thread that drops lines instead of blocking")
+		)
+		.arg(
+			Arg::with_name("LOG_BLOCK_ON_FULL")
+				.long("log-block-on-full")
+				.takes_value(false)
+				.help("Make the async log backend wait for the writer thread instead of dropping lines when its queue is full")
+		)
+		.arg(
+			Arg::with_name("ERROR_FD")
+				.long("error-fd")
+				.takes_value(true)
+				.help("On failure, write a JSON object describing the error to this file descriptor")
+		)
 		.arg(
 			Arg::with_name("DEBUG_LOG")
 				.long("debug-log")
 				.takes_value(false)
 				.help("Write out any logs to the runh root directory in addition to the specified log path.")
 		)
+		.arg(
+			Arg::with_name("DEBUG_LOG_MAX_SIZE")
+				.long("debug-log-max-size")
+				.takes_value(true)
+				.value_name("BYTES")
+				.requires("DEBUG_LOG")
+				.help("Rotate the --debug-log file once it reaches this size, keeping the last 5 backups")
+		)
+		.arg(
+			Arg::with_name("LOG_COMPRESS")
+				.long("log-compress")
+				.takes_value(false)
+				.requires("DEBUG_LOG_MAX_SIZE")
+				.help("Gzip-compress rotated --debug-log backups in the background")
+		)
 		.arg(
 			Arg::with_name("DEBUG_CONFIG")
 				.long("debug-config")
@@ -190,7 +613,22 @@ pub fn main() {
 			Arg::with_name("SYSTEMD_CGROUP")
 				.long("systemd-cgroup")
 				.takes_value(false)
-				.help("Currently unimplemented!")
+				.help("Interpret --cgroup-parent as a systemd slice. Managing cgroups through systemd is currently unimplemented!")
+		)
+		.arg(
+			Arg::with_name("MAX_CONFIG_SIZE")
+				.long("max-config-size")
+				.takes_value(true)
+				.value_name("BYTES")
+				.help("Refuse to load a config.json larger than this. Defaults to 4 MiB")
+		)
+		.arg(
+			Arg::with_name("ROOTLESS")
+				.long("rootless")
+				.takes_value(true)
+				.possible_values(&["auto", "true", "false"])
+				.default_value("auto")
+				.help("Set up containers without host privileges. auto enables it if runh does not run as root or the container gets a user namespace with id mappings")
 		)
 		.arg(
 			Arg::with_name("HERMIT_ENV_PATH")
@@ -221,19 +659,19 @@ pub fn main() {
 				),
 		)
 		.subcommand(
-				SubCommand::with_name("state")
-				.about("Query container state")
+			SubCommand::with_name("wait")
+				.about("Wait for a container to exit and print its exit status")
 				.version(crate_version!())
 				.arg(
 					Arg::with_name("CONTAINER_ID")
 						.takes_value(true)
 						.required(true)
 						.help("Id of the container"),
-				)
+				),
 		)
 		.subcommand(
-			SubCommand::with_name("create")
-				.about("Create a container")
+			SubCommand::with_name("stop")
+				.about("Send SIGTERM to a container and SIGKILL if it is still running after a timeout")
 				.version(crate_version!())
 				.arg(
 					Arg::with_name("CONTAINER_ID")
@@ -242,25 +680,74 @@ pub fn main() {
 						.help("Id of the container"),
 				)
 				.arg(
-					Arg::with_name("BUNDLE")
-						.long("bundle")
-						.short("b")
+					Arg::with_name("TIMEOUT")
+						.long("timeout")
+						.short("t")
+						.takes_value(true)
+						.value_name("SECONDS")
+						.default_value("10")
+						.help("Seconds to wait for the container to exit before killing it"),
+				),
+		)
+		.subcommand(
+			SubCommand::with_name("debug-dump")
+				.about("Print the spec, state, mounts, namespaces and cgroup stats of a container as JSON for bug reports")
+				.version(crate_version!())
+				.arg(
+					Arg::with_name("CONTAINER_ID")
 						.takes_value(true)
 						.required(true)
-						.help("Path to the root of the bundle directory"),
+						.help("Id of the container"),
+				),
+		)
+		.subcommand(
+			SubCommand::with_name("metrics")
+				.about("Print the resource usage of a container in the Prometheus text format")
+				.version(crate_version!())
+				.arg(
+					Arg::with_name("CONTAINER_ID")
+						.takes_value(true)
+						.required(true)
+						.help("Id of the container"),
 				)
 				.arg(
-					Arg::with_name("PID_FILE")
-						.long("pid-file")
+					Arg::with_name("INTERVAL")
+						.long("interval")
 						.takes_value(true)
-						.required(false)
-						.help("File to write the process id to"),
+						.value_name("SECONDS")
+						.help("Print the metrics again every SECONDS until the container is deleted"),
+				),
+		)
+		.subcommand(
+				SubCommand::with_name("state")
+				.about("Query container state")
+				.version(crate_version!())
+				.arg(
+					Arg::with_name("CONTAINER_ID")
+						.takes_value(true)
+						.required(true)
+						.help("Id of the container"),
+				)
+				.arg(format_arg("json")),
+		)
+		.subcommand(create_subcommand("create").about("Create a container"))
+		.subcommand(
+			create_subcommand("run")
+				.about("Create and start a container, then wait for it to exit and delete it")
+				.arg(
+					Arg::with_name("DETACH")
+						.long("detach")
+						.short("d")
+						.takes_value(false)
+						.help("Return after starting the container instead of waiting for it to exit"),
 				)
 				.arg(
-					Arg::with_name("CONSOLE_SOCKET")
-						.long("console-socket")
+					Arg::with_name("FORWARD_SIGNAL")
+						.long("forward-signal")
 						.takes_value(true)
-						.help("Path to an AF_UNIX socket for console IO")
+						.value_name("SIGNAL")
+						.conflicts_with("DETACH")
+						.help("Signal to send to the container instead of SIGINT when runh is interrupted (Ctrl-C)"),
 				),
 		)
 		.subcommand(
@@ -304,6 +791,12 @@ pub fn main() {
 						.takes_value(false)
 						.required(false)
 						.help("Delete the container, even if it is still running"),
+				)
+				.arg(
+					Arg::with_name("FAIL_ON_MOUNT_LEAK")
+						.long("fail-on-mount-leak")
+						.takes_value(false)
+						.help("Abort the deletion if mounts of the container are left behind after unmounting"),
 				),
 		)
 		.subcommand(
@@ -330,12 +823,21 @@ pub fn main() {
 					.takes_value(false)
 					.required(false)
 					.help("Send the signal to all container processes"),
+			)
+			.arg(
+				Arg::with_name("PID")
+					.long("pid")
+					.takes_value(true)
+					.required(false)
+					.conflicts_with("ALL")
+					.help("Send the signal to this process of the container instead of the init process"),
 			),
 		)
 		.subcommand(
 			SubCommand::with_name("list")
-				.about("Create and run a container")
-				.version(crate_version!()),
+				.about("List all containers")
+				.version(crate_version!())
+				.arg(format_arg("table")),
 		)
 		.subcommand(
 			SubCommand::with_name("start")
@@ -353,6 +855,18 @@ pub fn main() {
 				.about("Init process running inside a newly created container. Do not use outside of runh!")
 				.version(crate_version!())
 		)
+		.subcommand(
+			SubCommand::with_name("log-writer")
+				.about("Writes container output in CRI log format. Do not use outside of runh!")
+				.version(crate_version!())
+				.setting(AppSettings::Hidden)
+				.arg(
+					Arg::with_name("LOG_FILE")
+						.takes_value(true)
+						.required(true)
+						.help("Path to the container log file"),
+				)
+		)
 		.subcommand(
 			SubCommand::with_name("pull")
 				.about("Pull an image or a repository from a registry")
@@ -385,6 +899,35 @@ pub fn main() {
 						.help("Path to the root of the bundle directory"),
 				),
 		)
+		.subcommand(
+			SubCommand::with_name("validate")
+				.about("Check the configuration of a bundle for problems without creating a container")
+				.version(crate_version!())
+				.arg(
+					Arg::with_name("BUNDLE")
+						.takes_value(true)
+						.default_value(".")
+						.help("Path to the root of the bundle directory"),
+				),
+		)
+		.subcommand(
+			SubCommand::with_name("inspect-mounts")
+				.about("Print the mounts of a bundle in the order they would be performed, with their resolved destinations, flags and data")
+				.version(crate_version!())
+				.arg(
+					Arg::with_name("BUNDLE")
+						.takes_value(true)
+						.default_value(".")
+						.help("Path to the root of the bundle directory"),
+				)
+				.arg(format_arg("table")),
+		)
+		.subcommand(
+			SubCommand::with_name("features")
+				.alias("check")
+				.about("Report which kernel features relevant to containers are available on this host")
+				.version(crate_version!()),
+		)
 		.subcommand(
 			SubCommand::with_name("checkpoint")
 				.about("Checkpoint a running container (not supported)")
@@ -397,4 +940,5 @@ pub fn main() {
 		);
 
 	parse_matches(app.clone());
+	log::logger().flush();
 }
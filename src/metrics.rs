@@ -0,0 +1,96 @@
+use crate::cgroups;
+use crate::container::OCIContainer;
+use crate::error::{self, RunhError};
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// Name, type and help text of a metric, followed by its value in the cgroup stats
+type Metric = (
+	&'static str,
+	&'static str,
+	&'static str,
+	fn(&cgroups::CgroupStats) -> Option<f64>,
+);
+
+/// The exported metrics. Their names are part of runh's interface and must not change.
+const METRICS: [Metric; 7] = [
+	(
+		"runh_container_memory_bytes",
+		"gauge",
+		"Current memory usage of the container in bytes",
+		|stats| stats.memory_current.map(|value| value as f64),
+	),
+	(
+		"runh_container_memory_limit_bytes",
+		"gauge",
+		"Memory limit of the container in bytes",
+		|stats| stats.memory_max.map(|value| value as f64),
+	),
+	(
+		"runh_container_cpu_usage_seconds_total",
+		"counter",
+		"Total CPU time consumed by the container in seconds",
+		|stats| stats.cpu_usage_usec.map(|value| value as f64 / 1e6),
+	),
+	(
+		"runh_container_cpu_user_seconds_total",
+		"counter",
+		"CPU time consumed by the container in user mode in seconds",
+		|stats| stats.cpu_user_usec.map(|value| value as f64 / 1e6),
+	),
+	(
+		"runh_container_cpu_system_seconds_total",
+		"counter",
+		"CPU time consumed by the container in kernel mode in seconds",
+		|stats| stats.cpu_system_usec.map(|value| value as f64 / 1e6),
+	),
+	(
+		"runh_container_pids",
+		"gauge",
+		"Number of processes in the container",
+		|stats| stats.pids_current.map(|value| value as f64),
+	),
+	(
+		"runh_container_pids_limit",
+		"gauge",
+		"Maximum number of processes in the container",
+		|stats| stats.pids_max.map(|value| value as f64),
+	),
+];
+
+fn print_stats(id: &str, stats: &cgroups::CgroupStats) {
+	for (name, typ, help, value) in METRICS {
+		if let Some(value) = value(stats) {
+			println!("# HELP {} {}", name, help);
+			println!("# TYPE {} {}", name, typ);
+			println!("{}{{id=\"{}\"}} {}", name, id, value);
+		}
+	}
+}
+
+/// Prints the cgroup stats of a container in the Prometheus text exposition format.
+/// Metrics without a value (e.g. limits set to `max`) are left out. With an `interval`,
+/// the stats are printed again after every interval until the cgroup of the container is gone.
+pub fn print_metrics(project_dir: PathBuf, id: &str, interval: Option<Duration>) {
+	let container_dir = project_dir.join(id);
+	let container = OCIContainer::load(&container_dir)
+		.unwrap_or_else(|| error::raise(RunhError::ContainerNotFound(id.to_string())));
+	let mut reader = cgroups::CgroupStatsReader::new(&cgroups::get_container_cgroup_path(
+		&container_dir,
+		container.spec(),
+		id,
+	));
+
+	let interval = match interval {
+		Some(interval) => interval,
+		None => {
+			print_stats(id, &reader.read().unwrap_or_default());
+			return;
+		}
+	};
+	while let Some(stats) = reader.read() {
+		print_stats(id, &stats);
+		println!();
+		std::thread::sleep(interval);
+	}
+}
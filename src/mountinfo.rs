@@ -0,0 +1,114 @@
+use serde::Serialize;
+use std::path::PathBuf;
+
+/// A single line of /proc/<pid>/mountinfo, see proc(5)
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct MountInfoEntry {
+	pub mount_id: u32,
+	pub parent_id: u32,
+	/// Major and minor number of the device, e.g. `0:23`
+	pub dev: String,
+	/// Path of the mounted directory within its filesystem
+	pub root: PathBuf,
+	pub mount_point: PathBuf,
+	/// Per-mount options like `rw`, `nosuid` or `relatime`
+	pub options: Vec<String>,
+	/// Optional fields like `shared:1` or `master:2`, which describe the propagation type
+	pub optional_fields: Vec<String>,
+	pub fs_type: String,
+	pub source: String,
+	/// Per-superblock options, which includes filesystem-specific options
+	pub super_options: Vec<String>,
+}
+
+/// Reverts the octal escapes (e.g. `\040` for a space) the kernel uses for whitespace and
+/// backslashes in paths.
+fn unescape(field: &str) -> String {
+	let bytes = field.as_bytes();
+	let mut unescaped = Vec::with_capacity(bytes.len());
+	let mut i = 0;
+	while i < bytes.len() {
+		if bytes[i] == b'\\'
+			&& i + 3 < bytes.len()
+			&& bytes[i + 1..i + 4]
+				.iter()
+				.all(|b| (b'0'..=b'7').contains(b))
+		{
+			let digits = std::str::from_utf8(&bytes[i + 1..i + 4]).unwrap();
+			if let Ok(byte) = u8::from_str_radix(digits, 8) {
+				unescaped.push(byte);
+				i += 4;
+				continue;
+			}
+		}
+		unescaped.push(bytes[i]);
+		i += 1;
+	}
+	String::from_utf8_lossy(&unescaped).into_owned()
+}
+
+fn split_options(options: &str) -> Vec<String> {
+	options.split(',').map(unescape).collect()
+}
+
+/// Parses a single line of a mountinfo file
+pub fn parse_line(line: &str) -> Result<MountInfoEntry, String> {
+	let (head, tail) = line
+		.split_once(" - ")
+		.ok_or_else(|| format!("mountinfo line {:?} is missing the separator", line))?;
+
+	let mut head = head.split(' ');
+	let mut next_field = |name: &str| {
+		head.next()
+			.ok_or_else(|| format!("mountinfo line {:?} is missing the {}", line, name))
+	};
+	let mount_id = next_field("mount id")?
+		.parse()
+		.map_err(|_| format!("mountinfo line {:?} has an invalid mount id", line))?;
+	let parent_id = next_field("parent id")?
+		.parse()
+		.map_err(|_| format!("mountinfo line {:?} has an invalid parent id", line))?;
+	let dev = next_field("device")?.to_string();
+	let root = PathBuf::from(unescape(next_field("root")?));
+	let mount_point = PathBuf::from(unescape(next_field("mount point")?));
+	let options = split_options(next_field("mount options")?);
+	let optional_fields = head
+		.filter(|field| !field.is_empty())
+		.map(String::from)
+		.collect();
+
+	let mut tail = tail.splitn(3, ' ');
+	let fs_type = unescape(tail.next().unwrap_or_default());
+	let source = unescape(
+		tail.next()
+			.ok_or_else(|| format!("mountinfo line {:?} is missing the source", line))?,
+	);
+	let super_options = split_options(tail.next().unwrap_or_default());
+
+	Ok(MountInfoEntry {
+		mount_id,
+		parent_id,
+		dev,
+		root,
+		mount_point,
+		options,
+		optional_fields,
+		fs_type,
+		source,
+		super_options,
+	})
+}
+
+/// Reads all mounts visible to the process with the given pid (or to runh itself for `None`)
+/// in the order they were created
+pub fn read_mountinfo(pid: Option<i32>) -> Vec<MountInfoEntry> {
+	let path = match pid {
+		Some(pid) => format!("/proc/{}/mountinfo", pid),
+		None => String::from("/proc/self/mountinfo"),
+	};
+	std::fs::read_to_string(&path)
+		.unwrap_or_else(|err| panic!("Could not read {}: {}", path, err))
+		.lines()
+		.map(|line| parse_line(line).unwrap_or_else(|err| panic!("{}", err)))
+		.collect()
+}
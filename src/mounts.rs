@@ -1,16 +1,21 @@
 use nix::{mount::MsFlags, sys::stat::Mode};
 use oci_spec::runtime;
 use std::{
-	fs::{DirBuilder, File, OpenOptions},
+	fs::{DirBuilder, File, OpenOptions, Permissions},
 	os::unix::{
-		fs::DirBuilderExt,
+		fs::{DirBuilderExt, MetadataExt, PermissionsExt},
 		prelude::{AsRawFd, OpenOptionsExt},
 	},
 	path::Path,
 	path::PathBuf,
 };
 
+use crate::cgroups;
+use crate::devices;
+use crate::error::{self, RunhError};
 use crate::rootfs;
+use crate::selinux;
+use crate::syscall::traced_syscall;
 
 #[derive(Clone)]
 pub struct MountOptions {
@@ -39,26 +44,63 @@ pub fn mount_console(slave_path: &Path) {
 		.open("/dev/console")
 		.expect("Could not create /dev/console");
 
-	nix::mount::mount::<Path, str, str, str>(
-		Some(slave_path),
-		"/dev/console",
-		Some("bind"),
-		MsFlags::MS_BIND,
-		None,
+	traced_syscall(
+		"mount",
+		format_args!("{:?}, \"/dev/console\", \"bind\", MS_BIND", slave_path),
+		|| {
+			nix::mount::mount::<Path, str, str, str>(
+				Some(slave_path),
+				"/dev/console",
+				Some("bind"),
+				MsFlags::MS_BIND,
+				None,
+			)
+		},
 	)
 	.expect("Could not mount console at /dev/console!");
 
 	let _ = nix::sys::stat::umask(old_umask);
 }
 
+/// Computes the flags and data a mount of the config is mounted with
+pub fn resolve_mount_options(mount: &runtime::Mount) -> MountOptions {
+	let mut mount_options = mount
+		.options()
+		.as_ref()
+		.map(|options| parse_mount_options(options))
+		.unwrap_or_default();
+	if mount.typ().as_deref() == Some("tmpfs") && mount.destination() == Path::new("/dev") {
+		apply_dev_tmpfs_defaults(&mut mount_options);
+	}
+	mount_options
+}
+
+#[allow(clippy::too_many_arguments)]
 pub fn configure_mounts(
 	mounts: &[runtime::Mount],
 	rootfs: &Path,
 	bundle_rootfs: &Path,
 	mount_label: &Option<String>,
+	has_pid_namespace: bool,
+	has_cgroup_namespace: bool,
+	uid_mappings: &[runtime::LinuxIdMapping],
+	gid_mappings: &[runtime::LinuxIdMapping],
+	rootless: bool,
+	bind_host_dev: bool,
+	no_proc: bool,
+	no_sys: bool,
 ) -> bool {
 	let mut setup_dev = true;
 
+	// The host /dev takes the place of the /dev mount, so that the mounts below /dev end up on top of it
+	let has_dev_mount = mounts
+		.iter()
+		.any(|mount| mount.destination() == Path::new("/dev"));
+	if bind_host_dev && !has_dev_mount {
+		devices::bind_host_dev(rootfs);
+		setup_dev = false;
+	}
+
 	for mount in mounts {
 		//Resolve mount source
 		let mut mount_src = PathBuf::from(&mount.source().as_ref().unwrap());
@@ -69,11 +111,7 @@ pub fn configure_mounts(
 		let mount_dest = PathBuf::from(&mount.destination());
 		let mount_device = mount.typ().as_ref().unwrap().as_str();
 
-		let mount_options = mount
-			.options()
-			.as_ref()
-			.map(|options| parse_mount_options(options))
-			.unwrap_or_default();
+		let mount_options = resolve_mount_options(mount);
 
 		let destination_resolved = rootfs::resolve_in_rootfs(mount.destination(), rootfs);
 
@@ -85,23 +123,31 @@ pub fn configure_mounts(
 				mount.options().as_ref().unwrap_or(&vec![])
 			);
 
-			let is_bind_mount = mount
-				.options()
-				.as_ref()
-				.map(|options| {
-					options.contains(&"bind".to_string()) || options.contains(&"rbind".to_string())
-				})
-				.unwrap_or(false);
-			if is_bind_mount {
+			let is_bind_mount = mount_device == "bind"
+				|| mount
+					.options()
+					.as_ref()
+					.map(|options| {
+						options.contains(&"bind".to_string())
+							|| options.contains(&"rbind".to_string())
+					})
+					.unwrap_or(false);
+			if bind_host_dev && destination_resolved == rootfs.join("dev") {
+				debug!("Replacing the /dev mount with a bind mount of the host /dev");
+				devices::bind_host_dev(rootfs);
+				setup_dev = false;
+			} else if is_bind_mount {
 				if destination_resolved == PathBuf::from(&rootfs).join("dev") {
 					setup_dev = false;
 				}
 
-				if !mount_src.exists() {
-					panic!(
-						"Tried to bind-mount source {:?} which does not exist!",
-						mount_src
-					);
+				// Only bind mounts need an existing source, other file systems ignore it
+				if let Err(err) = mount_src.symlink_metadata() {
+					error::raise(RunhError::BindSourceMissing {
+						source: mount_src,
+						destination: mount_dest,
+						reason: err.to_string(),
+					});
 				}
 				if destination_resolved.starts_with(&rootfs.join("proc")) {
 					panic!(
@@ -109,26 +155,12 @@ pub fn configure_mounts(
 						mount_src, mount_dest
 					);
 				} else {
-					if mount_src.is_dir() {
-						create_all_dirs(&destination_resolved);
-					} else {
+					if !mount_src.is_dir() {
 						create_all_dirs(&PathBuf::from(&destination_resolved.parent().unwrap_or_else(||
 							panic!("Could not mount to destination {:?} which is not a directory and has no parent dir!", destination_resolved)
 						)));
-						if !destination_resolved.exists() {
-							let _ = OpenOptions::new()
-								.mode(0o755)
-								.create(true)
-								.write(true)
-								.open(&destination_resolved)
-								.unwrap_or_else(|_| {
-									panic!(
-										"Could not create destination for bind mount at {:?}",
-										destination_resolved
-									)
-								});
-						}
 					}
+					create_bind_placeholder(&destination_resolved, mount_src.is_dir());
 
 					mount_with_flags(
 						"bind",
@@ -139,6 +171,16 @@ pub fn configure_mounts(
 						mount_label.as_ref(),
 					);
 
+					// Has to happen before a read-only remount
+					let recursive_chown = mount
+						.options()
+						.as_ref()
+						.map(|options| options.contains(&"recursiveChown".to_string()))
+						.unwrap_or(false);
+					if recursive_chown {
+						shift_ownership(&destination_resolved, uid_mappings, gid_mappings);
+					}
+
 					let mut mount_options_copy = mount_options.clone();
 
 					mount_options_copy.mount_flags.remove(MsFlags::MS_REC);
@@ -158,20 +200,17 @@ pub fn configure_mounts(
 				}
 			} else {
 				match mount.typ().as_ref().map(|x| x.as_str()) {
-					Some("sysfs") | Some("proc") => {
-						if !destination_resolved.exists() || destination_resolved.is_dir() {
-							create_all_dirs(&destination_resolved);
-							mount_with_flags(
-								mount_device,
-								&mount_src,
-								&mount_dest,
-								&destination_resolved,
-								mount_options,
-								None,
-							);
-						} else {
-							panic!("Could not mount {:?}! sysfs and proc filesystems can only be mounted on directories!", destination_resolved);
-						}
+					Some("proc") if no_proc => {
+						debug!("Skipping proc mount at {:?} (--no-proc)", mount_dest);
+					}
+					Some("sysfs") if no_sys => {
+						debug!("Skipping sysfs mount at {:?} (--no-sys)", mount_dest);
+					}
+					Some("proc") => {
+						rootfs::mount_proc(rootfs, &mount_dest, mount_options, has_pid_namespace);
+					}
+					Some("sysfs") => {
+						rootfs::mount_sys(rootfs, &mount_dest, mount_options, rootless);
 					}
 					Some("mqueue") => {
 						if !destination_resolved.exists() {
@@ -182,14 +221,24 @@ pub fn configure_mounts(
 							&mount_src,
 							&mount_dest,
 							&destination_resolved,
-							mount_options,
+							mount_options.clone(),
 							None,
 						);
+						remount_if_read_only(
+							mount_device,
+							&mount_src,
+							&mount_dest,
+							&destination_resolved,
+							mount_options,
+						);
 					}
 					Some("tmpfs") => {
+						// An explicit mode= option takes precedence over the permissions of an existing destination
 						let tmpfs_mode = if !destination_resolved.exists() {
 							create_all_dirs(&destination_resolved);
 							None
+						} else if has_data_option(&mount_options.data, "mode") {
+							None
 						} else {
 							Some(
 								destination_resolved
@@ -203,7 +252,6 @@ pub fn configure_mounts(
 									.permissions(),
 							)
 						};
-						let is_read_only = mount_options.mount_flags.contains(MsFlags::MS_RDONLY);
 						mount_with_flags(
 							mount_device,
 							&mount_src,
@@ -224,29 +272,35 @@ pub fn configure_mounts(
 							);
 						}
 
-						if is_read_only {
-							remount(
-								mount_device,
-								&mount_src,
-								&mount_dest,
-								&destination_resolved,
-								mount_options,
-							);
-						}
+						remount_if_read_only(
+							mount_device,
+							&mount_src,
+							&mount_dest,
+							&destination_resolved,
+							mount_options,
+						);
 					}
-					Some("cgroup") => {
-						//TODO: Additional checks for cGroup v1 vs v2,
-						//		mount might fail when the cgroup-NS was not unshared earlier
-						// create_all_dirs(&destination_resolved);
-						// mount_with_flags(
-						// 	"cgroup2",
-						// 	&mount_src,
-						// 	&mount_dest,
-						// 	&destination_resolved,
-						// 	mount_options.clone(),
-						// 	mount_label.as_ref(),
-						// );
-						warn!("Warning: cgroups are currently unimplemented!");
+					Some("devpts") => {
+						create_all_dirs(&destination_resolved);
+						let mut devpts_options = mount_options.clone();
+						devpts_options.data = Some(get_devpts_data(&mount_options.data, rootfs));
+						// /dev/ptmx is linked to pts/ptmx in devices::setup_ptmx once all mounts are done
+						mount_with_flags(
+							mount_device,
+							&mount_src,
+							&mount_dest,
+							&destination_resolved,
+							devpts_options,
+							mount_label.as_ref(),
+						);
+					}
+					Some("cgroup") | Some("cgroup2") => {
+						mount_cgroup(
+							&mount_dest,
+							&destination_resolved,
+							mount_options.clone(),
+							has_cgroup_namespace,
+						);
 					}
 					_ => {
 						if destination_resolved.starts_with(&rootfs.join("proc")) {
@@ -264,6 +318,13 @@ pub fn configure_mounts(
 								mount_options.clone(),
 								mount_label.as_ref(),
 							);
+							remount_if_read_only(
+								mount_device,
+								&mount_src,
+								&mount_dest,
+								&destination_resolved,
+								mount_options,
+							);
 						}
 					}
 				}
@@ -278,23 +339,88 @@ pub fn configure_mounts(
 	setup_dev
 }
 
-fn remount(
+fn has_data_option(data: &Option<String>, key: &str) -> bool {
+	data.as_deref()
+		.map(|data| {
+			data.split(',')
+				.any(|option| option == key || option.starts_with(&format!("{}=", key)))
+		})
+		.unwrap_or(false)
+}
+
+/// Looks up the ID of a group by name in the /etc/group file of the container.
+fn resolve_group_id(rootfs: &Path, group: &str) -> u32 {
+	let group_file = rootfs::resolve_in_rootfs(&PathBuf::from("/etc/group"), rootfs);
+	let content = std::fs::read_to_string(&group_file)
+		.unwrap_or_else(|_| panic!("Could not read {:?} to resolve group {}", group_file, group));
+	content
+		.lines()
+		.map(|line| line.split(':').collect::<Vec<&str>>())
+		.find(|fields| fields.len() > 2 && fields[0] == group)
+		.map(|fields| {
+			fields[2].parse().unwrap_or_else(|_| {
+				panic!(
+					"Group {} has an invalid group ID in {:?}",
+					group, group_file
+				)
+			})
+		})
+		.unwrap_or_else(|| panic!("Could not find group {} in {:?}", group, group_file))
+}
+
+/// Assembles the data string for a devpts mount from the spec options. Every devpts mount
+/// gets its own instance with an accessible ptmx node, and group names given as gid are
+/// resolved against the container's /etc/group.
+fn get_devpts_data(data: &Option<String>, rootfs: &Path) -> String {
+	let mut options: Vec<String> = data
+		.as_deref()
+		.unwrap_or("")
+		.split(',')
+		.filter(|option| !option.is_empty())
+		.map(|option| match option.split_once('=') {
+			Some(("gid", group)) if group.parse::<u32>().is_err() => {
+				format!("gid={}", resolve_group_id(rootfs, group))
+			}
+			_ => option.to_string(),
+		})
+		.collect();
+
+	if !has_data_option(data, "newinstance") {
+		options.push(String::from("newinstance"));
+	}
+	if !has_data_option(data, "ptmxmode") {
+		options.push(String::from("ptmxmode=0666"));
+	}
+	options.join(",")
+}
+
+pub fn remount(
 	device: &str,
 	mount_src: &Path,
 	mount_dest: &Path,
 	full_dest: &Path,
 	mut options: MountOptions,
 ) {
-	let procfd = open_trough_procfd(device, mount_dest, full_dest, &mut options);
+	// The read-only flag is only stripped for the initial mount, a remount has to keep it
+	let procfd = open_trough_procfd(device, mount_dest, full_dest, &mut options.clone());
 	let procfd_path = PathBuf::from("/proc/self/fd").join(procfd.as_raw_fd().to_string());
 
 	options.mount_flags.insert(MsFlags::MS_REMOUNT);
-	nix::mount::mount::<Path, Path, str, str>(
-		Some(mount_src),
-		&procfd_path,
-		Some(device.to_owned().as_str()),
-		options.mount_flags,
-		None,
+	traced_syscall(
+		"mount",
+		format_args!(
+			"{:?}, {:?}, {:?}, {:?}",
+			mount_src, procfd_path, device, options.mount_flags
+		),
+		|| {
+			nix::mount::mount::<Path, Path, str, str>(
+				Some(mount_src),
+				&procfd_path,
+				Some(device),
+				options.mount_flags,
+				None,
+			)
+		},
 	)
 	.unwrap_or_else(|_| {
 		panic!(
@@ -304,50 +430,255 @@ fn remount(
 	});
 }
 
-pub fn mount_with_flags(
+/// The kernel ignores MS_RDONLY on the initial mount of some file systems (and runh strips it
+/// for tmpfs, so that its mode can still be changed). A freshly mounted file system that
+/// should be read-only is therefore remounted with the original flags and MS_RDONLY.
+pub fn remount_if_read_only(
 	device: &str,
 	mount_src: &Path,
 	mount_dest: &Path,
 	full_dest: &Path,
+	options: MountOptions,
+) {
+	if options.mount_flags.contains(MsFlags::MS_RDONLY) {
+		debug!("Remounting {:?} read-only", full_dest);
+		remount(device, mount_src, mount_dest, full_dest, options);
+	}
+}
+
+/// Bind-mounts the config of the container read-only at `destination` within the rootfs
+pub fn mount_config(config: &Path, destination: &Path, rootfs: &Path) {
+	let destination_resolved = rootfs::resolve_in_rootfs(destination, rootfs);
+	if let Some(parent) = destination_resolved.parent() {
+		if !parent.exists() {
+			create_all_dirs(parent);
+		}
+	}
+	create_bind_placeholder(&destination_resolved, false);
+
+	debug!("Exposing config {:?} at {:?}", config, destination);
+	let options = MountOptions {
+		mount_flags: MsFlags::MS_BIND | MsFlags::MS_RDONLY,
+		propagation_flags: MsFlags::empty(),
+		data: None,
+	};
+	mount_with_flags(
+		"bind",
+		config,
+		destination,
+		&destination_resolved,
+		options.clone(),
+		None,
+	);
+	// A bind mount only becomes read-only when it is remounted
+	remount("bind", config, destination, &destination_resolved, options);
+}
+
+/// Mounts the cgroup tree of the container, so that the container cgroup appears as root.
+/// Inside a cgroup namespace, a new cgroup2 mount already shows the namespace root.
+/// Otherwise, the container cgroup is bind-mounted from the host cgroup tree.
+fn mount_cgroup(
+	mount_dest: &Path,
+	destination_resolved: &Path,
 	mut options: MountOptions,
-	_label: Option<&String>,
+	has_cgroup_namespace: bool,
+) {
+	if cgroups::get_cgroup_version() != cgroups::CgroupVersion::V2 {
+		warn!(
+			"cgroup v1 mounts are currently unsupported. Skipping mount at {:?}!",
+			mount_dest
+		);
+		return;
+	}
+
+	create_all_dirs(destination_resolved);
+	options.mount_flags.insert(MsFlags::MS_NOSUID);
+	options.mount_flags.insert(MsFlags::MS_NODEV);
+	options.mount_flags.insert(MsFlags::MS_NOEXEC);
+
+	if has_cgroup_namespace {
+		mount_with_flags(
+			"cgroup2",
+			Path::new("cgroup2"),
+			mount_dest,
+			destination_resolved,
+			options,
+			None,
+		);
+	} else {
+		let own_cgroup = cgroups::get_own_cgroup();
+		debug!(
+			"Bind-mounting cgroup {:?} at {:?}",
+			own_cgroup, destination_resolved
+		);
+		let read_only = options.mount_flags.contains(MsFlags::MS_RDONLY);
+		options
+			.mount_flags
+			.insert(MsFlags::MS_BIND | MsFlags::MS_REC);
+		options.data = None;
+		mount_with_flags(
+			"bind",
+			&own_cgroup,
+			mount_dest,
+			destination_resolved,
+			options.clone(),
+			None,
+		);
+		if read_only {
+			remount(
+				"bind",
+				&own_cgroup,
+				mount_dest,
+				destination_resolved,
+				options,
+			);
+		}
+	}
+}
+
+fn map_id(id: u32, mappings: &[runtime::LinuxIdMapping]) -> Option<u32> {
+	mappings
+		.iter()
+		.find(|mapping| {
+			id >= mapping.container_id() && id - mapping.container_id() < mapping.size()
+		})
+		.map(|mapping| mapping.host_id() + (id - mapping.container_id()))
+}
+
+/// Fallback for kernels without idmapped mounts: changes the owner of every entry below `path`
+/// from its container ID to the host ID given by the user namespace mappings, so bind-mounted
+/// files appear with the right owner inside the container. This touches the files on the host!
+/// File systems mounted below `path` (e.g. a bind mount of another host directory) are skipped.
+pub fn shift_ownership(
+	path: &Path,
+	uid_mappings: &[runtime::LinuxIdMapping],
+	gid_mappings: &[runtime::LinuxIdMapping],
+) {
+	if uid_mappings.is_empty() && gid_mappings.is_empty() {
+		warn!(
+			"recursiveChown was requested for {:?}, but the container has no ID mappings. Skipping...",
+			path
+		);
+		return;
+	}
+
+	info!(
+		"Shifting ownership of all files below {:?}. This may take a while...",
+		path
+	);
+	let root_dev = std::fs::symlink_metadata(path)
+		.unwrap_or_else(|err| panic!("Could not stat {:?}: {}", path, err))
+		.dev();
+	let mut pending = vec![path.to_path_buf()];
+	let mut count: u64 = 0;
+	while let Some(entry) = pending.pop() {
+		let metadata = std::fs::symlink_metadata(&entry)
+			.unwrap_or_else(|err| panic!("Could not stat {:?}: {}", entry, err));
+		if metadata.dev() != root_dev {
+			debug!("Not shifting ownership below mount point {:?}", entry);
+			continue;
+		}
+
+		let uid = map_id(metadata.uid(), uid_mappings).map(nix::unistd::Uid::from_raw);
+		let gid = map_id(metadata.gid(), gid_mappings).map(nix::unistd::Gid::from_raw);
+		if uid.is_some() || gid.is_some() {
+			nix::unistd::fchownat(
+				None,
+				&entry,
+				uid,
+				gid,
+				nix::unistd::FchownatFlags::NoFollowSymlink,
+			)
+			.unwrap_or_else(|err| panic!("Could not change owner of {:?}: {}", entry, err));
+		}
+
+		if metadata.is_dir() {
+			let children = std::fs::read_dir(&entry)
+				.unwrap_or_else(|err| panic!("Could not read directory {:?}: {}", entry, err));
+			for child in children {
+				pending.push(
+					child
+						.unwrap_or_else(|err| {
+							panic!("Could not read entry of {:?}: {}", entry, err)
+						})
+						.path(),
+				);
+			}
+		}
+
+		count += 1;
+		if count % 10000 == 0 {
+			info!("Shifted ownership of {} files below {:?}", count, path);
+		}
+	}
+	info!("Shifted ownership of {} files below {:?}", count, path);
+}
+
+pub fn mount_with_flags(
+	device: &str,
+	mount_src: &Path,
+	mount_dest: &Path,
+	full_dest: &Path,
+	options: MountOptions,
+	label: Option<&String>,
 ) {
-	// TODO: Format mount label with data string
+	try_mount_with_flags(device, mount_src, mount_dest, full_dest, options, label).unwrap_or_else(
+		|err| {
+			panic!(
+				"Could not mount source {:?} at destination path {:?}: {}",
+				mount_src, full_dest, err
+			)
+		},
+	);
+}
+
+/// Like `mount_with_flags`, but returns the error of the mount call for callers that can
+/// fall back to a different kind of mount.
+pub fn try_mount_with_flags(
+	device: &str,
+	mount_src: &Path,
+	mount_dest: &Path,
+	full_dest: &Path,
+	mut options: MountOptions,
+	label: Option<&String>,
+) -> nix::Result<()> {
+	// Bind mounts ignore the mount data, so the label is only applied to new file systems
+	if let Some(label) = label {
+		if device != "bind" {
+			options.data = selinux::format_mount_label(options.data.as_deref(), label);
+		}
+	}
+
 	let procfd = open_trough_procfd(device, mount_dest, full_dest, &mut options);
 	let procfd_path = PathBuf::from("/proc/self/fd").join(procfd.as_raw_fd().to_string());
 
-	nix::mount::mount::<Path, Path, str, str>(
-		Some(mount_src),
-		&procfd_path,
-		Some(device.to_owned().as_str()),
-		options.mount_flags,
-		options.data.as_deref(),
-	)
-	.unwrap_or_else(|_| {
-		panic!(
-			"Could not mount source {:?} at destination path {:?}",
-			mount_src, full_dest
-		)
-	});
+	traced_syscall(
+		"mount",
+		format_args!(
+			"{:?}, {:?}, {:?}, {:?}, {:?}",
+			mount_src, procfd_path, device, options.mount_flags, options.data
+		),
+		|| {
+			nix::mount::mount::<Path, Path, str, str>(
+				Some(mount_src),
+				&procfd_path,
+				Some(device),
+				options.mount_flags,
+				options.data.as_deref(),
+			)
+		},
+	)?;
 
 	if !options.propagation_flags.is_empty() {
 		let new_procfd = open_trough_procfd(device, mount_dest, full_dest, &mut options);
 		let new_procfd_path =
 			PathBuf::from("/proc/self/fd").join(new_procfd.as_raw_fd().to_string());
-		nix::mount::mount::<PathBuf, PathBuf, str, str>(
-			None,
-			&new_procfd_path,
-			None,
-			options.propagation_flags,
-			None,
-		)
-		.unwrap_or_else(|_| {
-			panic!(
-				"Could not apply mount propagation for destination path {:?}",
-				full_dest
-			)
-		});
+		let mut propagation = options.propagation_flags;
+		let recursive = propagation.contains(MsFlags::MS_REC);
+		propagation.remove(MsFlags::MS_REC);
+		rootfs::set_mount_propagation(&new_procfd_path, propagation, recursive);
 	}
+	Ok(())
 }
 
 fn open_trough_procfd(
@@ -389,6 +720,42 @@ fn open_trough_procfd(
 	dest_file
 }
 
+/// Mode of the files and directories created as destinations of bind mounts
+const BIND_PLACEHOLDER_FILE_MODE: u32 = 0o644;
+const BIND_PLACEHOLDER_DIR_MODE: u32 = 0o755;
+
+/// Creates the destination of a bind mount if it does not exist yet. The placeholder is hidden by
+/// the mount, but stays in the rootfs after the container is gone, so its mode is set explicitly
+/// instead of depending on the umask of runh or the mode of the source.
+pub fn create_bind_placeholder(dest: &Path, is_dir: bool) {
+	if dest.exists() {
+		return;
+	}
+	let mode = if is_dir {
+		create_all_dirs(dest);
+		BIND_PLACEHOLDER_DIR_MODE
+	} else {
+		OpenOptions::new()
+			.mode(BIND_PLACEHOLDER_FILE_MODE)
+			.create(true)
+			.write(true)
+			.open(dest)
+			.unwrap_or_else(|err| {
+				panic!(
+					"Could not create destination for bind mount at {:?}: {}",
+					dest, err
+				)
+			});
+		BIND_PLACEHOLDER_FILE_MODE
+	};
+	std::fs::set_permissions(dest, Permissions::from_mode(mode)).unwrap_or_else(|err| {
+		panic!(
+			"Could not set mode of bind mount destination {:?}: {}",
+			dest, err
+		)
+	});
+}
+
 pub fn create_all_dirs(dest: &Path) {
 	DirBuilder::new()
 		.recursive(true)
@@ -397,6 +764,46 @@ pub fn create_all_dirs(dest: &Path) {
 		.unwrap_or_else(|_| panic!("Could not create directories for {:?}", dest));
 }
 
+/// Do not follow symlinks when resolving paths on the mount (Linux 5.10). Neither nix nor libc
+/// define it yet.
+const MS_NOSYMFOLLOW: libc::c_ulong = 256;
+
+/// Wraps flags newer than the MsFlags of nix, which from_bits_truncate would silently drop
+fn raw_mount_flag(bits: libc::c_ulong) -> MsFlags {
+	unsafe { MsFlags::from_bits_unchecked(bits) }
+}
+
+/// noatime, relatime and strictatime select mutually exclusive atime modes, so setting one of
+/// them clears the others. Without any of them, the kernel defaults to relatime.
+fn set_atime_mode(mount_flags: &mut MsFlags, mode: MsFlags) {
+	mount_flags.remove(MsFlags::MS_NOATIME | MsFlags::MS_RELATIME | MsFlags::MS_STRICTATIME);
+	mount_flags.insert(mode);
+}
+
+/// Brings a tmpfs at /dev in line with the one of runc: nosuid, strictatime and mode=755 unless
+/// the config chooses otherwise. noexec is deliberately not added, as runc does not set it either
+/// and some applications execute from /dev (e.g. through /dev/shm if it is no separate mount).
+/// nodev is removed, since the device nodes runh creates in /dev have to stay usable.
+fn apply_dev_tmpfs_defaults(options: &mut MountOptions) {
+	options.mount_flags.insert(MsFlags::MS_NOSUID);
+	if !options
+		.mount_flags
+		.intersects(MsFlags::MS_NOATIME | MsFlags::MS_RELATIME | MsFlags::MS_STRICTATIME)
+	{
+		options.mount_flags.insert(MsFlags::MS_STRICTATIME);
+	}
+	if options.mount_flags.contains(MsFlags::MS_NODEV) {
+		warn!("Ignoring nodev for the /dev tmpfs, as its device nodes would be unusable!");
+		options.mount_flags.remove(MsFlags::MS_NODEV);
+	}
+	if !has_data_option(&options.data, "mode") {
+		options.data = Some(match options.data.take() {
+			Some(data) => format!("{},mode=755", data),
+			None => String::from("mode=755"),
+		});
+	}
+}
+
 fn parse_mount_options(options: &[String]) -> MountOptions {
 	let mut mount_flags = MsFlags::empty();
 	let mut propagation_flags = MsFlags::empty();
@@ -414,31 +821,33 @@ fn parse_mount_options(options: &[String]) -> MountOptions {
 			"dirsync" => mount_flags.insert(MsFlags::MS_DIRSYNC),
 			"exec" => mount_flags.remove(MsFlags::MS_NOEXEC),
 			"iversion" => mount_flags.insert(MsFlags::MS_I_VERSION),
-			"lazytime" => unimplemented!("lazytime mount flag currently unsupported!"),
+			"lazytime" => mount_flags.insert(raw_mount_flag(libc::MS_LAZYTIME)),
 			"loud" => mount_flags.remove(MsFlags::MS_SILENT),
 			"mand" => mount_flags.insert(MsFlags::MS_MANDLOCK),
 			"noacl" => mount_flags.remove(MsFlags::MS_POSIXACL),
-			"noatime" => mount_flags.insert(MsFlags::MS_NOATIME),
+			"noatime" => set_atime_mode(&mut mount_flags, MsFlags::MS_NOATIME),
 			"nodev" => mount_flags.insert(MsFlags::MS_NODEV),
 			"nodiratime" => mount_flags.insert(MsFlags::MS_NODIRATIME),
 			"noexec" => mount_flags.insert(MsFlags::MS_NOEXEC),
 			"noiversion" => mount_flags.remove(MsFlags::MS_I_VERSION),
-			"nolazytime" => unimplemented!("nolazytime mount flag currently unsupported!"),
+			"nolazytime" => mount_flags.remove(raw_mount_flag(libc::MS_LAZYTIME)),
 			"nomand" => mount_flags.remove(MsFlags::MS_MANDLOCK),
 			"norelatime" => mount_flags.remove(MsFlags::MS_RELATIME),
 			"nostrictatime" => mount_flags.remove(MsFlags::MS_STRICTATIME),
 			"nosuid" => mount_flags.insert(MsFlags::MS_NOSUID),
+			"nosymfollow" => mount_flags.insert(raw_mount_flag(MS_NOSYMFOLLOW)),
 			"rbind" => {
 				mount_flags.insert(MsFlags::MS_BIND);
 				mount_flags.insert(MsFlags::MS_REC);
 			}
-			"relatime" => mount_flags.insert(MsFlags::MS_RELATIME),
+			"relatime" => set_atime_mode(&mut mount_flags, MsFlags::MS_RELATIME),
 			"remount" => mount_flags.insert(MsFlags::MS_REMOUNT),
 			"ro" => mount_flags.insert(MsFlags::MS_RDONLY),
 			"rw" => mount_flags.remove(MsFlags::MS_RDONLY),
 			"silent" => mount_flags.insert(MsFlags::MS_SILENT),
-			"strictatime" => mount_flags.insert(MsFlags::MS_STRICTATIME),
+			"strictatime" => set_atime_mode(&mut mount_flags, MsFlags::MS_STRICTATIME),
 			"suid" => mount_flags.remove(MsFlags::MS_NOSUID),
+			"symfollow" => mount_flags.remove(raw_mount_flag(MS_NOSYMFOLLOW)),
 			"sync" => mount_flags.insert(MsFlags::MS_SYNCHRONOUS),
 			"private" => propagation_flags.insert(MsFlags::MS_PRIVATE),
 			"shared" => propagation_flags.insert(MsFlags::MS_SHARED),
@@ -460,7 +869,10 @@ fn parse_mount_options(options: &[String]) -> MountOptions {
 				propagation_flags.insert(MsFlags::MS_UNBINDABLE);
 				propagation_flags.insert(MsFlags::MS_REC)
 			}
+			// Not a kernel option, handled by configure_mounts after the bind mount
+			"recursiveChown" => (),
 			"tmpcopyup" => unimplemented!("tmpcopyup mount flag currently unsupported!"),
+			// Filesystem-specific options like nouuid (xfs) or size=64m (tmpfs) are left to the filesystem
 			_ => {
 				debug!(
 					"Mount option {} not recognized, adding it to mount data string",
@@ -474,6 +886,283 @@ fn parse_mount_options(options: &[String]) -> MountOptions {
 	MountOptions {
 		mount_flags,
 		propagation_flags,
-		data: Some(data.join(",")),
+		data: if data.is_empty() {
+			None
+		} else {
+			Some(data.join(","))
+		},
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn parse(options: &[&str]) -> MountOptions {
+		parse_mount_options(
+			&options
+				.iter()
+				.map(|option| option.to_string())
+				.collect::<Vec<String>>(),
+		)
+	}
+
+	#[test]
+	fn unknown_options_are_passed_as_data() {
+		let options = parse(&["nosuid", "nouuid", "rbind", "rslave", "logbufs=8", "ro"]);
+		assert_eq!(
+			options.mount_flags,
+			MsFlags::MS_NOSUID | MsFlags::MS_BIND | MsFlags::MS_REC | MsFlags::MS_RDONLY
+		);
+		assert_eq!(
+			options.propagation_flags,
+			MsFlags::MS_SLAVE | MsFlags::MS_REC
+		);
+		assert_eq!(options.data.as_deref(), Some("nouuid,logbufs=8"));
+	}
+
+	#[test]
+	fn known_options_leave_no_data() {
+		let options = parse(&["nodev", "noexec", "private", "recursiveChown"]);
+		assert_eq!(options.mount_flags, MsFlags::MS_NODEV | MsFlags::MS_NOEXEC);
+		assert_eq!(options.propagation_flags, MsFlags::MS_PRIVATE);
+		assert_eq!(options.data, None);
+		assert_eq!(parse(&[]).data, None);
+	}
+
+	#[test]
+	fn last_atime_mode_wins() {
+		let atime_modes = MsFlags::MS_NOATIME | MsFlags::MS_RELATIME | MsFlags::MS_STRICTATIME;
+		for (options, expected) in [
+			(&["noatime", "relatime"][..], MsFlags::MS_RELATIME),
+			(&["relatime", "noatime"][..], MsFlags::MS_NOATIME),
+			(&["noatime", "strictatime"][..], MsFlags::MS_STRICTATIME),
+			(&["strictatime", "nostrictatime"][..], MsFlags::empty()),
+			(&["relatime", "norelatime"][..], MsFlags::empty()),
+			(&["noatime", "atime"][..], MsFlags::empty()),
+		] {
+			assert_eq!(
+				parse(options).mount_flags & atime_modes,
+				expected,
+				"{:?}",
+				options
+			);
+		}
+	}
+
+	#[test]
+	fn diratime_is_independent_of_atime_mode() {
+		let options = parse(&["nodiratime", "relatime", "ro"]);
+		assert_eq!(
+			options.mount_flags,
+			MsFlags::MS_NODIRATIME | MsFlags::MS_RELATIME | MsFlags::MS_RDONLY
+		);
+		let options = parse(&["nodiratime", "strictatime", "diratime"]);
+		assert_eq!(options.mount_flags, MsFlags::MS_STRICTATIME);
+	}
+
+	#[test]
+	fn lazytime_is_kept_as_mount_flag() {
+		let options = parse(&["lazytime", "noatime"]);
+		assert_eq!(
+			options.mount_flags.bits() & libc::MS_LAZYTIME,
+			libc::MS_LAZYTIME
+		);
+		assert!(options.mount_flags.contains(MsFlags::MS_NOATIME));
+		assert_eq!(options.data, None);
+
+		let options = parse(&["lazytime", "nolazytime"]);
+		assert_eq!(options.mount_flags.bits() & libc::MS_LAZYTIME, 0);
+	}
+
+	#[test]
+	fn nosymfollow_is_kept_as_mount_flag() {
+		let options = parse(&["nosymfollow", "ro"]);
+		assert_eq!(options.mount_flags.bits() & MS_NOSYMFOLLOW, MS_NOSYMFOLLOW);
+		assert!(options.mount_flags.contains(MsFlags::MS_RDONLY));
+		assert_eq!(parse(&["nosymfollow", "symfollow"]).mount_flags.bits(), 0);
+	}
+
+	#[test]
+	fn dev_tmpfs_gets_runc_defaults() {
+		let mut options = parse(&[]);
+		apply_dev_tmpfs_defaults(&mut options);
+		assert_eq!(
+			options.mount_flags,
+			MsFlags::MS_NOSUID | MsFlags::MS_STRICTATIME
+		);
+		assert!(!options.mount_flags.contains(MsFlags::MS_NOEXEC));
+		assert_eq!(options.data.as_deref(), Some("mode=755"));
+
+		// The /dev mount of the default runc spec
+		let mut options = parse(&["nosuid", "strictatime", "mode=755", "size=65536k"]);
+		apply_dev_tmpfs_defaults(&mut options);
+		assert_eq!(
+			options.mount_flags,
+			MsFlags::MS_NOSUID | MsFlags::MS_STRICTATIME
+		);
+		assert_eq!(options.data.as_deref(), Some("mode=755,size=65536k"));
+	}
+
+	#[test]
+	fn dev_tmpfs_keeps_explicit_options() {
+		let mut options = parse(&["relatime", "nodev", "noexec", "size=1m", "mode=700"]);
+		apply_dev_tmpfs_defaults(&mut options);
+		assert_eq!(
+			options.mount_flags,
+			MsFlags::MS_NOSUID | MsFlags::MS_RELATIME | MsFlags::MS_NOEXEC
+		);
+		assert_eq!(options.data.as_deref(), Some("size=1m,mode=700"));
+
+		// Without a mode, mode=755 is appended to the filesystem options
+		let mut options = parse(&["size=1m"]);
+		apply_dev_tmpfs_defaults(&mut options);
+		assert_eq!(options.data.as_deref(), Some("size=1m,mode=755"));
+	}
+
+	#[test]
+	fn bind_placeholders_get_explicit_modes() {
+		let dir = tempfile::tempdir().unwrap();
+		let file = dir.path().join("etc/resolv.conf");
+		let nested_dir = dir.path().join("data/cache");
+		std::fs::create_dir(dir.path().join("etc")).unwrap();
+
+		// The umask is per process, so both placeholders are created in one test
+		let old_umask = nix::sys::stat::umask(Mode::from_bits_truncate(0o077));
+		create_bind_placeholder(&file, false);
+		create_bind_placeholder(&nested_dir, true);
+		nix::sys::stat::umask(old_umask);
+
+		let mode = |path: &Path| std::fs::metadata(path).unwrap().mode() & 0o7777;
+		assert!(std::fs::metadata(&file).unwrap().is_file());
+		assert_eq!(mode(&file), BIND_PLACEHOLDER_FILE_MODE);
+		assert!(nested_dir.is_dir());
+		assert_eq!(mode(&nested_dir), BIND_PLACEHOLDER_DIR_MODE);
+	}
+
+	#[test]
+	fn existing_bind_destination_is_kept() {
+		let dir = tempfile::tempdir().unwrap();
+		let file = dir.path().join("hostname");
+		std::fs::write(&file, "container").unwrap();
+		std::fs::set_permissions(&file, Permissions::from_mode(0o600)).unwrap();
+
+		create_bind_placeholder(&file, false);
+		assert_eq!(std::fs::read_to_string(&file).unwrap(), "container");
+		assert_eq!(std::fs::metadata(&file).unwrap().mode() & 0o7777, 0o600);
+	}
+
+	/// Mounts a new tmpfs at `base/name` like `configure_mounts` does for a tmpfs mount entry
+	fn mount_new_tmpfs(base: &Path, name: &str, options: &[&str]) -> PathBuf {
+		let destination = base.join(name);
+		std::fs::create_dir(&destination).unwrap();
+		let mount_dest = Path::new("/").join(name);
+		let options = parse(options);
+		mount_with_flags(
+			"tmpfs",
+			Path::new("tmpfs"),
+			&mount_dest,
+			&destination,
+			options.clone(),
+			None,
+		);
+		remount_if_read_only(
+			"tmpfs",
+			Path::new("tmpfs"),
+			&mount_dest,
+			&destination,
+			options,
+		);
+		destination
+	}
+
+	fn id_mapping(container_id: u32, host_id: u32, size: u32) -> runtime::LinuxIdMapping {
+		runtime::LinuxIdMappingBuilder::default()
+			.container_id(container_id)
+			.host_id(host_id)
+			.size(size)
+			.build()
+			.unwrap()
+	}
+
+	fn owner(path: &Path) -> (u32, u32) {
+		let metadata = std::fs::symlink_metadata(path).unwrap();
+		(metadata.uid(), metadata.gid())
+	}
+
+	#[test]
+	fn ownership_is_shifted_to_host_ids() {
+		if !nix::unistd::geteuid().is_root() {
+			eprintln!("Skipping test that needs root to chown");
+			return;
+		}
+		let dir = tempfile::tempdir().unwrap();
+		let tree = dir.path().join("tree");
+		std::fs::create_dir_all(tree.join("sub")).unwrap();
+		std::fs::write(tree.join("sub/file"), "").unwrap();
+		std::os::unix::fs::symlink("/etc/passwd", tree.join("link")).unwrap();
+		// IDs outside of the mappings are left alone
+		nix::unistd::chown(
+			&tree.join("sub"),
+			Some(nix::unistd::Uid::from_raw(5000)),
+			Some(nix::unistd::Gid::from_raw(5000)),
+		)
+		.unwrap();
+
+		shift_ownership(
+			&tree,
+			&[id_mapping(0, 100000, 1000)],
+			&[id_mapping(0, 200000, 1000)],
+		);
+		assert_eq!(owner(&tree), (100000, 200000));
+		assert_eq!(owner(&tree.join("sub/file")), (100000, 200000));
+		assert_eq!(owner(&tree.join("link")), (100000, 200000));
+		assert_eq!(owner(&tree.join("sub")), (5000, 5000));
+		// The link target is not followed
+		assert_eq!(owner(Path::new("/etc/passwd")), (0, 0));
+	}
+
+	#[test]
+	fn ownership_is_not_shifted_across_mounts() {
+		rootfs::tests::in_mount_namespace(|base| {
+			let tree = base.join("tree");
+			std::fs::create_dir(&tree).unwrap();
+			std::fs::write(tree.join("file"), "").unwrap();
+			// E.g. a host directory bind-mounted into the tree
+			let mount = tree.join("mount");
+			rootfs::tests::mount_tmpfs(&mount);
+			std::fs::write(mount.join("host_file"), "").unwrap();
+
+			shift_ownership(&tree, &[id_mapping(0, 100000, 1)], &[]);
+			assert_eq!(owner(&tree.join("file")), (100000, 0));
+			assert_eq!(owner(&mount), (0, 0));
+			assert_eq!(owner(&mount.join("host_file")), (0, 0));
+		});
+	}
+
+	#[test]
+	fn read_only_tmpfs_rejects_writes() {
+		rootfs::tests::in_mount_namespace(|base| {
+			let destination = mount_new_tmpfs(base, "ro", &["ro", "nosuid", "mode=700"]);
+			let err = std::fs::write(destination.join("file"), "data").unwrap_err();
+			assert_eq!(err.raw_os_error(), Some(libc::EROFS));
+			// The mode is applied by the initial mount, before the remount
+			assert_eq!(
+				std::fs::metadata(&destination).unwrap().mode() & 0o7777,
+				0o700
+			);
+		});
+	}
+
+	#[test]
+	fn writable_tmpfs_is_not_remounted() {
+		rootfs::tests::in_mount_namespace(|base| {
+			let destination = mount_new_tmpfs(base, "rw", &["nosuid", "mode=755"]);
+			std::fs::write(destination.join("file"), "data").unwrap();
+			assert_eq!(
+				std::fs::read_to_string(destination.join("file")).unwrap(),
+				"data"
+			);
+		});
 	}
 }
@@ -1,6 +1,14 @@
+use crate::error::{self, RunhError};
 use crate::flags;
+use crate::paths;
+use crate::syscall::traced_syscall;
+use nix::mount::MsFlags;
 use oci_spec::runtime;
-use std::{fs::File, os::unix::prelude::AsRawFd};
+use std::ffi::CString;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::{fmt, fs::File, os::unix::prelude::AsRawFd};
 
 struct ConfiguredNamespace<'a>(File, &'a runtime::LinuxNamespace);
 
@@ -29,7 +37,259 @@ pub fn join_namespaces(namespaces: &[runtime::LinuxNamespace]) {
 	for ns_config in &configured_ns {
 		debug!("joining namespace {:?}", ns_config.1);
 		let flags = flags::get_cloneflag(ns_config.1.typ());
-		nix::sched::setns(ns_config.0.as_raw_fd(), flags)
-			.unwrap_or_else(|_| panic!("Failed to join NS {:?}", ns_config.1));
+		traced_syscall(
+			"setns",
+			format_args!("{}, {:?}", ns_config.0.as_raw_fd(), flags),
+			|| nix::sched::setns(ns_config.0.as_raw_fd(), flags),
+		)
+		.unwrap_or_else(|_| panic!("Failed to join NS {:?}", ns_config.1));
+	}
+}
+
+/// Name of the namespace below /proc/<pid>/ns
+fn get_proc_ns_name(typ: runtime::LinuxNamespaceType) -> &'static str {
+	match typ {
+		runtime::LinuxNamespaceType::Cgroup => "cgroup",
+		runtime::LinuxNamespaceType::Ipc => "ipc",
+		runtime::LinuxNamespaceType::Mount => "mnt",
+		runtime::LinuxNamespaceType::Network => "net",
+		runtime::LinuxNamespaceType::Pid => "pid",
+		runtime::LinuxNamespaceType::User => "user",
+		runtime::LinuxNamespaceType::Uts => "uts",
+	}
+}
+
+/// Bind-mounts the namespaces created for the process `pid` to files in `pin_dir` (like
+/// `ip netns` does), so they can still be entered after the process exited. Joined namespaces
+/// are not pinned, as they are owned by someone else. The pins are mounts in the container
+/// directory, which are removed by `runh delete` along with all other mounts there.
+pub fn pin_namespaces(pid: i32, namespaces: &[runtime::LinuxNamespace], pin_dir: &Path) {
+	std::fs::create_dir_all(pin_dir)
+		.unwrap_or_else(|err| panic!("Could not create namespace pin dir {:?}: {}", pin_dir, err));
+
+	for ns in namespaces.iter().filter(|ns| ns.path().is_none()) {
+		let name = get_proc_ns_name(ns.typ());
+		let source = PathBuf::from(format!("/proc/{}/ns/{}", pid, name));
+		let pin = pin_dir.join(name);
+		File::create(&pin)
+			.unwrap_or_else(|err| panic!("Could not create namespace pin {:?}: {}", pin, err));
+		traced_syscall(
+			"mount",
+			format_args!("{:?}, {:?}, \"bind\", MS_BIND", source, pin),
+			|| {
+				nix::mount::mount::<Path, Path, str, str>(
+					Some(&source),
+					&pin,
+					Some("bind"),
+					MsFlags::MS_BIND,
+					None,
+				)
+			},
+		)
+		.unwrap_or_else(|err| panic!("Could not pin {} namespace at {:?}: {}", name, pin, err));
+		info!("Pinned {} namespace of the container at {:?}", name, pin);
+	}
+}
+
+/// The kernel accepts at most this many lines in /proc/<pid>/{uid,gid}_map
+const MAX_ID_MAPPINGS: usize = 340;
+
+/// Problems of an id mapping that the kernel would only report as EINVAL on write
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MappingError {
+	TooMany {
+		kind: &'static str,
+		count: usize,
+	},
+	EmptyRange {
+		kind: &'static str,
+		container_id: u32,
+	},
+	RangeOverflow {
+		kind: &'static str,
+		start: u32,
+		size: u32,
+	},
+	Overlap {
+		kind: &'static str,
+		side: &'static str,
+		first: (u32, u32),
+		second: (u32, u32),
+	},
+}
+
+impl fmt::Display for MappingError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			MappingError::TooMany { kind, count } => write!(
+				f,
+				"{} has {} entries, but the kernel allows at most {}",
+				kind, count, MAX_ID_MAPPINGS
+			),
+			MappingError::EmptyRange { kind, container_id } => write!(
+				f,
+				"{} entry for container id {} has size 0",
+				kind, container_id
+			),
+			MappingError::RangeOverflow { kind, start, size } => write!(
+				f,
+				"{} range starting at {} with size {} exceeds the id space",
+				kind, start, size
+			),
+			MappingError::Overlap {
+				kind,
+				side,
+				first,
+				second,
+			} => write!(
+				f,
+				"{} {} ranges [{}, {}) and [{}, {}) overlap",
+				kind,
+				side,
+				first.0,
+				first.0 as u64 + first.1 as u64,
+				second.0,
+				second.0 as u64 + second.1 as u64
+			),
+		}
+	}
+}
+
+fn check_overlaps(
+	kind: &'static str,
+	side: &'static str,
+	mut ranges: Vec<(u32, u32)>,
+) -> Result<(), MappingError> {
+	ranges.sort_unstable();
+	for pair in ranges.windows(2) {
+		if pair[0].0 as u64 + pair[0].1 as u64 > pair[1].0 as u64 {
+			return Err(MappingError::Overlap {
+				kind,
+				side,
+				first: pair[0],
+				second: pair[1],
+			});
+		}
+	}
+	Ok(())
+}
+
+/// Validates the `uidMappings` or `gidMappings` (given as `kind`) of a spec and returns them
+/// sorted by container id, the order in which they are written to the id map.
+pub fn check_id_mappings(
+	kind: &'static str,
+	mappings: &[runtime::LinuxIdMapping],
+) -> Result<Vec<runtime::LinuxIdMapping>, MappingError> {
+	if mappings.len() > MAX_ID_MAPPINGS {
+		return Err(MappingError::TooMany {
+			kind,
+			count: mappings.len(),
+		});
+	}
+
+	for mapping in mappings {
+		if mapping.size() == 0 {
+			return Err(MappingError::EmptyRange {
+				kind,
+				container_id: mapping.container_id(),
+			});
+		}
+		for start in [mapping.container_id(), mapping.host_id()] {
+			if start as u64 + mapping.size() as u64 > u32::MAX as u64 + 1 {
+				return Err(MappingError::RangeOverflow {
+					kind,
+					start,
+					size: mapping.size(),
+				});
+			}
+		}
+	}
+
+	check_overlaps(
+		kind,
+		"container",
+		mappings
+			.iter()
+			.map(|mapping| (mapping.container_id(), mapping.size()))
+			.collect(),
+	)?;
+	check_overlaps(
+		kind,
+		"host",
+		mappings
+			.iter()
+			.map(|mapping| (mapping.host_id(), mapping.size()))
+			.collect(),
+	)?;
+
+	let mut sorted = mappings.to_vec();
+	sorted.sort_by_key(|mapping| mapping.container_id());
+	Ok(sorted)
+}
+
+/// Whether an unprivileged process can write the mappings itself. The kernel only allows a
+/// single line that maps the own id, everything else needs newuidmap/newgidmap.
+fn needs_subid_helper(mappings: &[runtime::LinuxIdMapping], own_id: u32) -> bool {
+	match mappings {
+		[] => false,
+		[mapping] => mapping.size() != 1 || mapping.host_id() != own_id,
+		_ => true,
+	}
+}
+
+/// newuidmap/newgidmap need either the setuid bit or the setuid/setgid file capabilities
+fn has_setuid_privileges(binary: &Path) -> Result<(), String> {
+	let metadata = std::fs::metadata(binary)
+		.map_err(|err| format!("{:?} is not accessible: {}", binary, err))?;
+	if metadata.permissions().mode() & libc::S_ISUID != 0 {
+		return Ok(());
+	}
+
+	let path = CString::new(binary.as_os_str().as_bytes()).unwrap();
+	let name = CString::new("security.capability").unwrap();
+	let size = unsafe { libc::getxattr(path.as_ptr(), name.as_ptr(), std::ptr::null_mut(), 0) };
+	if size > 0 {
+		Ok(())
+	} else {
+		Err(format!(
+			"{:?} has neither the setuid bit nor file capabilities",
+			binary
+		))
+	}
+}
+
+fn find_subid_helper(binary: &str) -> Result<PathBuf, String> {
+	let path = paths::find_in_path(PathBuf::from(binary), None)
+		.ok_or_else(|| String::from("it was not found in PATH"))?;
+	has_setuid_privileges(&path)?;
+	Ok(path)
+}
+
+/// Makes sure the setuid helpers needed for the id mappings of a rootless container are
+/// available, so a missing helper is reported before any namespace is set up.
+pub fn check_subid_helpers(linux_spec: &runtime::Linux) {
+	for (binary, mappings, own_id) in [
+		(
+			"newuidmap",
+			linux_spec.uid_mappings(),
+			nix::unistd::geteuid().as_raw(),
+		),
+		(
+			"newgidmap",
+			linux_spec.gid_mappings(),
+			nix::unistd::getegid().as_raw(),
+		),
+	] {
+		let mappings = match mappings {
+			Some(mappings) if needs_subid_helper(mappings, own_id) => mappings,
+			_ => continue,
+		};
+		match find_subid_helper(binary) {
+			Ok(path) => debug!("Using {:?} to write {} id mappings", path, mappings.len()),
+			Err(reason) => error::raise(RunhError::SubidHelpersMissing {
+				binary: binary.to_string(),
+				reason,
+			}),
+		}
 	}
 }
@@ -1,6 +1,11 @@
 use futures::TryStreamExt;
 use serde::{Deserialize, Serialize};
-use std::{error::Error, fmt, net::Ipv4Addr, process::Stdio};
+use std::{
+	error::Error,
+	fmt,
+	net::{IpAddr, Ipv4Addr},
+	process::Stdio,
+};
 
 #[derive(Debug)]
 struct HermitNetworkError {
@@ -36,12 +41,23 @@ impl Error for HermitNetworkError {
 	}
 }
 
+/// Brings up the loopback interface of the current network namespace and makes sure it has
+/// 127.0.0.1/8. The kernel usually assigns the address itself once lo is up.
 pub async fn set_lo_up() -> Result<(), rtnetlink::Error> {
 	let (connection, handle, _) = rtnetlink::new_connection().unwrap();
 	tokio::spawn(connection);
 	let mut links = handle.link().get().match_name("lo".to_string()).execute();
 	if let Some(link) = links.try_next().await? {
-		handle.link().set(link.header.index).up().execute().await?
+		handle.link().set(link.header.index).up().execute().await?;
+		match handle
+			.address()
+			.add(link.header.index, IpAddr::V4(Ipv4Addr::LOCALHOST), 8)
+			.execute()
+			.await
+		{
+			Err(rtnetlink::Error::NetlinkError(err)) if err.code == -libc::EEXIST => (),
+			result => result?,
+		}
 	} else {
 		panic!("Link lo not found!");
 	}
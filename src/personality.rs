@@ -0,0 +1,76 @@
+use serde::Deserialize;
+
+// The oci-spec version we use predates linux.personality,
+// so it is parsed from the raw linux object of the spec instead.
+#[derive(Clone, Debug, Deserialize)]
+pub struct Personality {
+	pub domain: String,
+	#[serde(default)]
+	pub flags: Vec<String>,
+}
+
+const PER_LINUX: libc::c_ulong = 0x0000;
+const PER_LINUX32: libc::c_ulong = 0x0008;
+
+/// Personality flags from linux/personality.h
+const PERSONALITY_FLAGS: [(&str, libc::c_ulong); 11] = [
+	("UNAME26", 0x0020000),
+	("ADDR_NO_RANDOMIZE", 0x0040000),
+	("FDPIC_FUNCPTRS", 0x0080000),
+	("MMAP_PAGE_ZERO", 0x0100000),
+	("ADDR_COMPAT_LAYOUT", 0x0200000),
+	("READ_IMPLIES_EXEC", 0x0400000),
+	("ADDR_LIMIT_32BIT", 0x0800000),
+	("SHORT_INODE", 0x1000000),
+	("WHOLE_SECONDS", 0x2000000),
+	("STICKY_TIMEOUTS", 0x4000000),
+	("ADDR_LIMIT_3GB", 0x8000000),
+];
+
+fn parse_domain(domain: &str) -> Result<libc::c_ulong, String> {
+	match domain {
+		"LINUX" => Ok(PER_LINUX),
+		"LINUX32" => Ok(PER_LINUX32),
+		_ => Err(format!(
+			"Unknown personality domain {}! Only LINUX and LINUX32 are supported",
+			domain
+		)),
+	}
+}
+
+fn parse_flag(flag: &str) -> Result<libc::c_ulong, String> {
+	PERSONALITY_FLAGS
+		.iter()
+		.find(|(name, _)| *name == flag)
+		.map(|(_, value)| *value)
+		.ok_or_else(|| format!("Unknown personality flag {}!", flag))
+}
+
+/// Checks the domain and flags of a personality without applying it
+pub fn validate_personality(personality: &Personality) -> Result<(), String> {
+	parse_domain(&personality.domain)?;
+	for flag in &personality.flags {
+		parse_flag(flag)?;
+	}
+	Ok(())
+}
+
+/// Sets the execution domain of the init, which is inherited by the entrypoint
+pub fn set_personality(personality: &Personality) {
+	let mut persona = parse_domain(&personality.domain).unwrap_or_else(|err| panic!("{}", err));
+	for flag in &personality.flags {
+		persona |= parse_flag(flag).unwrap_or_else(|err| panic!("{}", err));
+	}
+
+	debug!(
+		"Setting personality {} with flags {:?}",
+		personality.domain, personality.flags
+	);
+	if unsafe { libc::personality(persona) } == -1 {
+		panic!(
+			"Could not set personality {}: {}",
+			personality.domain,
+			std::io::Error::last_os_error()
+		);
+	}
+}
@@ -0,0 +1,68 @@
+use oci_spec::runtime::LinuxIntelRdt;
+use std::{
+	fs::{self, OpenOptions},
+	io::Write,
+	path::{Path, PathBuf},
+};
+
+const RESCTRL_ROOT: &str = "/sys/fs/resctrl";
+
+fn get_group_path(id: &str) -> PathBuf {
+	Path::new(RESCTRL_ROOT).join(id)
+}
+
+fn write_schema(schemata_path: &Path, schema: &str) {
+	// Every line of a schema is a separate resource, which the kernel expects as separate writes
+	for line in schema.lines().filter(|line| !line.trim().is_empty()) {
+		let mut schemata = OpenOptions::new()
+			.write(true)
+			.open(schemata_path)
+			.unwrap_or_else(|_| panic!("Could not open resctrl schemata at {:?}!", schemata_path));
+		schemata
+			.write_all(format!("{}\n", line.trim()).as_bytes())
+			.unwrap_or_else(|err| panic!("Could not write resctrl schema {}: {}", line, err));
+	}
+}
+
+/// Creates a resctrl group for the container, applies the cache and memory bandwidth
+/// schemata from the spec and moves the container init into the group.
+pub fn setup_group(id: &str, intel_rdt: &LinuxIntelRdt, pid: i32) {
+	if intel_rdt.l3_cache_schema().is_none() && intel_rdt.mem_bw_schema().is_none() {
+		return;
+	}
+
+	if !Path::new(RESCTRL_ROOT).join("info").exists() {
+		panic!(
+			"Container requests Intel RDT, but resctrl is not mounted at {}!",
+			RESCTRL_ROOT
+		);
+	}
+
+	let group_path = get_group_path(id);
+	debug!("Creating resctrl group at {:?}", group_path);
+	fs::create_dir(&group_path)
+		.unwrap_or_else(|err| panic!("Could not create resctrl group {:?}: {}", group_path, err));
+
+	let schemata_path = group_path.join("schemata");
+	if let Some(l3_cache_schema) = intel_rdt.l3_cache_schema() {
+		write_schema(&schemata_path, l3_cache_schema);
+	}
+	if let Some(mem_bw_schema) = intel_rdt.mem_bw_schema() {
+		write_schema(&schemata_path, mem_bw_schema);
+	}
+
+	fs::write(group_path.join("tasks"), pid.to_string())
+		.unwrap_or_else(|err| panic!("Could not move pid {} into resctrl group: {}", pid, err));
+}
+
+/// Removes the resctrl group of a container, if there is one.
+/// Remaining tasks are moved back to the default group by the kernel.
+pub fn remove_group(id: &str) {
+	let group_path = get_group_path(id);
+	if group_path.exists() {
+		debug!("Removing resctrl group at {:?}", group_path);
+		if let Err(err) = fs::remove_dir(&group_path) {
+			warn!("Could not remove resctrl group {:?}: {}", group_path, err);
+		}
+	}
+}
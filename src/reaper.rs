@@ -0,0 +1,195 @@
+use crate::state::{self, ExitStatus};
+use nix::sys::signal::{self, SigSet, SigmaskHow, Signal};
+use nix::sys::wait::{waitpid, WaitPidFlag, WaitStatus};
+use nix::unistd::{ForkResult, Pid};
+use std::fmt;
+use std::fs::File;
+use std::os::unix::process::CommandExt;
+use std::process::Command;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+const DEFAULT_RESTART_GRACE_PERIOD: Duration = Duration::from_secs(10);
+const INITIAL_RESTART_BACKOFF: Duration = Duration::from_millis(100);
+const MAX_RESTART_BACKOFF: Duration = Duration::from_secs(10);
+
+/// Forwarded signals that ask the entrypoint to stop. Others (e.g. SIGWINCH after a terminal
+/// resize or SIGUSR1) leave the restart policy in effect.
+const TERMINATING_SIGNALS: [Signal; 4] = [
+	Signal::SIGHUP,
+	Signal::SIGINT,
+	Signal::SIGQUIT,
+	Signal::SIGTERM,
+];
+
+/// Restart policy of the init wrapper, given as `on-failure:N[:GRACE_SECONDS]`. An entrypoint
+/// that exits non-zero within the grace period after its start is restarted up to N times.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RestartPolicy {
+	pub max_restarts: u32,
+	pub grace_period: Duration,
+}
+
+impl FromStr for RestartPolicy {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let mut parts = s.split(':');
+		if parts.next() != Some("on-failure") {
+			return Err(format!(
+				"Unknown restart policy {}! Only on-failure:N[:GRACE_SECONDS] is supported",
+				s
+			));
+		}
+		let max_restarts = parts
+			.next()
+			.ok_or_else(|| format!("Restart policy {} is missing the restart count", s))?
+			.parse()
+			.map_err(|_| format!("Restart count of policy {} is not a number", s))?;
+		let grace_period = match parts.next() {
+			Some(grace) => Duration::from_secs(
+				grace
+					.parse()
+					.map_err(|_| format!("Grace period of policy {} is not a number", s))?,
+			),
+			None => DEFAULT_RESTART_GRACE_PERIOD,
+		};
+		if parts.next().is_some() {
+			return Err(format!("Restart policy {} has too many fields", s));
+		}
+		Ok(RestartPolicy {
+			max_restarts,
+			grace_period,
+		})
+	}
+}
+
+impl fmt::Display for RestartPolicy {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(
+			f,
+			"on-failure:{}:{}",
+			self.max_restarts,
+			self.grace_period.as_secs()
+		)
+	}
+}
+
+/// Signals that are raised by faults of the init itself and must not be blocked
+const SYNCHRONOUS_SIGNALS: [Signal; 7] = [
+	Signal::SIGABRT,
+	Signal::SIGBUS,
+	Signal::SIGFPE,
+	Signal::SIGILL,
+	Signal::SIGSEGV,
+	Signal::SIGSYS,
+	Signal::SIGTRAP,
+];
+
+fn get_forwarded_signals() -> SigSet {
+	let mut signals = SigSet::all();
+	for sig in SYNCHRONOUS_SIGNALS {
+		signals.remove(sig);
+	}
+	signals
+}
+
+/// Reaps all exited children. Returns the exit status of the entrypoint, if it was among them.
+fn reap_children(entrypoint: Pid) -> Option<ExitStatus> {
+	let mut exit_status = None;
+	loop {
+		match waitpid(Pid::from_raw(-1), Some(WaitPidFlag::WNOHANG)) {
+			Ok(WaitStatus::StillAlive) | Err(_) => break,
+			Ok(status) if status.pid() == Some(entrypoint) => {
+				exit_status = ExitStatus::from_wait(status).or(exit_status)
+			}
+			Ok(_) => {}
+		}
+	}
+	exit_status
+}
+
+fn spawn_entrypoint(cmd: &mut Command, old_mask: &SigSet) -> Pid {
+	match unsafe { nix::unistd::fork() } {
+		Ok(ForkResult::Child) => {
+			signal::sigprocmask(SigmaskHow::SIG_SETMASK, Some(old_mask), None)
+				.expect("Could not restore signal mask of the entrypoint!");
+			let error = cmd.exec();
+			panic!("exec failed with error {}", error)
+		}
+		Ok(ForkResult::Parent { child }) => child,
+		Err(err) => panic!("Could not fork entrypoint from init wrapper: {}", err),
+	}
+}
+
+/// Runs the entrypoint as child of a minimal init, which stays PID 1 of the container.
+/// All signals sent to the init are forwarded to the entrypoint and orphaned processes are
+/// reaped. The init exits with the exit code of the entrypoint, unless the restart policy
+/// asks for the entrypoint to be started again. The final exit status of the entrypoint is
+/// written to `exit_status_file` before.
+pub fn run_as_init(
+	mut cmd: Command,
+	restart_policy: Option<RestartPolicy>,
+	exit_status_file: Option<File>,
+) -> ! {
+	let forwarded_signals = get_forwarded_signals();
+	let mut old_mask = SigSet::empty();
+	signal::sigprocmask(
+		SigmaskHow::SIG_BLOCK,
+		Some(&forwarded_signals),
+		Some(&mut old_mask),
+	)
+	.expect("Could not block signals in init wrapper!");
+
+	let mut entrypoint = spawn_entrypoint(&mut cmd, &old_mask);
+	let mut started_at = Instant::now();
+	let mut restarts = 0;
+	let mut backoff = INITIAL_RESTART_BACKOFF;
+	// An entrypoint that is stopped through a signal from outside is never restarted
+	let mut stopping = false;
+
+	loop {
+		let sig = match forwarded_signals.wait() {
+			Ok(sig) => sig,
+			Err(_) => continue,
+		};
+
+		if sig == Signal::SIGCHLD {
+			if let Some(exit_status) = reap_children(entrypoint) {
+				let exit_code = exit_status.code();
+				match restart_policy {
+					Some(policy)
+						if exit_code != 0
+							&& !stopping && restarts < policy.max_restarts
+							&& started_at.elapsed() < policy.grace_period =>
+					{
+						restarts += 1;
+						warn!(
+							"Entrypoint exited with code {}. Restarting it in {:?} ({}/{})...",
+							exit_code, backoff, restarts, policy.max_restarts
+						);
+						std::thread::sleep(backoff);
+						backoff = std::cmp::min(backoff * 2, MAX_RESTART_BACKOFF);
+						entrypoint = spawn_entrypoint(&mut cmd, &old_mask);
+						started_at = Instant::now();
+					}
+					_ => {
+						if let Some(file) = &exit_status_file {
+							if let Err(err) = state::write_exit_status(file, &exit_status) {
+								warn!("Could not persist exit status of entrypoint: {}", err);
+							}
+						}
+						std::process::exit(exit_code)
+					}
+				}
+			}
+		} else if let Err(err) = signal::kill(entrypoint, sig) {
+			// The entrypoint may already have exited, in which case the next SIGCHLD ends the init
+			if err != nix::errno::Errno::ESRCH {
+				panic!("Could not forward signal {} to entrypoint: {}", sig, err);
+			}
+		} else if TERMINATING_SIGNALS.contains(&sig) {
+			stopping = true;
+		}
+	}
+}
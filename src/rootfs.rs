@@ -1,6 +1,7 @@
 use std::{
+	ffi::CString,
 	fs::OpenOptions,
-	os::unix::prelude::{AsRawFd, OpenOptionsExt},
+	os::unix::prelude::{AsRawFd, OpenOptionsExt, OsStrExt},
 	path::Path,
 	path::PathBuf,
 };
@@ -9,6 +10,61 @@ use nix::mount::{MntFlags, MsFlags};
 use oci_spec::runtime::Spec;
 use path_clean::PathClean;
 
+use crate::mountinfo;
+use crate::mounts::{self, MountOptions};
+use crate::syscall::traced_syscall;
+
+/// Resolves `root.path` of a spec. Relative paths are relative to the bundle, absolute paths
+/// are used verbatim and may point anywhere outside of the bundle.
+pub fn resolve_root_path(root_path: &Path, bundle: &Path) -> std::io::Result<PathBuf> {
+	std::fs::canonicalize(if root_path.is_absolute() {
+		root_path.to_path_buf()
+	} else {
+		bundle.join(root_path)
+	})
+}
+
+/// Mounts a root that is not a directory (e.g. the block device of a container image) at
+/// `target`, so that it can be used like a directory root afterwards.
+pub fn mount_root_source(source: &Path, fs_type: Option<&str>, target: &Path, read_only: bool) {
+	let fs_type = fs_type.unwrap_or_else(|| {
+		panic!(
+			"root.path {:?} is not a directory, so its filesystem type has to be given with --rootfs-type!",
+			source
+		)
+	});
+	mounts::create_all_dirs(target);
+
+	let flags = if read_only {
+		MsFlags::MS_RDONLY
+	} else {
+		MsFlags::empty()
+	};
+	debug!(
+		"Mounting root {:?} with type {} at {:?}",
+		source, fs_type, target
+	);
+	traced_syscall(
+		"mount",
+		format_args!("{:?}, {:?}, {:?}, {:?}", source, target, fs_type, flags),
+		|| {
+			nix::mount::mount::<Path, Path, str, str>(
+				Some(source),
+				target,
+				Some(fs_type),
+				flags,
+				None,
+			)
+		},
+	)
+	.unwrap_or_else(|err| {
+		panic!(
+			"Could not mount root {:?} with type {}: {}",
+			source, fs_type, err
+		)
+	});
+}
+
 // This function should be equivalent to cyphar/filepath-securejoin/SecureJoinVFS
 pub fn resolve_in_rootfs(destination_rel: &Path, rootfs: &Path) -> PathBuf {
 	let mut unsafe_path = destination_rel.to_path_buf();
@@ -79,78 +135,491 @@ pub fn resolve_in_rootfs(destination_rel: &Path, rootfs: &Path) -> PathBuf {
 }
 
 pub fn mount_rootfs(spec: &Spec, rootfs_path: &Path) {
-	let mut mount_flags = MsFlags::empty();
-	mount_flags.insert(MsFlags::MS_REC);
-	mount_flags.insert(
-		match spec
-			.linux()
-			.as_ref()
-			.unwrap()
-			.rootfs_propagation()
-			.as_ref()
-			.map(|x| x.as_str())
-		{
-			Some("shared") => MsFlags::MS_SHARED,
-			Some("slave") => MsFlags::MS_SLAVE,
-			Some("private") => MsFlags::MS_PRIVATE,
-			Some("unbindable") => MsFlags::MS_UNBINDABLE,
-			Some(_) => panic!(
-				"Value of rootfsPropagation did not match any known option! Given value: {}",
-				&spec
-					.linux()
-					.as_ref()
-					.unwrap()
-					.rootfs_propagation()
-					.as_ref()
-					.unwrap()
-			),
-			None => MsFlags::MS_SLAVE,
-		},
-	);
+	let propagation = match spec
+		.linux()
+		.as_ref()
+		.unwrap()
+		.rootfs_propagation()
+		.as_ref()
+		.map(|x| x.as_str())
+	{
+		// The propagation is always applied recursively, so the r-variants are equivalent
+		Some("shared") | Some("rshared") => MsFlags::MS_SHARED,
+		Some("slave") | Some("rslave") => MsFlags::MS_SLAVE,
+		Some("private") | Some("rprivate") => MsFlags::MS_PRIVATE,
+		Some("unbindable") | Some("runbindable") => MsFlags::MS_UNBINDABLE,
+		Some(_) => panic!(
+			"Value of rootfsPropagation did not match any known option! Given value: {}",
+			&spec
+				.linux()
+				.as_ref()
+				.unwrap()
+				.rootfs_propagation()
+				.as_ref()
+				.unwrap()
+		),
+		None => MsFlags::MS_SLAVE,
+	};
 
-	nix::mount::mount::<str, str, str, str>(None, "/", None, mount_flags, None).unwrap_or_else(
-		|_| {
-			panic!(
-				"Could not mount rootfs with given MsFlags {:?}",
-				mount_flags
-			)
+	// Bind-mounting from an unbindable mount fails with EINVAL. For unbindable roots,
+	// the rootfs is therefore bound while / is a slave and only marked unbindable afterwards.
+	let is_unbindable = propagation == MsFlags::MS_UNBINDABLE;
+	set_mount_propagation(
+		Path::new("/"),
+		if is_unbindable {
+			MsFlags::MS_SLAVE
+		} else {
+			propagation
 		},
+		true,
 	);
 
-	//TODO: Make parent mount private (?)
+	// pivot_root refuses to move a rootfs whose parent mount is shared
+	make_parent_mount_private(rootfs_path);
+	remove_stale_rootfs_binds(rootfs_path);
+
 	let mut bind_mount_flags = MsFlags::empty();
 	bind_mount_flags.insert(MsFlags::MS_BIND);
 	bind_mount_flags.insert(MsFlags::MS_REC);
 
 	debug!("Mounting rootfs at {:?}", rootfs_path);
 
-	nix::mount::mount::<Path, Path, str, str>(
-		Some(rootfs_path),
-		rootfs_path,
-		Some("bind"),
-		bind_mount_flags,
-		None,
+	traced_syscall(
+		"mount",
+		format_args!(
+			"{:?}, {:?}, \"bind\", {:?}",
+			rootfs_path, rootfs_path, bind_mount_flags
+		),
+		|| {
+			nix::mount::mount::<Path, Path, str, str>(
+				Some(rootfs_path),
+				rootfs_path,
+				Some("bind"),
+				bind_mount_flags,
+				None,
+			)
+		},
 	)
 	.unwrap_or_else(|_| panic!("Could not bind-mount rootfs at {:?}", &rootfs_path));
+
+	// The bind mount inherits the propagation of its source, which differs from the requested
+	// one for unbindable roots and roots below the (now private) parent mount
+	set_mount_propagation(rootfs_path, propagation, true);
+}
+
+/// Whether `mount` binds the directory it is mounted on onto itself, which is what
+/// `mount_rootfs` does. Other mounts at the rootfs path (e.g. an overlay) share neither.
+fn is_self_bind(mount: &mountinfo::MountInfoEntry, parent: &mountinfo::MountInfoEntry) -> bool {
+	mount.dev == parent.dev
+		&& mount
+			.mount_point
+			.strip_prefix(&parent.mount_point)
+			.map_or(false, |relative| mount.root == parent.root.join(relative))
+}
+
+/// Unmounts rootfs bind mounts left behind by an earlier, failed attempt, so that
+/// they are not stacked on top of each other
+fn remove_stale_rootfs_binds(rootfs_path: &Path) {
+	loop {
+		let mounts = mountinfo::read_mountinfo(None);
+		let stale = mounts.iter().rev().find(|mount| {
+			mount.mount_point == rootfs_path
+				&& mounts
+					.iter()
+					.find(|parent| parent.mount_id == mount.parent_id)
+					.map_or(false, |parent| is_self_bind(mount, parent))
+		});
+		match stale {
+			Some(mount) => {
+				warn!(
+					"Rootfs {:?} is already bind-mounted (mount {}), unmounting it first",
+					rootfs_path, mount.mount_id
+				);
+				traced_syscall(
+					"umount2",
+					format_args!("{:?}, MNT_DETACH", rootfs_path),
+					|| nix::mount::umount2(rootfs_path, MntFlags::MNT_DETACH),
+				)
+				.unwrap_or_else(|err| {
+					panic!(
+						"Could not unmount stale rootfs at {:?}: {}",
+						rootfs_path, err
+					)
+				});
+			}
+			None => break,
+		}
+	}
+}
+
+/// Makes the mount that contains the rootfs directory private. Only this mount is changed,
+/// the propagation of all other mounts stays as requested for /.
+fn make_parent_mount_private(rootfs_path: &Path) {
+	let parent_dir = rootfs_path.parent().unwrap_or(rootfs_path);
+	let parent_mount = mountinfo::read_mountinfo(None)
+		.into_iter()
+		.filter(|mount| parent_dir.starts_with(&mount.mount_point))
+		.max_by_key(|mount| mount.mount_point.components().count());
+	match parent_mount {
+		Some(mount)
+			if !mount
+				.optional_fields
+				.iter()
+				.any(|field| field.starts_with("shared:")) =>
+		{
+			debug!(
+				"Parent mount {:?} of rootfs is not shared",
+				mount.mount_point
+			)
+		}
+		Some(mount) => set_mount_propagation(&mount.mount_point, MsFlags::MS_PRIVATE, false),
+		None => warn!(
+			"Could not find the parent mount of rootfs {:?}",
+			rootfs_path
+		),
+	}
+}
+
+/// Mounts a fresh procfs for the container at `mount_dest`. The mount is always nosuid, nodev
+/// and noexec, while procfs options like `hidepid=` and `gid=` are taken from the spec.
+/// Without a PID namespace, the new procfs would expose all host processes, so this is refused.
+pub fn mount_proc(
+	rootfs: &Path,
+	mount_dest: &Path,
+	mut options: MountOptions,
+	has_pid_namespace: bool,
+) {
+	if !has_pid_namespace {
+		panic!(
+			"Refusing to mount proc at {:?} as the container has no PID namespace and would see all host processes!",
+			mount_dest
+		);
+	}
+
+	let destination_resolved = resolve_in_rootfs(mount_dest, rootfs);
+	if destination_resolved.exists() && !destination_resolved.is_dir() {
+		panic!(
+			"Could not mount {:?}! proc filesystems can only be mounted on directories!",
+			destination_resolved
+		);
+	}
+	mounts::create_all_dirs(&destination_resolved);
+
+	options.mount_flags.insert(MsFlags::MS_NOSUID);
+	options.mount_flags.insert(MsFlags::MS_NODEV);
+	options.mount_flags.insert(MsFlags::MS_NOEXEC);
+	mounts::mount_with_flags(
+		"proc",
+		Path::new("proc"),
+		mount_dest,
+		&destination_resolved,
+		options.clone(),
+		None,
+	);
+	mounts::remount_if_read_only(
+		"proc",
+		Path::new("proc"),
+		mount_dest,
+		&destination_resolved,
+		options,
+	);
+}
+
+/// Mounts a new sysfs for privileged containers. A new sysfs can only be created by the
+/// owner of the network namespace, so rootless containers (or a failing mount with EPERM)
+/// fall back to a read-only recursive bind mount of the host's /sys.
+pub fn mount_sys(rootfs: &Path, mount_dest: &Path, mut options: MountOptions, rootless: bool) {
+	let destination_resolved = resolve_in_rootfs(mount_dest, rootfs);
+	if destination_resolved.exists() && !destination_resolved.is_dir() {
+		panic!(
+			"Could not mount {:?}! sysfs filesystems can only be mounted on directories!",
+			destination_resolved
+		);
+	}
+	mounts::create_all_dirs(&destination_resolved);
+
+	options.mount_flags.insert(MsFlags::MS_NOSUID);
+	options.mount_flags.insert(MsFlags::MS_NODEV);
+	options.mount_flags.insert(MsFlags::MS_NOEXEC);
+
+	if !rootless {
+		match mounts::try_mount_with_flags(
+			"sysfs",
+			Path::new("sysfs"),
+			mount_dest,
+			&destination_resolved,
+			options.clone(),
+			None,
+		) {
+			Ok(_) => return,
+			Err(nix::errno::Errno::EPERM) => {
+				warn!("Not allowed to mount a new sysfs. Bind-mounting the host's /sys read-only instead...")
+			}
+			Err(err) => panic!(
+				"Could not mount sysfs at destination path {:?}: {}",
+				destination_resolved, err
+			),
+		}
+	}
+
+	debug!(
+		"Bind-mounting host /sys read-only at {:?}",
+		destination_resolved
+	);
+	let bind_options = MountOptions {
+		mount_flags: MsFlags::MS_BIND | MsFlags::MS_REC,
+		propagation_flags: options.propagation_flags,
+		data: None,
+	};
+	mounts::mount_with_flags(
+		"bind",
+		Path::new("/sys"),
+		mount_dest,
+		&destination_resolved,
+		bind_options,
+		None,
+	);
+
+	// The read-only flag of a bind mount only takes effect on a remount
+	let mut remount_options = options;
+	remount_options
+		.mount_flags
+		.insert(MsFlags::MS_BIND | MsFlags::MS_RDONLY);
+	mounts::remount(
+		"bind",
+		Path::new("/sys"),
+		mount_dest,
+		&destination_resolved,
+		remount_options,
+	);
+	// The remount only covers the top mount, not the submounts like cgroup or securityfs
+	make_tree_read_only(&destination_resolved);
+}
+
+/// Changes the propagation type of an existing mount. The kernel rejects propagation
+/// flags that are combined with any other mount flags, so this always has to be a
+/// separate step after the mount itself has been established.
+pub fn set_mount_propagation(path: &Path, propagation: MsFlags, recursive: bool) {
+	let mut flags = propagation;
+	if recursive {
+		flags.insert(MsFlags::MS_REC);
+	}
+
+	traced_syscall(
+		"mount",
+		format_args!("NULL, {:?}, NULL, {:?}", path, flags),
+		|| nix::mount::mount::<str, Path, str, str>(None, path, None, flags, None),
+	)
+	.unwrap_or_else(|_| {
+		panic!(
+			"Could not apply mount propagation {:?} for path {:?}",
+			flags, path
+		)
+	});
+}
+
+const MOUNT_ATTR_RDONLY: u64 = 0x1;
+const AT_RECURSIVE: libc::c_uint = 0x8000;
+
+/// `struct mount_attr` of mount_setattr(2)
+#[repr(C)]
+struct MountAttr {
+	attr_set: u64,
+	attr_clr: u64,
+	propagation: u64,
+	userns_fd: u64,
 }
 
-pub fn set_rootfs_read_only() {
+fn mount_setattr(path: &Path, recursive: bool, attr_set: u64, attr_clr: u64) -> nix::Result<()> {
+	let path = CString::new(path.as_os_str().as_bytes()).unwrap();
+	let attr = MountAttr {
+		attr_set,
+		attr_clr,
+		propagation: 0,
+		userns_fd: 0,
+	};
+	traced_syscall(
+		"mount_setattr",
+		format_args!(
+			"AT_FDCWD, {:?}, {}, {:#x}, {:#x}",
+			path,
+			if recursive { "AT_RECURSIVE" } else { "0" },
+			attr.attr_set,
+			attr.attr_clr
+		),
+		|| {
+			let ret = unsafe {
+				libc::syscall(
+					libc::SYS_mount_setattr,
+					libc::AT_FDCWD,
+					path.as_ptr(),
+					if recursive { AT_RECURSIVE } else { 0 },
+					&attr as *const MountAttr,
+					std::mem::size_of::<MountAttr>(),
+				)
+			};
+			nix::errno::Errno::result(ret).map(drop)
+		},
+	)
+}
+
+fn remount_read_only(path: &Path) {
 	let mut flags = MsFlags::MS_BIND;
 	flags.insert(MsFlags::MS_REMOUNT);
 	flags.insert(MsFlags::MS_RDONLY);
-	if nix::mount::mount::<str, str, str, str>(None, "/", None, flags, None).is_err() {
-		let stat =
-			nix::sys::statvfs::statvfs("/").expect("Could not stat / after read-only remount!");
+	if traced_syscall(
+		"mount",
+		format_args!("NULL, {:?}, NULL, {:?}", path, flags),
+		|| nix::mount::mount::<str, Path, str, str>(None, path, None, flags, None),
+	)
+	.is_err()
+	{
+		let stat = nix::sys::statvfs::statvfs(path)
+			.unwrap_or_else(|_| panic!("Could not stat {:?} after read-only remount!", path));
 
 		let mount_flags_new = MsFlags::from_bits(flags.bits() | stat.flags().bits())
 			.expect("Could not combine old and new mount flags!");
 
-		nix::mount::mount::<str, str, str, str>(None, "/", None, mount_flags_new, None)
-			.expect("Could not change / mount type!");
+		traced_syscall(
+			"mount",
+			format_args!("NULL, {:?}, NULL, {:?}", path, mount_flags_new),
+			|| nix::mount::mount::<str, Path, str, str>(None, path, None, mount_flags_new, None),
+		)
+		.unwrap_or_else(|_| panic!("Could not change {:?} mount type!", path));
 	} //The first mount should not fail unless we are in a user namespace so technically the content of the if-block is unreachable.
 }
 
-pub fn pivot_root(rootfs: &Path) {
+/// Makes the mount at `path` and all mounts below it read-only
+fn make_tree_read_only(path: &Path) {
+	match mount_setattr(path, true, MOUNT_ATTR_RDONLY, 0) {
+		Ok(_) => return,
+		Err(nix::errno::Errno::ENOSYS) => debug!(
+			"mount_setattr is not supported. Remounting every mount below {:?} read-only...",
+			path
+		),
+		Err(err) => panic!("Could not make mounts below {:?} read-only: {}", path, err),
+	}
+
+	for mount in mountinfo::read_mountinfo(None) {
+		if mount.mount_point.starts_with(path) {
+			remount_read_only(&mount.mount_point);
+		}
+	}
+}
+
+/// Makes / and all mounts below it read-only, except for the given spec mounts that were not
+/// requested to be read-only. Submounts of these spec mounts are still made read-only.
+/// On kernels with mount_setattr (>= 5.12), the whole tree is made read-only at once and the
+/// writable mounts are reverted afterwards. Otherwise, every other mount is remounted
+/// read-only on its own.
+pub fn set_rootfs_read_only(writable_mounts: &[PathBuf]) {
+	match mount_setattr(Path::new("/"), true, MOUNT_ATTR_RDONLY, 0) {
+		Ok(_) => {
+			for mount_point in writable_mounts {
+				// Mounts inherited read-only from the host (e.g. the /sys fallback) stay read-only
+				if let Err(err) = mount_setattr(mount_point, false, 0, MOUNT_ATTR_RDONLY) {
+					warn!(
+						"Could not make mount at {:?} writable again: {}",
+						mount_point, err
+					);
+				}
+			}
+			return;
+		}
+		Err(nix::errno::Errno::ENOSYS) => {
+			debug!("mount_setattr is not supported. Remounting every mount read-only...")
+		}
+		Err(err) => panic!("Could not make rootfs read-only: {}", err),
+	}
+
+	for mount in mountinfo::read_mountinfo(None) {
+		if !writable_mounts.contains(&mount.mount_point) {
+			remount_read_only(&mount.mount_point);
+		}
+	}
+}
+
+/// A step of entering the rootfs that failed
+#[derive(Debug)]
+pub struct PivotRootError {
+	pub step: &'static str,
+	pub errno: nix::errno::Errno,
+	/// Whether the root was already switched, so that the old root may still be mounted
+	/// underneath the new one
+	pub half_pivoted: bool,
+}
+
+impl std::fmt::Display for PivotRootError {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		write!(f, "{} failed: {}", self.step, self.errno)?;
+		if self.half_pivoted {
+			write!(
+				f,
+				" (the root was already switched, the old root may still be mounted below /)"
+			)?;
+		}
+		Ok(())
+	}
+}
+
+/// Tags the error of a step of pivot_root or move_root with the step name
+fn pivot_step<T>(
+	step: &'static str,
+	half_pivoted: bool,
+	result: nix::Result<T>,
+) -> Result<T, PivotRootError> {
+	result.map_err(|errno| {
+		error!("Entering the rootfs failed at {}: {}", step, errno);
+		PivotRootError {
+			step,
+			errno,
+			half_pivoted,
+		}
+	})
+}
+
+/// Fallback for pivot_root: moves the rootfs mount over / and chroots into it. Unlike
+/// pivot_root, this leaves the old root reachable below the new one for processes that can
+/// escape the chroot, so it should only be used where pivot_root is not possible.
+fn move_root(rootfs: &Path) -> Result<(), PivotRootError> {
+	pivot_step("chdir into the rootfs", false, nix::unistd::chdir(rootfs))?;
+	pivot_step(
+		"moving the rootfs over /",
+		false,
+		traced_syscall("mount", format_args!("\".\", \"/\", NULL, MS_MOVE"), || {
+			nix::mount::mount::<str, str, str, str>(Some("."), "/", None, MsFlags::MS_MOVE, None)
+		}),
+	)?;
+	pivot_step("chroot into the rootfs", true, nix::unistd::chroot("."))?;
+	pivot_step("chdir to /", true, nix::unistd::chdir("/"))
+}
+
+const RAMFS_MAGIC: i64 = 0x8584_58f6;
+const TMPFS_MAGIC: i64 = 0x0102_1994;
+
+/// Whether / is the initial ramfs (or tmpfs) of the kernel, which pivot_root cannot move
+fn root_is_initramfs() -> bool {
+	match nix::sys::statfs::statfs("/") {
+		Ok(stat) => {
+			let fs_type = stat.filesystem_type().0 as i64;
+			fs_type == RAMFS_MAGIC || fs_type == TMPFS_MAGIC
+		}
+		Err(err) => {
+			warn!("Could not determine the file system of /: {}", err);
+			false
+		}
+	}
+}
+
+/// Makes the rootfs the new / of the container. If `no_pivot` is set or / is an initramfs,
+/// which pivot_root does not support, the rootfs is moved over / instead. Every failing step
+/// is logged together with its errno.
+pub fn pivot_root(rootfs: &Path, no_pivot: bool) -> Result<(), PivotRootError> {
+	if no_pivot {
+		debug!("Using MS_MOVE and chroot instead of pivot_root");
+		return move_root(rootfs);
+	}
+
 	let old_root = OpenOptions::new()
 		.read(true)
 		.write(false)
@@ -169,17 +638,325 @@ pub fn pivot_root(rootfs: &Path) {
 
 	nix::unistd::fchdir(new_root.as_raw_fd()).expect("Could not fchdir into new root!");
 
-	nix::unistd::pivot_root(".", ".").expect("Could not pivot root!");
+	match nix::unistd::pivot_root(".", ".") {
+		Ok(_) => {}
+		Err(nix::errno::Errno::EINVAL) if root_is_initramfs() => {
+			warn!("pivot_root is not supported on an initramfs. Falling back to MS_MOVE and chroot...");
+			return move_root(rootfs);
+		}
+		Err(nix::errno::Errno::EINVAL) => {
+			return pivot_step(
+				"pivot_root (pass --no-pivot if the rootfs cannot be pivoted)",
+				false,
+				Err(nix::errno::Errno::EINVAL),
+			)
+		}
+		Err(errno) => return pivot_step("pivot_root", false, Err(errno)),
+	}
+
+	// From here on, the old root is stacked below the new one until it is unmounted
+	pivot_step(
+		"fchdir to the old root",
+		true,
+		nix::unistd::fchdir(old_root.as_raw_fd()),
+	)?;
+
+	let flags = MsFlags::MS_SLAVE | MsFlags::MS_REC;
+	pivot_step(
+		"making the old root a slave mount",
+		true,
+		traced_syscall(
+			"mount",
+			format_args!("NULL, \".\", NULL, {:?}", flags),
+			|| nix::mount::mount::<str, str, str, str>(None, ".", None, flags, None),
+		),
+	)?;
+
+	pivot_step(
+		"unmounting the old root",
+		true,
+		traced_syscall("umount2", format_args!("\".\", MNT_DETACH"), || {
+			nix::mount::umount2(".", MntFlags::MNT_DETACH)
+		}),
+	)?;
+
+	pivot_step("chdir to /", false, nix::unistd::chdir("/"))
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+	use super::*;
+	use nix::sched::CloneFlags;
+	use nix::sys::wait::{waitpid, WaitStatus};
+	use nix::unistd::ForkResult;
+
+	/// Runs `test` in a forked child with a private mount namespace, so that the propagation
+	/// changes of `mount_rootfs` never reach the host. The test gets a directory with a tmpfs
+	/// of its own. Mounting needs CAP_SYS_ADMIN, so the test is skipped unless run as root.
+	pub(crate) fn in_mount_namespace(test: fn(&Path)) {
+		if !nix::unistd::geteuid().is_root() {
+			eprintln!("Skipping test that needs root to mount");
+			return;
+		}
+		// Created outside of the child, so that it is removed again once the child is gone
+		let dir = tempfile::tempdir().unwrap();
+		let base = std::fs::canonicalize(dir.path()).unwrap();
+
+		match unsafe { nix::unistd::fork() }.unwrap() {
+			ForkResult::Child => {
+				let result = std::panic::catch_unwind(|| {
+					nix::sched::unshare(CloneFlags::CLONE_NEWNS).unwrap();
+					set_mount_propagation(Path::new("/"), MsFlags::MS_PRIVATE, true);
+					mount_tmpfs(&base);
+					test(&base);
+				});
+				unsafe { libc::_exit(if result.is_ok() { 0 } else { 1 }) };
+			}
+			ForkResult::Parent { child } => assert_eq!(
+				waitpid(child, None).unwrap(),
+				WaitStatus::Exited(child, 0),
+				"Test failed in its mount namespace"
+			),
+		}
+	}
+
+	pub(crate) fn mount_tmpfs(target: &Path) {
+		std::fs::create_dir_all(target).unwrap();
+		nix::mount::mount(
+			Some("tmpfs"),
+			target,
+			Some("tmpfs"),
+			MsFlags::empty(),
+			None::<&str>,
+		)
+		.unwrap();
+	}
+
+	fn spec_with_propagation(propagation: &str) -> Spec {
+		serde_json::from_value(serde_json::json!({
+			"ociVersion": "1.0.2",
+			"root": {"path": "rootfs"},
+			"linux": {"rootfsPropagation": propagation}
+		}))
+		.unwrap()
+	}
+
+	/// The propagation fields of the topmost mount at `path`
+	fn propagation_of(path: &Path) -> Vec<String> {
+		mountinfo::read_mountinfo(None)
+			.into_iter()
+			.rev()
+			.find(|mount| mount.mount_point == path)
+			.unwrap_or_else(|| panic!("{:?} is not mounted", path))
+			.optional_fields
+	}
+
+	fn enter_mount_namespace(ns: &std::fs::File) {
+		nix::sched::setns(ns.as_raw_fd(), CloneFlags::CLONE_NEWNS).unwrap();
+	}
+
+	/// Makes `base` shared like the host mounts usually are, then moves to a new mount
+	/// namespace for the container and sets up a rootfs at `base/<propagation>` like runh
+	/// init does. Returns the namespace of the host and the rootfs.
+	fn mount_rootfs_in_new_namespace(base: &Path, propagation: &str) -> (std::fs::File, PathBuf) {
+		set_mount_propagation(base, MsFlags::MS_SHARED, false);
+		let rootfs = base.join(propagation);
+		std::fs::create_dir_all(&rootfs).unwrap();
+
+		let host_ns = std::fs::File::open("/proc/self/ns/mnt").unwrap();
+		nix::sched::unshare(CloneFlags::CLONE_NEWNS).unwrap();
+		mount_rootfs(&spec_with_propagation(propagation), &rootfs);
+		(host_ns, rootfs)
+	}
+
+	#[test]
+	fn rootfs_gets_each_propagation() {
+		in_mount_namespace(|base| {
+			for (propagation, expected) in [
+				("shared", Some("shared:")),
+				("rshared", Some("shared:")),
+				("private", None),
+				("rprivate", None),
+				// A slave of the shared host mount the rootfs is bound from
+				("slave", Some("master:")),
+				("rslave", Some("master:")),
+				("unbindable", Some("unbindable")),
+				("runbindable", Some("unbindable")),
+			] {
+				let (host_ns, rootfs) = mount_rootfs_in_new_namespace(base, propagation);
+				let fields = propagation_of(&rootfs);
+				enter_mount_namespace(&host_ns);
+
+				match expected {
+					Some(prefix) => assert!(
+						fields.len() == 1 && fields[0].starts_with(prefix),
+						"{}: {:?}",
+						propagation,
+						fields
+					),
+					None => assert!(fields.is_empty(), "{}: {:?}", propagation, fields),
+				}
+			}
+		});
+	}
+
+	fn is_mounted(path: &Path) -> bool {
+		mountinfo::read_mountinfo(None)
+			.iter()
+			.any(|mount| mount.mount_point == path)
+	}
+
+	/// Sets up a rootfs with `propagation` in the namespace of the container, then mounts one
+	/// mount below the rootfs on either side. Returns whether the mount of the host became
+	/// visible in the container and whether the mount of the container reached the host.
+	fn propagates(base: &Path, propagation: &str) -> (bool, bool) {
+		std::fs::create_dir_all(base.join(propagation).join("from_host")).unwrap();
+		std::fs::create_dir_all(base.join(propagation).join("from_container")).unwrap();
+		let (host_ns, rootfs) = mount_rootfs_in_new_namespace(base, propagation);
+		let container_ns = std::fs::File::open("/proc/self/ns/mnt").unwrap();
+
+		mount_tmpfs(&rootfs.join("from_container"));
+		enter_mount_namespace(&host_ns);
+		let to_host = is_mounted(&rootfs.join("from_container"));
+		mount_tmpfs(&rootfs.join("from_host"));
+		enter_mount_namespace(&container_ns);
+		let to_container = is_mounted(&rootfs.join("from_host"));
+		enter_mount_namespace(&host_ns);
+		(to_container, to_host)
+	}
+
+	#[test]
+	fn rootfs_propagation_between_namespaces() {
+		in_mount_namespace(|base| {
+			// The rootfs only receives the mounts of the host, like a volume with rslave
+			assert_eq!(propagates(base, "slave"), (true, false));
+			assert_eq!(propagates(base, "rslave"), (true, false));
+			assert_eq!(propagates(base, "private"), (false, false));
+			assert_eq!(propagates(base, "unbindable"), (false, false));
+			// pivot_root needs a private parent mount, so a shared rootfs gets a peer group of
+			// its own instead of that of the host mount
+			assert_eq!(propagates(base, "shared"), (false, false));
+		});
+	}
+
+	#[test]
+	fn slave_rootfs_has_host_mount_as_master() {
+		in_mount_namespace(|base| {
+			let (host_ns, rootfs) = mount_rootfs_in_new_namespace(base, "rslave");
+			let fields = propagation_of(&rootfs);
+			enter_mount_namespace(&host_ns);
+			let host_fields = propagation_of(base);
 
-	nix::unistd::fchdir(old_root.as_raw_fd()).expect("Could not fchdir to old root!");
+			let peer_group = host_fields[0].strip_prefix("shared:").unwrap();
+			assert_eq!(fields, vec![format!("master:{}", peer_group)]);
+		});
+	}
 
-	let mut mount_flags = MsFlags::MS_SLAVE;
-	mount_flags.insert(MsFlags::MS_REC);
+	fn mounts_at(path: &Path) -> usize {
+		mountinfo::read_mountinfo(None)
+			.iter()
+			.filter(|mount| mount.mount_point == path)
+			.count()
+	}
 
-	nix::mount::mount::<str, str, str, str>(None, ".", None, mount_flags, None)
-		.expect("Could not change old_root propagation type!");
+	#[test]
+	fn repeated_rootfs_setup_leaves_one_mount() {
+		in_mount_namespace(|base| {
+			let rootfs = base.join("rootfs");
+			std::fs::create_dir(&rootfs).unwrap();
+			let spec = spec_with_propagation("slave");
+			mount_rootfs(&spec, &rootfs);
+			mount_rootfs(&spec, &rootfs);
+			assert_eq!(mounts_at(&rootfs), 1);
+		});
+	}
 
-	nix::mount::umount2(".", MntFlags::MNT_DETACH).expect("Could not unmount cwd!");
+	#[test]
+	fn other_mounts_at_rootfs_are_kept() {
+		in_mount_namespace(|base| {
+			// E.g. an overlay the container manager mounted as rootfs
+			let rootfs = base.join("rootfs");
+			mount_tmpfs(&rootfs);
+			let spec = spec_with_propagation("slave");
+			mount_rootfs(&spec, &rootfs);
+			mount_rootfs(&spec, &rootfs);
+			assert_eq!(mounts_at(&rootfs), 2);
+		});
+	}
+
+	#[test]
+	fn unbindable_rootfs_cannot_be_bound() {
+		in_mount_namespace(|base| {
+			let rootfs = base.join("rootfs");
+			std::fs::create_dir(&rootfs).unwrap();
+			mount_rootfs(&spec_with_propagation("unbindable"), &rootfs);
 
-	nix::unistd::chdir("/").expect("Could not chdir into new_root at /!");
+			let target = base.join("target");
+			std::fs::create_dir(&target).unwrap();
+			assert_eq!(
+				nix::mount::mount::<Path, Path, str, str>(
+					Some(&rootfs),
+					&target,
+					None,
+					MsFlags::MS_BIND,
+					None,
+				),
+				Err(nix::errno::Errno::EINVAL)
+			);
+		});
+	}
+
+	#[test]
+	fn sys_bind_fallback_is_read_only_with_submounts() {
+		in_mount_namespace(|base| {
+			let rootfs = base.join("rootfs");
+			std::fs::create_dir(&rootfs).unwrap();
+			// Rootless containers always get the bind mount of the host /sys
+			mount_sys(&rootfs, Path::new("/sys"), MountOptions::default(), true);
+
+			let sys = rootfs.join("sys");
+			let mounts: Vec<_> = mountinfo::read_mountinfo(None)
+				.into_iter()
+				.filter(|mount| mount.mount_point.starts_with(&sys))
+				.collect();
+			if mounts.len() < 2 {
+				eprintln!("Skipping submount check, the host /sys has no submounts");
+			}
+			for mount in &mounts {
+				assert!(
+					mount.options.iter().any(|option| option == "ro"),
+					"{:?} is writable: {:?}",
+					mount.mount_point,
+					mount.options
+				);
+			}
+			assert!(mounts[0].options.iter().any(|option| option == "nosuid"));
+		});
+	}
+
+	#[test]
+	fn absolute_root_path_is_used_verbatim() {
+		let bundle = tempfile::tempdir().unwrap();
+		let image = tempfile::tempdir().unwrap();
+		// A rootfs in the bundle must not shadow the absolute path
+		std::fs::create_dir(bundle.path().join("rootfs")).unwrap();
+
+		let root_path = image.path().join("rootfs");
+		std::fs::create_dir(&root_path).unwrap();
+		assert_eq!(
+			resolve_root_path(&root_path, bundle.path()).unwrap(),
+			std::fs::canonicalize(&root_path).unwrap()
+		);
+	}
+
+	#[test]
+	fn relative_root_path_is_relative_to_bundle() {
+		let bundle = tempfile::tempdir().unwrap();
+		std::fs::create_dir_all(bundle.path().join("images/rootfs")).unwrap();
+		assert_eq!(
+			resolve_root_path(Path::new("images/rootfs"), bundle.path()).unwrap(),
+			std::fs::canonicalize(bundle.path().join("images/rootfs")).unwrap()
+		);
+		assert!(resolve_root_path(Path::new("missing"), bundle.path()).is_err());
+	}
 }
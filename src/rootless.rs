@@ -0,0 +1,55 @@
+use oci_spec::runtime::{LinuxNamespaceType, Spec};
+use std::str::FromStr;
+
+/// Value of --rootless. `Auto` detects whether runh runs without (real) root privileges.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RootlessMode {
+	Auto,
+	Enabled,
+	Disabled,
+}
+
+impl FromStr for RootlessMode {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s {
+			"auto" => Ok(RootlessMode::Auto),
+			"true" => Ok(RootlessMode::Enabled),
+			"false" => Ok(RootlessMode::Disabled),
+			_ => Err(format!("Unknown rootless mode {}", s)),
+		}
+	}
+}
+
+/// Whether the container gets a new user namespace with its own id mappings, in which
+/// root of the container is unprivileged on the host
+fn creates_mapped_user_ns(spec: &Spec) -> bool {
+	spec.linux().as_ref().map_or(false, |linux| {
+		let creates_user_ns = linux.namespaces().as_ref().map_or(false, |namespaces| {
+			namespaces
+				.iter()
+				.any(|ns| ns.typ() == LinuxNamespaceType::User && ns.path().is_none())
+		});
+		let has_mappings = linux
+			.uid_mappings()
+			.as_ref()
+			.map_or(false, |mappings| !mappings.is_empty());
+		creates_user_ns && has_mappings
+	})
+}
+
+impl RootlessMode {
+	/// Decides whether the container is set up rootless, i.e. whether mounts, cgroups and devices
+	/// have to take the paths that work without host privileges. This is the only place that
+	/// decides it, all other code gets the result passed in.
+	pub fn resolve(self, spec: &Spec) -> bool {
+		let rootless = match self {
+			RootlessMode::Enabled => true,
+			RootlessMode::Disabled => false,
+			RootlessMode::Auto => !nix::unistd::geteuid().is_root() || creates_mapped_user_ns(spec),
+		};
+		debug!("Rootless mode {:?} resolved to {}", self, rootless);
+		rootless
+	}
+}
@@ -0,0 +1,116 @@
+use crate::delete;
+use crate::kill;
+use crate::start;
+use crate::state;
+use capctl::prctl;
+use nix::sys::signal::{self, SigSet, SigmaskHow, Signal};
+use nix::sys::wait::{waitpid, WaitPidFlag, WaitStatus};
+use nix::unistd::Pid;
+use std::path::PathBuf;
+
+/// Signals that a foreground `runh run` passes on to the container init
+const FORWARDED_SIGNALS: [Signal; 4] = [
+	Signal::SIGHUP,
+	Signal::SIGINT,
+	Signal::SIGQUIT,
+	Signal::SIGTERM,
+];
+
+/// Reaps all exited children. Returns the exit status of the container init, if it was among them.
+fn reap_children(init: Pid) -> Option<state::ExitStatus> {
+	let mut exit_status = None;
+	loop {
+		match waitpid(Pid::from_raw(-1), Some(WaitPidFlag::WNOHANG)) {
+			Ok(WaitStatus::StillAlive) | Err(_) => break,
+			Ok(status) if status.pid() == Some(init) => {
+				exit_status = state::ExitStatus::from_wait(status).or(exit_status)
+			}
+			Ok(_) => {}
+		}
+	}
+	exit_status
+}
+
+/// Forwards signals to the container init until it exits and returns its exit status.
+/// SIGINT (i.e. Ctrl-C) is replaced by `interrupt_signal` if one is given.
+fn wait_for_init(
+	init: Pid,
+	signals: &SigSet,
+	interrupt_signal: Option<Signal>,
+) -> state::ExitStatus {
+	// SIGCHLD may have been discarded before it was blocked, so the init may already be gone
+	if let Some(exit_status) = reap_children(init) {
+		return exit_status;
+	}
+
+	loop {
+		let sig = match signals.wait() {
+			Ok(sig) => sig,
+			Err(_) => continue,
+		};
+
+		if sig == Signal::SIGCHLD {
+			if let Some(exit_status) = reap_children(init) {
+				return exit_status;
+			}
+			continue;
+		}
+
+		let sig = match interrupt_signal {
+			Some(interrupt_signal) if sig == Signal::SIGINT => interrupt_signal,
+			_ => sig,
+		};
+		debug!("Forwarding signal {} to container init {}", sig, init);
+		if let Err(err) = signal::kill(init, sig) {
+			if err != nix::errno::Errno::ESRCH {
+				panic!("Could not forward signal {} to container: {}", sig, err);
+			}
+		}
+	}
+}
+
+/// Creates and starts a container in one go. Unless `detach` is set, runh stays in the
+/// foreground as subreaper of the container init, waits for it to exit, deletes the container
+/// and exits with the exit code of the init.
+pub fn run_container<F: FnOnce()>(
+	project_dir: PathBuf,
+	id: Option<&str>,
+	detach: bool,
+	interrupt_signal: Option<&str>,
+	create: F,
+) {
+	let interrupt_signal = interrupt_signal.map(kill::parse_signal);
+
+	let mut signals = SigSet::empty();
+	if !detach {
+		// The container init is orphaned by runh init and has to be re-parented to us to be waited for
+		prctl::set_subreaper(true).expect("Could not become subreaper of the container!");
+
+		for sig in FORWARDED_SIGNALS {
+			signals.add(sig);
+		}
+		signals.add(Signal::SIGCHLD);
+		signal::sigprocmask(SigmaskHow::SIG_BLOCK, Some(&signals), None)
+			.expect("Could not block signals for the container!");
+	}
+
+	create();
+	start::start_container(project_dir.clone(), id);
+
+	if detach {
+		return;
+	}
+
+	let init = state::get_container_state(project_dir.clone(), id.unwrap())
+		.and_then(|state| state.pid)
+		.unwrap_or_else(|| panic!("Could not determine pid of container {}!", id.unwrap()));
+	let exit_status = wait_for_init(Pid::from_raw(init), &signals, interrupt_signal);
+	info!("Container {} {}", id.unwrap(), exit_status);
+	// A concurrent `runh wait` reads it from the container dir until the delete
+	state::persist_exit_status(&project_dir.join(id.unwrap()), &exit_status);
+	let exit_code = exit_status.code();
+
+	delete::delete_container(project_dir, id, false, false);
+	log::logger().flush();
+	std::process::exit(exit_code);
+}
@@ -0,0 +1,258 @@
+use nix::sched::CpuSet;
+use nix::unistd::Pid;
+use serde::Deserialize;
+
+// The oci-spec version we use predates the scheduling related process fields,
+// so they are parsed from the raw process object of the spec instead.
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct SchedulingConfig {
+	#[serde(default)]
+	pub scheduler: Option<Scheduler>,
+	#[serde(default, rename = "ioPriority")]
+	pub io_priority: Option<IoPriority>,
+	#[serde(default, rename = "execCPUAffinity")]
+	pub exec_cpu_affinity: Option<ExecCpuAffinity>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Scheduler {
+	pub policy: String,
+	#[serde(default)]
+	pub nice: i32,
+	#[serde(default)]
+	pub priority: i32,
+	#[serde(default)]
+	pub flags: Vec<String>,
+	#[serde(default)]
+	pub runtime: u64,
+	#[serde(default)]
+	pub deadline: u64,
+	#[serde(default)]
+	pub period: u64,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct IoPriority {
+	pub class: String,
+	#[serde(default)]
+	pub priority: i32,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct ExecCpuAffinity {
+	#[serde(default)]
+	pub initial: Option<String>,
+	#[serde(default, rename = "final")]
+	pub final_cpus: Option<String>,
+}
+
+const SCHED_OTHER: u32 = 0;
+const SCHED_FIFO: u32 = 1;
+const SCHED_RR: u32 = 2;
+const SCHED_BATCH: u32 = 3;
+const SCHED_IDLE: u32 = 5;
+const SCHED_DEADLINE: u32 = 6;
+
+/// Layout of `struct sched_attr` as expected by the sched_setattr syscall (SCHED_ATTR_SIZE_VER0)
+#[repr(C)]
+struct SchedAttr {
+	size: u32,
+	sched_policy: u32,
+	sched_flags: u64,
+	sched_nice: i32,
+	sched_priority: u32,
+	sched_runtime: u64,
+	sched_deadline: u64,
+	sched_period: u64,
+}
+
+fn parse_policy(policy: &str) -> u32 {
+	match policy {
+		"SCHED_OTHER" => SCHED_OTHER,
+		"SCHED_FIFO" => SCHED_FIFO,
+		"SCHED_RR" => SCHED_RR,
+		"SCHED_BATCH" => SCHED_BATCH,
+		"SCHED_IDLE" => SCHED_IDLE,
+		"SCHED_DEADLINE" => SCHED_DEADLINE,
+		"SCHED_ISO" => panic!("Scheduling policy SCHED_ISO is not supported by the Linux kernel!"),
+		_ => panic!("Unknown scheduling policy {}!", policy),
+	}
+}
+
+fn parse_flag(flag: &str) -> u64 {
+	match flag {
+		"SCHED_FLAG_RESET_ON_FORK" => 0x01,
+		"SCHED_FLAG_RECLAIM" => 0x02,
+		"SCHED_FLAG_DL_OVERRUN" => 0x04,
+		"SCHED_FLAG_KEEP_POLICY" => 0x08,
+		"SCHED_FLAG_KEEP_PARAMS" => 0x10,
+		"SCHED_FLAG_UTIL_CLAMP_MIN" => 0x20,
+		"SCHED_FLAG_UTIL_CLAMP_MAX" => 0x40,
+		_ => panic!("Unknown scheduler flag {}!", flag),
+	}
+}
+
+fn validate_scheduler(scheduler: &Scheduler, policy: u32) {
+	match policy {
+		SCHED_FIFO | SCHED_RR => {
+			if !(1..=99).contains(&scheduler.priority) {
+				panic!(
+					"Scheduler priority {} is invalid for policy {}! Expected a value between 1 and 99",
+					scheduler.priority, scheduler.policy
+				);
+			}
+		}
+		_ => {
+			if scheduler.priority != 0 {
+				panic!(
+					"Scheduler priority {} can not be used with policy {}! Only SCHED_FIFO and SCHED_RR support priorities",
+					scheduler.priority, scheduler.policy
+				);
+			}
+		}
+	}
+
+	if scheduler.nice != 0 && policy != SCHED_OTHER && policy != SCHED_BATCH {
+		panic!(
+			"Nice value {} can not be used with policy {}! Only SCHED_OTHER and SCHED_BATCH support nice values",
+			scheduler.nice, scheduler.policy
+		);
+	}
+	if !(-20..=19).contains(&scheduler.nice) {
+		panic!(
+			"Nice value {} is invalid! Expected a value between -20 and 19",
+			scheduler.nice
+		);
+	}
+
+	if policy == SCHED_DEADLINE {
+		let period = if scheduler.period == 0 {
+			scheduler.deadline
+		} else {
+			scheduler.period
+		};
+		if scheduler.runtime == 0
+			|| scheduler.runtime > scheduler.deadline
+			|| scheduler.deadline > period
+		{
+			panic!(
+				"Invalid SCHED_DEADLINE parameters (runtime {}, deadline {}, period {})! Expected 0 < runtime <= deadline <= period",
+				scheduler.runtime, scheduler.deadline, scheduler.period
+			);
+		}
+	} else if scheduler.runtime != 0 || scheduler.deadline != 0 || scheduler.period != 0 {
+		panic!(
+			"Scheduler runtime, deadline and period can only be used with SCHED_DEADLINE, not with {}!",
+			scheduler.policy
+		);
+	}
+}
+
+/// Applies the scheduling policy of the spec to the current process. This uses sched_setattr,
+/// which also covers the nice value for SCHED_OTHER and SCHED_BATCH, so no separate
+/// setpriority call is needed.
+pub fn set_scheduler(scheduler: &Scheduler) {
+	let policy = parse_policy(&scheduler.policy);
+	validate_scheduler(scheduler, policy);
+
+	let attr = SchedAttr {
+		size: std::mem::size_of::<SchedAttr>() as u32,
+		sched_policy: policy,
+		sched_flags: scheduler
+			.flags
+			.iter()
+			.fold(0, |flags, flag| flags | parse_flag(flag)),
+		sched_nice: scheduler.nice,
+		sched_priority: scheduler.priority as u32,
+		sched_runtime: scheduler.runtime,
+		sched_deadline: scheduler.deadline,
+		sched_period: scheduler.period,
+	};
+
+	debug!("Setting scheduler {:?}", scheduler);
+	let ret = unsafe { libc::syscall(libc::SYS_sched_setattr, 0, &attr as *const SchedAttr, 0) };
+	if ret != 0 {
+		panic!(
+			"Could not set scheduler {}: {}",
+			scheduler.policy,
+			std::io::Error::last_os_error()
+		);
+	}
+}
+
+const IOPRIO_WHO_PROCESS: libc::c_int = 1;
+const IOPRIO_CLASS_SHIFT: libc::c_int = 13;
+
+/// Applies the I/O scheduling class and priority of the spec to the current process.
+pub fn set_io_priority(io_priority: &IoPriority) {
+	let class = match io_priority.class.as_str() {
+		"IOPRIO_CLASS_RT" => 1,
+		"IOPRIO_CLASS_BE" => 2,
+		"IOPRIO_CLASS_IDLE" => 3,
+		_ => panic!("Unknown I/O priority class {}!", io_priority.class),
+	};
+	if !(0..=7).contains(&io_priority.priority) {
+		panic!(
+			"I/O priority {} is out of range! Expected a value between 0 and 7",
+			io_priority.priority
+		);
+	}
+
+	debug!("Setting I/O priority {:?}", io_priority);
+	let ioprio = (class << IOPRIO_CLASS_SHIFT) | io_priority.priority;
+	let ret = unsafe { libc::syscall(libc::SYS_ioprio_set, IOPRIO_WHO_PROCESS, 0, ioprio) };
+	if ret != 0 {
+		panic!(
+			"Could not set I/O priority {:?}: {}",
+			io_priority,
+			std::io::Error::last_os_error()
+		);
+	}
+}
+
+/// Parses a cpu list in the kernel's list format (e.g. `0-3,7`).
+fn parse_cpu_list(cpu_list: &str) -> Vec<usize> {
+	let mut cpus = Vec::new();
+	for range in cpu_list.trim().split(',').filter(|range| !range.is_empty()) {
+		let parse_cpu = |cpu: &str| -> usize {
+			cpu.trim()
+				.parse()
+				.unwrap_or_else(|_| panic!("Could not parse CPU {} in cpu list {}", cpu, cpu_list))
+		};
+		if let Some((start, end)) = range.split_once('-') {
+			let (start, end) = (parse_cpu(start), parse_cpu(end));
+			if start > end {
+				panic!("Invalid CPU range {} in cpu list {}", range, cpu_list);
+			}
+			cpus.extend(start..=end);
+		} else {
+			cpus.push(parse_cpu(range));
+		}
+	}
+	cpus
+}
+
+/// Pins the current process to the given cpu list. Every CPU in the list has to be online.
+pub fn set_cpu_affinity(cpu_list: &str) {
+	let online_cpus = parse_cpu_list(
+		&std::fs::read_to_string("/sys/devices/system/cpu/online")
+			.expect("Could not read the list of online CPUs!"),
+	);
+
+	let mut cpu_set = CpuSet::new();
+	for cpu in parse_cpu_list(cpu_list) {
+		if !online_cpus.contains(&cpu) {
+			panic!(
+				"CPU {} of the requested CPU affinity {} is not online!",
+				cpu, cpu_list
+			);
+		}
+		cpu_set
+			.set(cpu)
+			.unwrap_or_else(|_| panic!("CPU {} exceeds the maximum supported CPU index!", cpu));
+	}
+
+	debug!("Setting CPU affinity to {}", cpu_list);
+	nix::sched::sched_setaffinity(Pid::from_raw(0), &cpu_set)
+		.unwrap_or_else(|err| panic!("Could not set CPU affinity to {}: {}", cpu_list, err));
+}
@@ -0,0 +1,51 @@
+use std::{fs::OpenOptions, io::Write, path::Path};
+
+/// SELinux is considered enabled if selinuxfs is mounted at its usual location
+pub fn is_enabled() -> bool {
+	Path::new("/sys/fs/selinux/enforce").exists()
+}
+
+/// Sets the SELinux label the current process transitions into on its next exec.
+pub fn set_exec_label(label: &str) {
+	if !is_enabled() {
+		warn!(
+			"SELinux is not enabled on this host. Ignoring process label {}!",
+			label
+		);
+		return;
+	}
+
+	debug!("Setting SELinux exec label {}", label);
+	let mut attr_file = OpenOptions::new()
+		.write(true)
+		.open("/proc/self/attr/exec")
+		.expect("Could not open /proc/self/attr/exec!");
+	attr_file
+		.write_all(label.as_bytes())
+		.unwrap_or_else(|_| panic!("Could not set SELinux exec label {}!", label));
+}
+
+/// Mount options that label a filesystem. The kernel rejects `context` together with the others.
+const CONTEXT_OPTIONS: [&str; 4] = ["context=", "fscontext=", "defcontext=", "rootcontext="];
+
+fn has_context_option(data: &str) -> bool {
+	data.split(',').any(|option| {
+		CONTEXT_OPTIONS
+			.iter()
+			.any(|prefix| option.starts_with(prefix))
+	})
+}
+
+/// Adds the SELinux mount label as context option to a mount data string, unless the mount
+/// already sets a context of its own. Labels contain commas themselves, so they have to be quoted.
+pub fn format_mount_label(data: Option<&str>, label: &str) -> Option<String> {
+	if !is_enabled() || label.is_empty() || data.map_or(false, has_context_option) {
+		return data.map(|data| data.to_string());
+	}
+
+	let context = format!("context=\"{}\"", label);
+	Some(match data {
+		Some(data) if !data.is_empty() => format!("{},{}", data, context),
+		_ => context,
+	})
+}
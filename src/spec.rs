@@ -1,6 +1,72 @@
 use oci_spec::runtime;
+use serde::Deserialize;
+use serde_json::Value;
+use std::fmt;
 use std::fs;
+use std::io::{BufReader, Read};
 use std::path;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::consts;
+
+static MAX_CONFIG_SIZE: AtomicU64 = AtomicU64::new(consts::DEFAULT_MAX_CONFIG_SIZE);
+
+/// Sets the maximum number of bytes a config.json may have
+pub fn set_max_config_size(size: u64) {
+	MAX_CONFIG_SIZE.store(size, Ordering::Relaxed);
+}
+
+pub fn max_config_size() -> u64 {
+	MAX_CONFIG_SIZE.load(Ordering::Relaxed)
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+	Io(std::io::Error),
+	TooLarge(u64),
+	Invalid(serde_json::Error),
+}
+
+impl fmt::Display for ConfigError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			ConfigError::Io(err) => write!(f, "{}", err),
+			ConfigError::TooLarge(limit) => write!(f, "the config is larger than {} bytes", limit),
+			ConfigError::Invalid(err) => write!(f, "the config is not valid JSON: {}", err),
+		}
+	}
+}
+
+/// Parses a config from `reader`, but reads at most --max-config-size bytes, so that a huge
+/// file cannot make runh run out of memory.
+pub fn read_config<R: Read>(reader: R) -> Result<Value, ConfigError> {
+	let limit = max_config_size();
+	// One byte more than allowed is read to tell a config of exactly the limit from a larger one
+	let mut limited = reader.take(limit.saturating_add(1));
+	let result = {
+		let mut deserializer = serde_json::Deserializer::from_reader(BufReader::new(&mut limited));
+		Value::deserialize(&mut deserializer).and_then(|value| {
+			deserializer.end()?;
+			Ok(value)
+		})
+	};
+	if limited.limit() == 0 {
+		return Err(ConfigError::TooLarge(limit));
+	}
+	result.map_err(|err| {
+		if err.is_io() {
+			ConfigError::Io(err.into())
+		} else {
+			ConfigError::Invalid(err)
+		}
+	})
+}
+
+/// Reads the config at `path`, see `read_config`
+pub fn load_config(path: &path::Path) -> Result<Value, ConfigError> {
+	let file = fs::File::open(path).map_err(ConfigError::Io)?;
+	read_config(file)
+}
 
 pub fn create_spec(bundle: Option<&str>, args: Vec<String>) {
 	let dir = fs::canonicalize(path::PathBuf::from(bundle.unwrap()))
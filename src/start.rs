@@ -1,15 +1,80 @@
 use crate::container::OCIContainer;
+use crate::error::{self, RunhError};
+use crate::hooks;
+use crate::state::{self, ContainerStatus};
+use crate::wait;
+use nix::poll::{poll, PollFd, PollFlags};
 use std::fs::{self, File};
 use std::io::Read;
-use std::path::PathBuf;
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
-pub fn start_container(mut project_dir: PathBuf, id: Option<&str>) {
-	project_dir.push(id.unwrap());
+/// How often `runh start` checks whether the init is still alive while waiting on the fifo
+const EXEC_FIFO_POLL_MILLIS: i32 = 100;
+
+/// Opens the exec fifo for reading and waits until the init opened its end. A blocking open
+/// would hang forever if the init died before reaching the fifo, so the fifo is opened
+/// non-blocking and polled while checking that the init is still alive. Returns `None` if the
+/// init exited before it opened the fifo.
+fn open_exec_fifo(fifo_path: &Path, pid: Option<i32>) -> Option<File> {
+	let fifo = fs::OpenOptions::new()
+		.read(true)
+		.custom_flags(libc::O_NONBLOCK | libc::O_CLOEXEC)
+		.open(fifo_path)
+		.expect("Could not open exec fifo!");
+
+	let mut poll_fds = [PollFd::new(fifo.as_raw_fd(), PollFlags::POLLIN)];
+	loop {
+		match poll(&mut poll_fds, EXEC_FIFO_POLL_MILLIS) {
+			// Readable or closed by the init, either way read_exact tells which
+			Ok(n) if n > 0 => break,
+			Ok(_) | Err(nix::errno::Errno::EINTR) => {}
+			Err(err) => panic!("Could not poll exec fifo: {}", err),
+		}
+		if let Some(pid) = pid {
+			if wait::wait_for_exit(pid, Some(Duration::ZERO)) {
+				return None;
+			}
+		}
+	}
+
+	// The go-ahead is a single byte, which is read blocking again
+	nix::fcntl::fcntl(
+		fifo.as_raw_fd(),
+		nix::fcntl::FcntlArg::F_SETFL(nix::fcntl::OFlag::empty()),
+	)
+	.expect("Could not make exec fifo blocking!");
+	Some(fifo)
+}
+
+/// Executes the user process of a created container.
+///
+/// `runh create` leaves the container init blocked on opening the `exec.fifo` in the
+/// container directory for writing. Opening the fifo for reading here unblocks the init,
+/// which then writes a single zero byte as its go-ahead and execs the entrypoint. The init
+/// keeps its end open until the exec, so start only succeeds once the entrypoint really runs.
+/// The fifo is removed afterwards, which marks the container as running.
+pub fn start_container(project_dir: PathBuf, id: Option<&str>) {
+	let mut init_pid = None;
+	if let Some(container_state) = state::get_container_state(project_dir.clone(), id.unwrap()) {
+		if container_state.status != ContainerStatus::Created {
+			error::raise(RunhError::InvalidState {
+				id: id.unwrap().to_string(),
+				status: container_state.status,
+				expected: ContainerStatus::Created.to_string(),
+			});
+		}
+		init_pid = container_state.pid;
+	}
+
+	let container_dir = project_dir.join(id.unwrap());
 
 	if let Ok(mut file) = fs::OpenOptions::new()
 		.read(true)
 		.write(false)
-		.open(project_dir.join("container.json"))
+		.open(container_dir.join("container.json"))
 	{
 		let mut contents = String::new();
 		file.read_to_string(&mut contents)
@@ -25,17 +90,48 @@ pub fn start_container(mut project_dir: PathBuf, id: Option<&str>) {
 			);
 
 			debug!("Open exec fifo to start container!");
-			let mut fifo =
-				File::open(project_dir.join("exec.fifo")).expect("Could not open exec fifo!");
 			let mut buffer = [1u8];
-			fifo.read_exact(&mut buffer)
-				.expect("Could not read from exec fifo!");
-			drop(fifo);
+			// The fifo is closed at the end of the match
+			let result = match open_exec_fifo(&container_dir.join("exec.fifo"), init_pid) {
+				Some(mut fifo) => fifo
+					.read_exact(&mut buffer)
+					.map_err(|err| format!("could not read from exec fifo: {}", err))
+					.and_then(|_| {
+						// The init holds its end until the exec closes it, anything it wrote
+						// in between is the reason the exec failed
+						let mut exec_error = String::new();
+						fifo.read_to_string(&mut exec_error)
+							.map_err(|err| format!("could not read from exec fifo: {}", err))?;
+						if exec_error.is_empty() {
+							Ok(())
+						} else {
+							Err(exec_error)
+						}
+					}),
+				None => Err(String::from(
+					"the init exited before it opened the exec fifo",
+				)),
+			};
+			if let Err(err) = result {
+				// The init closes the fifo without a go-ahead if it fails before the exec
+				if let Some(state) = state::get_container_state(project_dir.clone(), id.unwrap()) {
+					if state.status == ContainerStatus::Stopped {
+						hooks::run_poststop_hooks(&container_dir, container.spec(), &state);
+					}
+				}
+				panic!("Could not start container: {}", err);
+			}
 
 			if buffer[0] == 0 {
 				info!("Container started successfully! Deleting exec fifo!");
-				std::fs::remove_file(project_dir.join("exec.fifo"))
+				std::fs::remove_file(container_dir.join("exec.fifo"))
 					.expect("Could not delete exec fifo!");
+				state::set_status(
+					&project_dir,
+					id.unwrap(),
+					ContainerStatus::Running,
+					"runh start",
+				);
 			} else {
 				panic!(
 					"Invalid value read from fifo. Read byte was {:x}",
@@ -1,18 +1,260 @@
+use crate::error::{self, RunhError};
+use crate::format::{self, OutputFormat};
 use crate::{consts, container::OCIContainer};
+use nix::sys::wait::WaitStatus;
+use nix::unistd::AccessFlags;
 use serde::*;
-use std::{collections::HashMap, fs::OpenOptions, io::BufReader, path::PathBuf};
+use std::{
+	collections::HashMap,
+	convert::TryFrom,
+	fmt,
+	fs::{DirBuilder, File, OpenOptions},
+	io::{self, BufReader, ErrorKind, Write},
+	os::unix::fs::{DirBuilderExt, OpenOptionsExt},
+	path::Path,
+	path::PathBuf,
+	str::FromStr,
+	time::Duration,
+};
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ContainerStatus {
+	Creating,
+	Created,
+	Running,
+	Stopped,
+}
+
+impl fmt::Display for ContainerStatus {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		let status = match self {
+			ContainerStatus::Creating => "creating",
+			ContainerStatus::Created => "created",
+			ContainerStatus::Running => "running",
+			ContainerStatus::Stopped => "stopped",
+		};
+		write!(f, "{}", status)
+	}
+}
+
+impl FromStr for ContainerStatus {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s {
+			"creating" => Ok(ContainerStatus::Creating),
+			"created" => Ok(ContainerStatus::Created),
+			"running" => Ok(ContainerStatus::Running),
+			"stopped" => Ok(ContainerStatus::Stopped),
+			_ => Err(format!("Unknown container status {}", s)),
+		}
+	}
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct State {
 	#[serde(rename = "ociVersion")]
 	pub version: String,
 	pub id: String,
-	pub status: String,
+	pub status: ContainerStatus,
 	#[serde(default, skip_serializing_if = "Option::is_none")]
 	pub pid: Option<i32>,
 	pub bundle: String,
 	#[serde(default, skip_serializing_if = "Option::is_none")]
 	pub annotations: Option<HashMap<String, String>>,
+	#[serde(flatten)]
+	pub exit_status: ExitStatus,
+}
+
+/// Why the container init stopped. Either an exit code or the name of the
+/// signal that killed the init is set, once the container is stopped.
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ExitStatus {
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub exit_code: Option<i32>,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub signal: Option<String>,
+}
+
+impl ExitStatus {
+	/// Decodes a raw wait status as found in the `exit_code` field of /proc/<pid>/stat
+	fn from_wait_status(status: i32) -> Self {
+		if libc::WIFSIGNALED(status) {
+			let signal = libc::WTERMSIG(status);
+			ExitStatus {
+				exit_code: None,
+				signal: Some(
+					nix::sys::signal::Signal::try_from(signal)
+						.map_or_else(|_| signal.to_string(), |sig| sig.as_str().to_string()),
+				),
+			}
+		} else {
+			ExitStatus {
+				exit_code: Some(libc::WEXITSTATUS(status)),
+				signal: None,
+			}
+		}
+	}
+
+	/// The exit status of a process reaped with waitpid, if it exited or was killed
+	pub fn from_wait(status: WaitStatus) -> Option<Self> {
+		match status {
+			WaitStatus::Exited(_, code) => Some(ExitStatus {
+				exit_code: Some(code),
+				signal: None,
+			}),
+			WaitStatus::Signaled(_, signal, _) => Some(ExitStatus {
+				exit_code: None,
+				signal: Some(signal.as_str().to_string()),
+			}),
+			_ => None,
+		}
+	}
+
+	/// The exit code a shell would report, i.e. 128 + the signal number for a killed process
+	pub fn code(&self) -> i32 {
+		match (self.exit_code, &self.signal) {
+			(_, Some(signal)) => signal
+				.parse::<nix::sys::signal::Signal>()
+				.map_or(1, |signal| 128 + signal as i32),
+			(Some(exit_code), None) => exit_code,
+			(None, None) => 1,
+		}
+	}
+}
+
+impl fmt::Display for ExitStatus {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match (self.exit_code, &self.signal) {
+			(_, Some(signal)) => write!(f, "was killed by signal {}", signal),
+			(Some(exit_code), None) => write!(f, "exited with code {}", exit_code),
+			(None, None) => write!(f, "stopped for an unknown reason"),
+		}
+	}
+}
+
+fn get_persisted_exit_status(container_dir: &Path) -> ExitStatus {
+	std::fs::read_to_string(container_dir.join("exit_status"))
+		.ok()
+		.and_then(|exit_status| serde_json::from_str(&exit_status).ok())
+		.unwrap_or_default()
+}
+
+/// The exit status can only be read while the init is a zombie, so it is kept in the
+/// container directory for queries after the init was reaped. Everything that reaps the
+/// init stores it here, a state query only when it happens to see the zombie.
+pub fn persist_exit_status(container_dir: &Path, exit_status: &ExitStatus) {
+	if let Err(err) = std::fs::write(
+		container_dir.join("exit_status"),
+		serde_json::to_string(exit_status).unwrap(),
+	) {
+		warn!("Could not persist exit status of container: {}", err);
+	}
+}
+
+/// Opens the exit status file for the init wrapper, which cannot reach the container
+/// directory once it runs inside of the container
+pub fn open_exit_status_file(container_dir: &Path) -> File {
+	OpenOptions::new()
+		.write(true)
+		.create(true)
+		.truncate(true)
+		.mode(0o644)
+		.custom_flags(libc::O_CLOEXEC)
+		.open(container_dir.join("exit_status"))
+		.expect("Could not create exit status file!")
+}
+
+/// Writes the exit status to a file opened with `open_exit_status_file`
+pub fn write_exit_status(mut file: &File, exit_status: &ExitStatus) -> io::Result<()> {
+	file.write_all(serde_json::to_string(exit_status).unwrap().as_bytes())
+}
+
+fn get_persisted_status(container_dir: &Path) -> Option<ContainerStatus> {
+	std::fs::read_to_string(container_dir.join("status"))
+		.ok()
+		.and_then(|status| status.parse().ok())
+}
+
+/// Persists a new status for the container and logs the transition from the previously
+/// persisted status, so that the lifecycle of a container can be traced in the logs.
+pub fn set_status(project_dir: &Path, id: &str, new_status: ContainerStatus, trigger: &str) {
+	let container_dir = project_dir.join(id);
+	let old_status = get_persisted_status(&container_dir);
+	if old_status == Some(new_status) {
+		return;
+	}
+
+	info!(
+		"Container {} changed status: {} -> {} (triggered by {})",
+		id,
+		old_status.map_or_else(|| String::from("none"), |status| status.to_string()),
+		new_status,
+		trigger
+	);
+	std::fs::write(container_dir.join("status"), new_status.to_string())
+		.unwrap_or_else(|_| panic!("Could not persist status of container {}!", id));
+}
+
+fn is_vanished_process_error(err: &procfs::ProcError) -> bool {
+	match err {
+		procfs::ProcError::NotFound(_) | procfs::ProcError::Incomplete(_) => true,
+		procfs::ProcError::Io(io_err, _) => matches!(
+			io_err.raw_os_error(),
+			Some(libc::ESRCH) | Some(libc::ENOENT)
+		),
+		_ => false,
+	}
+}
+
+/// Reads the stat of a process from /proc. The process may exit at any point while we
+/// read its entries, in which case `None` is returned instead of an error.
+fn get_process_stat(pid: i32) -> Option<procfs::process::Stat> {
+	read_process_stat(pid, procfs::process::Process::new(pid))
+}
+
+fn read_process_stat(
+	pid: i32,
+	process: procfs::ProcResult<procfs::process::Process>,
+) -> Option<procfs::process::Stat> {
+	match process.and_then(|process| process.stat()) {
+		Ok(stat) => Some(stat),
+		Err(err) if is_vanished_process_error(&err) => {
+			debug!("Process {} vanished while querying its state: {}", pid, err);
+			None
+		}
+		Err(err) => panic!(
+			"Could not query state. Process stat could not be read: {}",
+			err
+		),
+	}
+}
+
+/// Adds the files a container directory of the current format has, but one created by an older
+/// runh lacks, so that upgrading runh does not orphan existing containers. Fields missing in an
+/// older container file already get their defaults when it is parsed.
+fn migrate_container_dir(
+	project_dir: &Path,
+	id: &str,
+	container: &OCIContainer,
+	status: ContainerStatus,
+) {
+	let version = *container.state_version();
+	if version >= consts::CONTAINER_STATE_VERSION {
+		return;
+	}
+
+	// Containers of version 0 may predate the persisted status, which starts from the current one
+	if get_persisted_status(&project_dir.join(id)).is_none() {
+		set_status(
+			project_dir,
+			id,
+			status,
+			&format!("migration from container state version {}", version),
+		);
+	}
 }
 
 pub fn get_container_state(project_dir: PathBuf, id: &str) -> Option<State> {
@@ -56,48 +298,272 @@ pub fn get_container_state(project_dir: PathBuf, id: &str) -> Option<State> {
 	let container: OCIContainer = serde_json::from_reader(BufReader::new(container_file))
 		.expect("Could not query state. Container file could not be parsed!");
 
-	Some(State {
-		version: String::from(consts::OCI_STATE_VERSION),
-		id: id.to_string(),
-		status: String::from(if let Some(pid_int) = pid {
-			if let Ok(process) = procfs::process::Process::new(pid_int) {
-				let process_state = process
-					.stat()
-					.expect("Could not query state. Process stat could not be read!")
-					.state()
-					.expect("Could not query state. Process state could not be read!");
-				match process_state {
-					procfs::process::ProcState::Zombie => "stopped",
-					procfs::process::ProcState::Dead => "stopped",
-					_ => {
-						if exec_fifo.exists() {
-							"created"
-						} else {
-							"running"
-						}
+	let mut exit_status = ExitStatus::default();
+	let status = if let Some(pid_int) = pid {
+		let stat = get_process_stat(pid_int);
+		match stat.as_ref().map(|stat| stat.state()) {
+			Some(Ok(procfs::process::ProcState::Zombie)) => {
+				exit_status = match stat.and_then(|stat| stat.exit_code) {
+					Some(wait_status) => {
+						let exit_status = ExitStatus::from_wait_status(wait_status);
+						persist_exit_status(&container_dir, &exit_status);
+						exit_status
 					}
+					None => get_persisted_exit_status(&container_dir),
+				};
+				ContainerStatus::Stopped
+			}
+			Some(Ok(procfs::process::ProcState::Dead)) | None => {
+				exit_status = get_persisted_exit_status(&container_dir);
+				ContainerStatus::Stopped
+			}
+			Some(Err(_)) => panic!("Could not query state. Process state could not be read!"),
+			Some(Ok(_)) => {
+				if exec_fifo.exists() {
+					ContainerStatus::Created
+				} else {
+					ContainerStatus::Running
 				}
-			} else {
-				"stopped"
 			}
-		} else {
-			"creating"
-		}),
+		}
+	} else {
+		ContainerStatus::Creating
+	};
+
+	// While the init is alive, the persisted status knows more than /proc, e.g. that the
+	// create is not finished yet although the init already waits on the exec fifo
+	let status = match get_persisted_status(&container_dir) {
+		Some(persisted) if status != ContainerStatus::Stopped => persisted,
+		_ => status,
+	};
+
+	migrate_container_dir(&project_dir, id, &container, status);
+
+	// Transitions that happen outside of runh (i.e. the container process exiting) are only noticed here
+	if status == ContainerStatus::Stopped {
+		set_status(&project_dir, id, status, "state query");
+	}
+
+	Some(State {
+		version: String::from(consts::OCI_STATE_VERSION),
+		id: id.to_string(),
+		status,
 		pid,
 		bundle,
 		annotations: container.spec().annotations().clone(),
+		exit_status,
 	})
 }
 
-pub fn print_container_state(project_dir: PathBuf, id: &str) {
-	println!(
-		"{}",
-		serde_json::to_string(
-			&get_container_state(project_dir, id).unwrap_or_else(|| panic!(
-				"Could not query state. Container {} does not exist!",
-				id
-			))
+const STATE_COLUMNS: [(&str, &str); 8] = [
+	("OCI VERSION", "ociVersion"),
+	("ID", "id"),
+	("STATUS", "status"),
+	("PID", "pid"),
+	("BUNDLE", "bundle"),
+	("EXIT CODE", "exitCode"),
+	("SIGNAL", "signal"),
+	("ANNOTATIONS", "annotations"),
+];
+
+pub fn print_container_state(project_dir: PathBuf, id: &str, format: OutputFormat) {
+	let state = get_container_state(project_dir, id)
+		.unwrap_or_else(|| panic!("Could not query state. Container {} does not exist!", id));
+	format::print_output(&state, format, &STATE_COLUMNS);
+}
+
+/// Number of attempts to create the state root before giving up
+const STATE_ROOT_ATTEMPTS: u32 = 3;
+
+/// Creates the state root if necessary and makes sure runh can create container directories in
+/// it. Creating it is retried a few times, as temporary failures are common when the parent
+/// (e.g. /run/user/<uid>) is only being set up right now.
+pub fn ensure_state_root(project_dir: &Path) {
+	let unwritable = |source: String| {
+		error::raise(RunhError::StateRootUnwritable {
+			path: project_dir.to_path_buf(),
+			source,
+		})
+	};
+
+	let mut backoff = Duration::from_millis(10);
+	for attempt in 1..=STATE_ROOT_ATTEMPTS {
+		match DirBuilder::new()
+			.recursive(true)
+			.mode(0o700)
+			.create(project_dir)
+		{
+			Ok(()) => break,
+			Err(err)
+				if attempt < STATE_ROOT_ATTEMPTS
+					&& matches!(
+						err.kind(),
+						ErrorKind::NotFound | ErrorKind::Interrupted | ErrorKind::WouldBlock
+					) =>
+			{
+				debug!(
+					"Could not create state root {:?} ({}), retrying in {:?}",
+					project_dir, err, backoff
+				);
+				std::thread::sleep(backoff);
+				backoff *= 2;
+			}
+			Err(err) => unwritable(err.to_string()),
+		}
+	}
+
+	if !project_dir.is_dir() {
+		unwritable(String::from("it is not a directory"));
+	}
+	if let Err(err) = nix::unistd::access(project_dir, AccessFlags::W_OK | AccessFlags::X_OK) {
+		unwritable(err.desc().to_string());
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use procfs::process::Process;
+
+	#[test]
+	fn process_vanishing_before_stat_is_stopped() {
+		let proc_dir = tempfile::tempdir().unwrap();
+		let process_dir = proc_dir.path().join("4242");
+		std::fs::create_dir(&process_dir).unwrap();
+		let process = Process::new_with_root(process_dir.clone());
+		assert!(process.is_ok());
+
+		// The process exits after its /proc entry was opened, but before its stat is read
+		std::fs::remove_dir(&process_dir).unwrap();
+		assert!(read_process_stat(4242, process).is_none());
+	}
+
+	#[test]
+	fn old_container_dir_gets_status_file() {
+		let project_dir = tempfile::tempdir().unwrap();
+		let container_dir = project_dir.path().join("old");
+		std::fs::create_dir(&container_dir).unwrap();
+		std::os::unix::fs::symlink("/var/lib/bundles/old", container_dir.join("bundle")).unwrap();
+		// Neither a state version nor a status file, and the init was never started
+		std::fs::write(
+			container_dir.join("container.json"),
+			r#"{"id": "old", "bundle": "/var/lib/bundles/old", "pidfile": "",
+				"spec": {"ociVersion": "1.0.2", "root": {"path": "rootfs"}}}"#,
 		)
-		.expect("Could not query state. State could not be serialized!")
-	);
+		.unwrap();
+
+		let state = get_container_state(project_dir.path().to_path_buf(), "old").unwrap();
+		assert_eq!(state.status, ContainerStatus::Creating);
+		assert_eq!(state.bundle, "/var/lib/bundles/old");
+		assert_eq!(
+			get_persisted_status(&container_dir),
+			Some(ContainerStatus::Creating)
+		);
+	}
+
+	/// A container dir of the current version whose init has `pid`
+	fn container_dir_with_init(project_dir: &Path, id: &str, pid: u32) -> PathBuf {
+		let container_dir = project_dir.join(id);
+		std::fs::create_dir(&container_dir).unwrap();
+		std::os::unix::fs::symlink("/var/lib/bundles/test", container_dir.join("bundle")).unwrap();
+		std::fs::write(
+			container_dir.join("container.json"),
+			format!(
+				r#"{{"id": "{}", "bundle": "/var/lib/bundles/test", "pidfile": "", "stateVersion": {},
+					"spec": {{"ociVersion": "1.0.2", "root": {{"path": "rootfs"}}}}}}"#,
+				id,
+				consts::CONTAINER_STATE_VERSION
+			),
+		)
+		.unwrap();
+		std::fs::write(container_dir.join("created"), pid.to_string()).unwrap();
+		std::fs::write(container_dir.join("exec.fifo"), "").unwrap();
+		container_dir
+	}
+
+	#[test]
+	fn persisted_status_is_reported_while_init_is_alive() {
+		let project_dir = tempfile::tempdir().unwrap();
+		// The test process stands in for an init waiting on the exec fifo
+		container_dir_with_init(project_dir.path(), "test", std::process::id());
+		let state = get_container_state(project_dir.path().to_path_buf(), "test").unwrap();
+		assert_eq!(state.status, ContainerStatus::Created);
+
+		set_status(
+			project_dir.path(),
+			"test",
+			ContainerStatus::Creating,
+			"test",
+		);
+		let state = get_container_state(project_dir.path().to_path_buf(), "test").unwrap();
+		assert_eq!(state.status, ContainerStatus::Creating);
+	}
+
+	#[test]
+	fn exited_init_overrides_persisted_status() {
+		let project_dir = tempfile::tempdir().unwrap();
+		let mut child = std::process::Command::new("true").spawn().unwrap();
+		child.wait().unwrap();
+		let container_dir = container_dir_with_init(project_dir.path(), "test", child.id());
+		set_status(project_dir.path(), "test", ContainerStatus::Running, "test");
+
+		let state = get_container_state(project_dir.path().to_path_buf(), "test").unwrap();
+		assert_eq!(state.status, ContainerStatus::Stopped);
+		assert_eq!(
+			get_persisted_status(&container_dir),
+			Some(ContainerStatus::Stopped)
+		);
+	}
+
+	#[test]
+	fn process_vanished_before_open_is_stopped() {
+		let proc_dir = tempfile::tempdir().unwrap();
+		let process = Process::new_with_root(proc_dir.path().join("4242"));
+		assert!(read_process_stat(4242, process).is_none());
+	}
+
+	#[test]
+	fn exit_status_from_wait() {
+		let pid = nix::unistd::Pid::from_raw(4242);
+		let exited = ExitStatus::from_wait(WaitStatus::Exited(pid, 3)).unwrap();
+		assert_eq!(
+			(exited.exit_code, exited.signal.as_deref()),
+			(Some(3), None)
+		);
+		assert_eq!(exited.code(), 3);
+
+		let signal = nix::sys::signal::Signal::SIGKILL;
+		let killed = ExitStatus::from_wait(WaitStatus::Signaled(pid, signal, false)).unwrap();
+		assert_eq!(killed.signal.as_deref(), Some("SIGKILL"));
+		assert_eq!(killed.code(), 137);
+
+		assert!(ExitStatus::from_wait(WaitStatus::StillAlive).is_none());
+		assert_eq!(ExitStatus::default().code(), 1);
+	}
+
+	#[test]
+	fn exit_status_file_is_read_back() {
+		let container_dir = tempfile::tempdir().unwrap();
+		let file = open_exit_status_file(container_dir.path());
+		// The file is created empty when the container is created
+		assert_eq!(get_persisted_exit_status(container_dir.path()).code(), 1);
+
+		let exit_status = ExitStatus {
+			exit_code: None,
+			signal: Some(String::from("SIGTERM")),
+		};
+		write_exit_status(&file, &exit_status).unwrap();
+		let persisted = get_persisted_exit_status(container_dir.path());
+		assert_eq!(persisted.signal.as_deref(), Some("SIGTERM"));
+		assert_eq!(persisted.code(), 143);
+
+		persist_exit_status(
+			container_dir.path(),
+			&ExitStatus {
+				exit_code: Some(0),
+				signal: None,
+			},
+		);
+		assert_eq!(get_persisted_exit_status(container_dir.path()).code(), 0);
+	}
 }
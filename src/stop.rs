@@ -0,0 +1,78 @@
+use crate::error::{self, RunhError};
+use crate::kill;
+use crate::state::{self, ContainerStatus};
+use crate::wait;
+use nix::sys::signal::Signal;
+use std::fmt;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// How `stop_container` got the container to stop
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopOutcome {
+	/// The container was not running anymore
+	AlreadyStopped,
+	/// The init exited within the grace period after SIGTERM
+	Terminated,
+	/// The init ignored SIGTERM and was killed after the grace period
+	Killed,
+}
+
+impl fmt::Display for StopOutcome {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		let outcome = match self {
+			StopOutcome::AlreadyStopped => "was already stopped",
+			StopOutcome::Terminated => "exited after SIGTERM",
+			StopOutcome::Killed => "was killed after the grace period",
+		};
+		write!(f, "{}", outcome)
+	}
+}
+
+fn signal_init(pid: i32, signal: Signal) -> bool {
+	match kill::send_signal(pid, signal) {
+		Ok(()) => true,
+		// The init exited in the meantime
+		Err(nix::errno::Errno::ESRCH) => false,
+		Err(err) => panic!(
+			"Could not send signal {} to container init {}: {}",
+			signal, pid, err
+		),
+	}
+}
+
+/// Sends SIGTERM to the init of a container and waits up to `timeout` for it to exit. Inits that
+/// are still alive afterwards are killed with SIGKILL.
+pub fn stop_container(project_dir: PathBuf, id: &str, timeout: Duration) -> StopOutcome {
+	let container_state = state::get_container_state(project_dir, id)
+		.unwrap_or_else(|| error::raise(RunhError::ContainerNotFound(id.to_string())));
+	let pid = match (container_state.status, container_state.pid) {
+		(ContainerStatus::Created, Some(pid)) | (ContainerStatus::Running, Some(pid)) => pid,
+		(ContainerStatus::Stopped, _) => return StopOutcome::AlreadyStopped,
+		(status, _) => error::raise(RunhError::InvalidState {
+			id: id.to_string(),
+			status,
+			expected: String::from("created or running"),
+		}),
+	};
+
+	if !signal_init(pid, Signal::SIGTERM) {
+		return StopOutcome::Terminated;
+	}
+	debug!(
+		"Sent SIGTERM to init {} of container {}, waiting up to {:?}",
+		pid, id, timeout
+	);
+	if wait::wait_for_exit(pid, Some(timeout)) {
+		return StopOutcome::Terminated;
+	}
+
+	warn!(
+		"Container {} did not exit within {:?} after SIGTERM, sending SIGKILL",
+		id, timeout
+	);
+	if signal_init(pid, Signal::SIGKILL) {
+		wait::wait_for_exit(pid, None);
+	}
+	StopOutcome::Killed
+}
@@ -0,0 +1,34 @@
+use nix::errno::Errno;
+use std::fmt;
+
+/// Re-issues a syscall that was interrupted by a signal before it could complete.
+/// runh may be signalled at any point during container setup (e.g. when the container
+/// manager shuts down), which should not make the current syscall fail.
+pub fn retry_on_eintr<T, F>(mut syscall: F) -> nix::Result<T>
+where
+	F: FnMut() -> nix::Result<T>,
+{
+	loop {
+		match syscall() {
+			Err(Errno::EINTR) => {
+				debug!("Syscall was interrupted by a signal, retrying...");
+				continue;
+			}
+			result => return result,
+		}
+	}
+}
+
+/// Like `retry_on_eintr`, but logs the syscall with its arguments and result at trace level.
+/// The arguments are only formatted if trace logging is enabled.
+pub fn traced_syscall<T, F>(name: &str, args: fmt::Arguments, syscall: F) -> nix::Result<T>
+where
+	F: FnMut() -> nix::Result<T>,
+{
+	let result = retry_on_eintr(syscall);
+	match &result {
+		Ok(_) => trace!("{}({}) = 0", name, args),
+		Err(errno) => trace!("{}({}) = -1 {}", name, args, errno),
+	}
+	result
+}
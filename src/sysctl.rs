@@ -0,0 +1,96 @@
+use oci_spec::runtime::LinuxNamespaceType;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::PathBuf;
+
+/// IPC sysctls outside of the fs.mqueue prefix
+const IPC_SYSCTLS: [&str; 8] = [
+	"kernel.msgmax",
+	"kernel.msgmnb",
+	"kernel.msgmni",
+	"kernel.sem",
+	"kernel.shmall",
+	"kernel.shmmax",
+	"kernel.shmmni",
+	"kernel.shm_rmid_forced",
+];
+
+const UTS_SYSCTLS: [&str; 2] = ["kernel.domainname", "kernel.hostname"];
+
+/// Reasons for refusing a sysctl, which would otherwise change the host
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SysctlError {
+	RequiresNetNs(String),
+	RequiresIpcNs(String),
+	RequiresUtsNs(String),
+	NotNamespaced(String),
+}
+
+impl fmt::Display for SysctlError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			SysctlError::RequiresNetNs(key) => {
+				write!(f, "sysctl {} requires a network namespace", key)
+			}
+			SysctlError::RequiresIpcNs(key) => {
+				write!(f, "sysctl {} requires an IPC namespace", key)
+			}
+			SysctlError::RequiresUtsNs(key) => {
+				write!(f, "sysctl {} requires a UTS namespace", key)
+			}
+			SysctlError::NotNamespaced(key) => write!(
+				f,
+				"sysctl {} is not namespaced and would change the host",
+				key
+			),
+		}
+	}
+}
+
+/// Checks that a sysctl only affects namespaces of the container. Joined namespaces count as
+/// well, as they belong to the container the same way created ones do.
+pub fn check_sysctl(key: &str, namespaces: &[LinuxNamespaceType]) -> Result<(), SysctlError> {
+	let (required, error): (LinuxNamespaceType, fn(String) -> SysctlError) =
+		if key.starts_with("net.") {
+			(LinuxNamespaceType::Network, SysctlError::RequiresNetNs)
+		} else if key.starts_with("fs.mqueue.") || IPC_SYSCTLS.contains(&key) {
+			(LinuxNamespaceType::Ipc, SysctlError::RequiresIpcNs)
+		} else if UTS_SYSCTLS.contains(&key) {
+			(LinuxNamespaceType::Uts, SysctlError::RequiresUtsNs)
+		} else {
+			return Err(SysctlError::NotNamespaced(key.to_string()));
+		};
+
+	if namespaces.contains(&required) {
+		Ok(())
+	} else {
+		Err(error(key.to_string()))
+	}
+}
+
+/// Writes the sysctls of the spec to /proc/sys after checking all of them
+pub fn apply_sysctls(sysctl: &HashMap<String, String>, namespaces: &[LinuxNamespaceType]) {
+	for key in sysctl.keys() {
+		if let Err(err) = check_sysctl(key, namespaces) {
+			panic!("Refusing to apply linux.sysctl: {}", err);
+		}
+	}
+
+	for (key, value) in sysctl {
+		let key_path = key.replace('.', "/");
+		let full_path = PathBuf::from("/proc/sys").join(key_path);
+		debug!("Setting sysctl {}={}", key, value);
+		let mut sysctl_file = OpenOptions::new()
+			.write(true)
+			.open(&full_path)
+			.unwrap_or_else(|_| panic!("Could not open sysctl entry at {:?}", full_path));
+		sysctl_file.write_all(value.as_bytes()).unwrap_or_else(|_| {
+			panic!(
+				"Could not write value {} to sysctl entry at {:?}",
+				value, full_path
+			)
+		});
+	}
+}
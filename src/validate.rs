@@ -0,0 +1,341 @@
+use crate::capabilities;
+use crate::cgroups;
+use crate::devices;
+use crate::error::{self, RunhError};
+use crate::kill;
+use crate::namespaces;
+use crate::personality::{self, Personality};
+use crate::rootfs;
+use crate::spec;
+use crate::sysctl;
+use oci_spec::runtime::{LinuxIdMapping, LinuxNamespace, LinuxNamespaceType, Spec};
+use serde_json::Value;
+use std::os::unix::fs::FileTypeExt;
+use std::path::Path;
+
+const PROPAGATION_VALUES: [&str; 8] = [
+	"private",
+	"rprivate",
+	"shared",
+	"rshared",
+	"slave",
+	"rslave",
+	"unbindable",
+	"runbindable",
+];
+
+const RLIMIT_NAMES: [&str; 16] = [
+	"RLIMIT_AS",
+	"RLIMIT_CORE",
+	"RLIMIT_CPU",
+	"RLIMIT_DATA",
+	"RLIMIT_FSIZE",
+	"RLIMIT_LOCKS",
+	"RLIMIT_MEMLOCK",
+	"RLIMIT_MSGQUEUE",
+	"RLIMIT_NICE",
+	"RLIMIT_NOFILE",
+	"RLIMIT_NPROC",
+	"RLIMIT_RSS",
+	"RLIMIT_RTPRIO",
+	"RLIMIT_RTTIME",
+	"RLIMIT_SIGPENDING",
+	"RLIMIT_STACK",
+];
+
+const STOP_SIGNAL_ANNOTATION: &str = "org.opencontainers.image.stopSignal";
+
+/// Fields of other platforms that would silently change what runs on Linux if they were ignored
+const UNSUPPORTED_FIELDS: [&str; 1] = ["process.commandLine"];
+
+fn has_field(config: &Value, field: &str) -> bool {
+	config
+		.pointer(&format!("/{}", field.replace('.', "/")))
+		.is_some()
+}
+
+/// Rejects container ids that runc would reject as well. Ids are used as directory names below
+/// the state root, so anything that could escape it has to be refused.
+pub fn validate_container_id(id: &str) {
+	let reason = if id.is_empty() {
+		Some(String::from("the id must not be empty"))
+	} else if id == "." || id == ".." {
+		Some(String::from("the id must not be a relative path component"))
+	} else {
+		id.chars()
+			.find(|c| !(c.is_ascii_alphanumeric() || ['_', '+', '-', '.'].contains(c)))
+			.map(|c| format!("character {:?} is not allowed, only [a-zA-Z0-9_+.-] are", c))
+	};
+	if let Some(reason) = reason {
+		error::raise(RunhError::InvalidId {
+			id: id.to_string(),
+			reason,
+		});
+	}
+}
+
+/// Returns the first field of the config that runh does not support on Linux
+pub fn find_unsupported_field(config: &Value) -> Option<&'static str> {
+	UNSUPPORTED_FIELDS
+		.iter()
+		.copied()
+		.find(|field| has_field(config, field))
+}
+
+fn check_version(config: &Value, problems: &mut Vec<String>) {
+	match config
+		.get("ociVersion")
+		.and_then(|version| version.as_str())
+	{
+		Some(version) if version.split('.').next() == Some("1") => {}
+		Some(version) => problems.push(format!("ociVersion {} is not supported", version)),
+		None => problems.push(String::from("ociVersion is missing")),
+	}
+}
+
+/// A root is either a directory or, mounted with `--rootfs-type`, a block device
+fn check_root(config: &Value, bundle: &Path, problems: &mut Vec<String>) {
+	let path = match config.pointer("/root/path").and_then(|path| path.as_str()) {
+		Some(path) => path,
+		None => {
+			problems.push(String::from("root.path is missing"));
+			return;
+		}
+	};
+	let resolved = match rootfs::resolve_root_path(Path::new(path), bundle) {
+		Ok(resolved) => resolved,
+		Err(err) => {
+			problems.push(format!("root.path {} cannot be resolved: {}", path, err));
+			return;
+		}
+	};
+	match std::fs::metadata(&resolved) {
+		Ok(metadata) if metadata.is_dir() || metadata.file_type().is_block_device() => {}
+		Ok(_) => problems.push(format!(
+			"root.path {:?} is neither a directory nor a block device",
+			resolved
+		)),
+		Err(err) => problems.push(format!("root.path {:?} cannot be read: {}", resolved, err)),
+	}
+}
+
+fn check_process(config: &Value, problems: &mut Vec<String>) {
+	let process = match config.get("process") {
+		Some(process) => process,
+		None => {
+			problems.push(String::from("process is missing"));
+			return;
+		}
+	};
+
+	match process.get("args").and_then(|args| args.as_array()) {
+		Some(args) if !args.is_empty() => {}
+		_ => problems.push(String::from("process.args must not be empty")),
+	}
+
+	for set in capabilities::CAPABILITY_SETS {
+		let caps = process
+			.pointer(&format!("/capabilities/{}", set))
+			.and_then(|caps| caps.as_array());
+		// Every entry is checked on its own, so that all typos are reported at once
+		for cap in caps.into_iter().flatten() {
+			if let Err(err) = capabilities::parse_capabilities(std::slice::from_ref(cap)) {
+				problems.push(format!("process.capabilities.{}: {}", set, err));
+			}
+		}
+	}
+
+	let rlimits = process
+		.get("rlimits")
+		.and_then(|rlimits| rlimits.as_array());
+	for rlimit in rlimits.into_iter().flatten() {
+		match rlimit.get("type").and_then(|typ| typ.as_str()) {
+			Some(typ) if RLIMIT_NAMES.contains(&typ) => {}
+			Some(typ) => problems.push(format!("process.rlimits contains unknown rlimit {}", typ)),
+			None => problems.push(String::from(
+				"process.rlimits contains an entry without type",
+			)),
+		}
+	}
+}
+
+fn check_mounts(config: &Value, problems: &mut Vec<String>) {
+	let mounts = config.get("mounts").and_then(|mounts| mounts.as_array());
+	for mount in mounts.into_iter().flatten() {
+		match mount
+			.get("destination")
+			.and_then(|destination| destination.as_str())
+		{
+			Some(destination) if Path::new(destination).is_absolute() => {}
+			Some(destination) => problems.push(format!(
+				"mount destination {} is not an absolute path",
+				destination
+			)),
+			None => problems.push(String::from("mount without destination")),
+		}
+	}
+}
+
+fn check_linux(config: &Value, problems: &mut Vec<String>) {
+	if let Some(propagation) = config
+		.pointer("/linux/rootfsPropagation")
+		.and_then(|propagation| propagation.as_str())
+	{
+		if !PROPAGATION_VALUES.contains(&propagation) {
+			problems.push(format!(
+				"linux.rootfsPropagation {} is unknown",
+				propagation
+			));
+		}
+	}
+
+	for kind in ["uidMappings", "gidMappings"] {
+		if let Some(mappings) = config.pointer(&format!("/linux/{}", kind)) {
+			match serde_json::from_value::<Vec<LinuxIdMapping>>(mappings.clone()) {
+				Ok(mappings) => {
+					if let Err(err) = namespaces::check_id_mappings(kind, &mappings) {
+						problems.push(format!("linux.{}", err));
+					}
+				}
+				Err(err) => problems.push(format!("linux.{} is invalid: {}", kind, err)),
+			}
+		}
+	}
+
+	if let Some(personality) = config.pointer("/linux/personality") {
+		match serde_json::from_value::<Personality>(personality.clone()) {
+			Ok(personality) => {
+				if let Err(err) = personality::validate_personality(&personality) {
+					problems.push(format!("linux.personality is invalid: {}", err));
+				}
+			}
+			Err(err) => problems.push(format!("linux.personality is invalid: {}", err)),
+		}
+	}
+
+	if let Some(sysctl) = config
+		.pointer("/linux/sysctl")
+		.and_then(|sysctl| sysctl.as_object())
+	{
+		let namespaces: Vec<LinuxNamespaceType> = config
+			.pointer("/linux/namespaces")
+			.and_then(|namespaces| {
+				serde_json::from_value::<Vec<LinuxNamespace>>(namespaces.clone()).ok()
+			})
+			.into_iter()
+			.flatten()
+			.map(|ns| ns.typ())
+			.collect();
+		for key in sysctl.keys() {
+			if let Err(err) = sysctl::check_sysctl(key, &namespaces) {
+				problems.push(format!("linux.sysctl: {}", err));
+			}
+		}
+	}
+
+	if let Some(swap) = config
+		.pointer("/linux/resources/memory/swap")
+		.and_then(|swap| swap.as_i64())
+	{
+		let limit = config
+			.pointer("/linux/resources/memory/limit")
+			.and_then(|limit| limit.as_i64());
+		if let Err(err) = cgroups::swap_to_v2(swap, limit) {
+			problems.push(format!("linux.resources.memory.swap is invalid: {}", err));
+		}
+	}
+}
+
+fn check_annotations(config: &Value, problems: &mut Vec<String>) {
+	if let Some(stop_signal) = config
+		.pointer(&format!(
+			"/annotations/{}",
+			STOP_SIGNAL_ANNOTATION.replace('/', "~1")
+		))
+		.and_then(|signal| signal.as_str())
+	{
+		if kill::try_parse_signal(stop_signal).is_none() {
+			problems.push(format!(
+				"annotation {} contains unknown signal {}",
+				STOP_SIGNAL_ANNOTATION, stop_signal
+			));
+		}
+	}
+	for annotation in [cgroups::OOM_GROUP_ANNOTATION, devices::HOST_DEV_ANNOTATION] {
+		if let Some(value) = config
+			.pointer(&format!("/annotations/{}", annotation.replace('/', "~1")))
+			.and_then(|value| value.as_str())
+		{
+			if value != "true" && value != "false" {
+				problems.push(format!(
+					"annotation {} has to be true or false, not {}",
+					annotation, value
+				));
+			}
+		}
+	}
+	if let Some(margin) = config
+		.pointer(&format!(
+			"/annotations/{}",
+			cgroups::MEMORY_HIGH_MARGIN_ANNOTATION
+		))
+		.and_then(|margin| margin.as_str())
+	{
+		let limit = config
+			.pointer("/linux/resources/memory/limit")
+			.and_then(|limit| limit.as_i64())
+			.unwrap_or(-1);
+		if let Err(err) = cgroups::memory_high_from_margin(margin, limit) {
+			problems.push(format!(
+				"annotation {} is invalid: {}",
+				cgroups::MEMORY_HIGH_MARGIN_ANNOTATION,
+				err
+			));
+		}
+	}
+}
+
+/// Runs all static checks on the `config.json` of a bundle and returns every problem found.
+/// The configuration is checked as raw JSON, so that a single problem does not hide the others.
+pub fn validate_bundle(bundle: &Path) -> Vec<String> {
+	let config_path = bundle.join("config.json");
+	let config: Value = match spec::load_config(&config_path) {
+		Ok(config) => config,
+		Err(err) => return vec![format!("Could not read {:?}: {}", config_path, err)],
+	};
+
+	let mut problems = Vec::new();
+	check_version(&config, &mut problems);
+	check_root(&config, bundle, &mut problems);
+	check_process(&config, &mut problems);
+	check_mounts(&config, &mut problems);
+	check_linux(&config, &mut problems);
+	check_annotations(&config, &mut problems);
+	for field in UNSUPPORTED_FIELDS {
+		if has_field(&config, field) {
+			problems.push(format!("{} is not supported on Linux", field));
+		}
+	}
+
+	// Any remaining problems would only show up when runh loads the spec
+	if problems.is_empty() {
+		if let Err(err) = serde_json::from_value::<Spec>(config) {
+			problems.push(format!("Could not load spec: {}", err));
+		}
+	}
+	problems
+}
+
+pub fn print_bundle_problems(bundle: &str) {
+	let problems = validate_bundle(Path::new(bundle));
+	if problems.is_empty() {
+		println!("Bundle {} is valid", bundle);
+		return;
+	}
+
+	for problem in &problems {
+		println!("{}", problem);
+	}
+	println!("Found {} problems in bundle {}", problems.len(), bundle);
+	std::process::exit(1);
+}
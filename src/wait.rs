@@ -0,0 +1,94 @@
+use crate::error::{self, RunhError};
+use crate::state::{self, ContainerStatus};
+use nix::poll::{poll, PollFd, PollFlags};
+use std::convert::TryFrom;
+use std::os::unix::io::RawFd;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+const PROC_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+fn pidfd_open(pid: i32) -> nix::Result<RawFd> {
+	let ret = unsafe { libc::syscall(libc::SYS_pidfd_open, pid, 0) };
+	nix::errno::Errno::result(ret).map(|fd| fd as RawFd)
+}
+
+/// Milliseconds until the deadline for poll, which waits forever for -1
+fn remaining_millis(deadline: Option<Instant>) -> i32 {
+	match deadline {
+		Some(deadline) => {
+			let remaining = deadline.saturating_duration_since(Instant::now());
+			i32::try_from(remaining.as_millis()).unwrap_or(i32::MAX)
+		}
+		None => -1,
+	}
+}
+
+/// Blocks until the process exits or the timeout elapses and returns whether it exited.
+/// A pidfd becomes readable once the process has exited, older kernels without pidfd_open
+/// fall back to polling /proc.
+pub fn wait_for_exit(pid: i32, timeout: Option<Duration>) -> bool {
+	let deadline = timeout.map(|timeout| Instant::now() + timeout);
+	match pidfd_open(pid) {
+		Ok(pidfd) => {
+			let mut poll_fds = [PollFd::new(pidfd, PollFlags::POLLIN)];
+			let exited = loop {
+				match poll(&mut poll_fds, remaining_millis(deadline)) {
+					Ok(0) => break false,
+					Ok(_) => break true,
+					Err(nix::errno::Errno::EINTR) => continue,
+					Err(err) => panic!("Could not poll pidfd of process {}: {}", pid, err),
+				}
+			};
+			let _ = nix::unistd::close(pidfd);
+			exited
+		}
+		// The process is already gone
+		Err(nix::errno::Errno::ESRCH) => true,
+		Err(err) => {
+			debug!(
+				"Could not open pidfd for process {}: {}. Polling /proc instead...",
+				pid, err
+			);
+			loop {
+				match procfs::process::Process::new(pid).and_then(|process| process.stat()) {
+					Ok(stat) => match stat.state() {
+						Ok(procfs::process::ProcState::Zombie)
+						| Ok(procfs::process::ProcState::Dead) => return true,
+						_ if remaining_millis(deadline) == 0 => return false,
+						_ => std::thread::sleep(PROC_POLL_INTERVAL),
+					},
+					Err(_) => return true,
+				}
+			}
+		}
+	}
+}
+
+/// Blocks until the init of a container exits, then prints its exit status and exits with
+/// its exit code (128 + signal number if it was killed by a signal). The status of containers
+/// that already stopped is taken from the container directory.
+pub fn wait_container(project_dir: PathBuf, id: &str) {
+	let container_state = state::get_container_state(project_dir.clone(), id)
+		.unwrap_or_else(|| error::raise(RunhError::ContainerNotFound(id.to_string())));
+
+	let exit_status = if container_state.status == ContainerStatus::Stopped {
+		container_state.exit_status
+	} else {
+		let pid = container_state
+			.pid
+			.unwrap_or_else(|| panic!("Container {} has no init process yet!", id));
+		debug!("Waiting for init {} of container {} to exit", pid, id);
+		wait_for_exit(pid, None);
+		state::get_container_state(project_dir, id)
+			.map(|state| state.exit_status)
+			.unwrap_or_default()
+	};
+
+	println!(
+		"{}",
+		serde_json::to_string(&exit_status).expect("Could not serialize exit status!")
+	);
+	log::logger().flush();
+	std::process::exit(exit_status.code());
+}